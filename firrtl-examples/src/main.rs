@@ -1,20 +1,40 @@
 //! Print the parsed contents of a FIRRTL file
+//!
+//! Usage: `firrtl-examples [dump|json] <.fir file>`
+//!
+//! - `dump` (the default) writes the circuit back out as FIRRTL
+//! - `json` writes the circuit as JSON (see `firrtl::schema::JSON_SCHEMA`)
 
 use std::env;
-use firrtl::{ FirrtlParseError, FirrtlFile };
+use firrtl::{ FirrtlParseError, FirrtlFile, Diagnostic };
 
 fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
-    println!("{:?}", args);
-    if args.len() < 2 {
-        return Err(format!("usage: {} <.fir file>", args[0]));
-    }
+    let (cmd, filename) = match args.len() {
+        2 => ("dump", &args[1]),
+        3 => (args[1].as_str(), &args[2]),
+        _ => return Err(format!("usage: {} [dump|json] <.fir file>", args[0])),
+    };
 
-    let ff = FirrtlFile::from_file(&args[1]);
-    let circuit = ff.parse().map_err(|e: FirrtlParseError| {
-        e.kind.message()
+    let ff = FirrtlFile::from_file(filename).map_err(|e: FirrtlParseError| {
+        Diagnostic::from_parse_error(filename, &e).to_string()
     })?;
-                                            
-    circuit.dump();
+    let circuit = match ff.parse() {
+        Ok(circuit) => circuit,
+        Err(e) => {
+            eprint!("{}", ff.render_error(&e));
+            return Err(format!("failed to parse {}", filename));
+        },
+    };
+
+    match cmd {
+        "dump" => circuit.dump(),
+        "json" => {
+            let json = serde_json::to_string_pretty(&circuit)
+                .map_err(|e| e.to_string())?;
+            println!("{}", json);
+        },
+        _ => return Err(format!("unknown command '{}'", cmd)),
+    }
     Ok(())
 }
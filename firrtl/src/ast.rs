@@ -1,22 +1,44 @@
 //! Elements in the FIRRTL abstract syntax tree. 
 
 use std::fmt;
+use num_bigint::Sign;
+
+pub use num_bigint::{ BigInt, BigUint };
 
 /// FIRRTL circuit (`circuit`)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Circuit { 
     pub id: String,
+    /// Version from the `FIRRTL version` header (if there was one)
+    pub version: Option<Version>,
+    /// Layers declared in this circuit (`layer <id>, <convention> :`)
+    pub layers: Vec<Layer>,
+    /// Option groups declared in this circuit (`option <id> :`)
+    pub options: Vec<OptionGroup>,
+    /// Type aliases declared in this circuit (`type <id> = <type>`)
+    pub type_aliases: Vec<TypeAlias>,
     pub modules: Vec<Module>,
     pub intmodules: Vec<IntModule>,
     pub extmodules: Vec<ExtModule>,
+    pub classes: Vec<Class>,
+    pub extclasses: Vec<ExtClass>,
+    pub info: Option<SourceInfo>,
 }
 impl Circuit {
     pub fn new(id: impl ToString) -> Self { 
         Self {
             id: id.to_string(),
+            version: None,
+            layers: Vec::new(),
+            options: Vec::new(),
+            type_aliases: Vec::new(),
             modules: Vec::new(),
             intmodules: Vec::new(),
             extmodules: Vec::new(),
+            classes: Vec::new(),
+            extclasses: Vec::new(),
+            info: None,
         }
     }
 
@@ -35,197 +57,441 @@ impl Circuit {
         self.extmodules.push(m);
     }
 
+    /// Add a class to this circuit.
+    pub fn add_class(&mut self, c: Class) {
+        self.classes.push(c);
+    }
+
+    /// Add an external class to this circuit.
+    pub fn add_extclass(&mut self, c: ExtClass) {
+        self.extclasses.push(c);
+    }
+
+    /// Add a layer to this circuit.
+    pub fn add_layer(&mut self, l: Layer) {
+        self.layers.push(l);
+    }
+
+    /// Find a layer by its path (ie. `A.B` for the layer `B` nested in `A`).
+    pub fn layer(&self, path: &str) -> Option<&Layer> {
+        let mut parts = path.split('.');
+        let mut layer = self.layers.iter().find(|l| Some(l.id.as_str()) == 
+            parts.next())?;
+        for part in parts {
+            layer = layer.layers.iter().find(|l| l.id == part)?;
+        }
+        Some(layer)
+    }
+
+    /// Add an option group to this circuit.
+    pub fn add_option(&mut self, o: OptionGroup) {
+        self.options.push(o);
+    }
+
+    /// Find an option group by name.
+    pub fn option(&self, id: &str) -> Option<&OptionGroup> {
+        self.options.iter().find(|o| o.id == id)
+    }
+
+    /// Add a type alias to this circuit.
+    pub fn add_type_alias(&mut self, t: TypeAlias) {
+        self.type_aliases.push(t);
+    }
+
+    /// Get the type that an alias refers to.
+    pub fn type_alias(&self, id: &str) -> Option<&FirrtlType> {
+        self.type_aliases.iter().find(|t| t.id == id).map(|t| &t.ty)
+    }
+
+    /// Expand all type aliases in `ty` into their structural types.
+    ///
+    /// Returns `None` if `ty` refers to an alias that doesn't exist.
+    pub fn resolve_type(&self, ty: &FirrtlType) -> Option<FirrtlType> {
+        ty.resolve(&|id| self.type_alias(id))
+    }
+
     /// Get a reference to the top module in this circuit.
     pub fn top_module(&self) -> Option<&Module> {
         /// NOTE: This should always correspond to the name of the circuit. 
         self.modules.iter().find(|m| m.id == self.id)
     }
 
-    /// Print a FIRRTL statement with some indentation level
-    fn dump_indent_stmt(indent: usize, statement: &Statement) {
-        match statement {
-            Statement::Reg(id, ty, clkexpr, rvexpr) => {
-                if let Some((reset_expr, val_expr)) = rvexpr { 
-                    println!("{:idt$}reg {}: {}, {} with:", "", 
-                             id, ty, clkexpr, idt=indent);
-                    println!("{:idt$}(reset => ({}, {}))", "", 
-                             reset_expr, val_expr, idt=indent+2);
-                } else { 
-                    println!("{:idt$}reg {}: {}, {}", "", 
-                             id, ty, clkexpr, idt=indent);
-                }
-            },
-            Statement::Wire(id, ty) => {
-                println!("{:idt$}wire {}: {}", "", id, ty, idt=indent);
-            },
-            Statement::Inst(id, mid) => {
-                println!("{:idt$}inst {} of {}", "", id, mid, idt=indent);
-            },
-            Statement::Node(id, expr) => {
-                println!("{:idt$}node {} = {}", "", id, expr, idt=indent);
-            },
-
-            // FIXME: We're *always* expanding single-line 'when' and 'else'
-            Statement::When(condexpr, wblk, eblk) => {
-                println!("{:idt$}when {} :", "", condexpr, idt=indent);
-                for s in wblk {
-                    Self::dump_indent_stmt(indent+2, s);
-                }
-                if !eblk.is_empty() {
-                    println!("{:idt$}else :", "", idt=indent);
-                    for s in eblk {
-                        Self::dump_indent_stmt(indent+2, s);
-                    }
-                }
-            },
+    /// List the named simulation/verification events in the hierarchy 
+    /// below the top module.
+    pub fn named_events(&self) -> Vec<crate::event::NamedEvent> {
+        crate::event::named_events(self)
+    }
 
-            Statement::Connect(r, e) => {
-                println!("{:idt$}connect {}, {}", "", r, e, idt=indent);
-            },
-            Statement::PartialConnect(r, e) => {
-                println!("{:idt$}{} <- {}", "", r, e, idt=indent);
-            },
-            Statement::Invalidate(r) => {
-                println!("{:idt$}invalidate {}", "", r, idt=indent);
-            },
-            Statement::Skip => {
-                println!("{:idt$}skip", "", idt=indent);
-            },
-            Statement::Printf(e1, e2, s, args) => {
-                if args.is_empty() {
-                    println!("{:idt$}printf({}, {}, {})", "", 
-                            e1, e2, s, idt=indent);
-                } else {
-                    let arglist: String = args.iter()
-                        .map(|x| x.to_string() + ", ").collect::<String>();
-                    let argstr = arglist.trim_end_matches(", ");
-                    println!("{:idt$}printf({}, {}, {}, {})", "", 
-                            e1, e2, s, argstr, idt=indent);
-                }
-            },
-            Statement::Stop(e1, e2, val) => {
-                println!("{:idt$}stop({}, {}, {})", "", e1, e2, val, idt=indent);
-            },
-            Statement::Unimplemented(s) => {
-                println!("{:idt$}unimpl_{}()", "", s, idt=indent);
-            }
-            Statement::Mem(decl) => {
-                println!("{:idt$}mem {} :", "", decl.id, idt=indent);
-                println!("{:idt$}data-type => {}", "", decl.ty, idt=indent+2);
-                println!("{:idt$}depth => {}", "", decl.depth, idt=indent+2);
-                println!("{:idt$}read-latency => {}", "", 
-                    decl.read_latency, idt=indent+2
-                );
-                println!("{:idt$}write-latency => {}", "", 
-                    decl.write_latency, idt=indent+2
-                );
-                println!("{:idt$}read-under-write => {}", "", 
-                    decl.read_under_write, idt=indent+2
-                );
-                for rp in &decl.rp_list {
-                    println!("{:idt$}reader => {}", "", rp, idt=indent+2);
-                }
-                for wp in &decl.wp_list {
-                    println!("{:idt$}writer => {}", "", wp, idt=indent+2);
-                }
-                for rwp in &decl.rwp_list {
-                    println!("{:idt$}readwriter => {}", "", rwp, idt=indent+2);
-                }
-            }
-            Statement::Attach(refs) => {
-                let reflist: String = refs.iter().map(|x| x.to_string() + ", ")
-                    .collect::<String>();
-                let s = reflist.trim_end_matches(", ");
-                println!("{:idt$}attach({})", "", s, idt=indent);
-            },
-            Statement::Define(sr, re) => {
-                println!("{:idt$}define {} = {}", "", sr, re, idt=indent);
-            },
-            Statement::ForceInitial(re, e) => {
-                println!("{:idt$}force_initial({}, {})", "", re, e, idt=indent);
-            },
-            Statement::Force(e1, e2, re, e3) => {
-                println!("{:idt$}force({}, {}, {}, {})", "", 
-                    e1, e2, re, e3, idt=indent);
-            },
-            Statement::Release(e1, e2, re) => {
-                println!("{:idt$}release({}, {}, {})", "", 
-                    e1, e2, re, idt=indent);
-            },
-            Statement::ReleaseInitial(re) => {
-                println!("{:idt$}release_initial({})", "", re, idt=indent);
-            },
-            _ => panic!("{:?}", statement),
-        }
+    /// Find the first structural difference between this circuit and 
+    /// another (if any).
+    pub fn diff(&self, other: &Circuit) -> Option<crate::diff::Difference> {
+        crate::diff::diff_circuits(self, other)
     }
 
     /// Write the FIRRTL for this [Circuit] to `stdout`.
     pub fn dump(&self) {
-        println!("circuit {}:", self.id);
-        for m in &self.modules {
-            println!("{:idt$}module {}:", "", m.id, idt=2);
-            for port in &m.ports {
-                println!("{:idt$}{}", "", port, idt=4);
-            }
-            for s in &m.statements {
-                Self::dump_indent_stmt(4, s);
+        print!("{}", self);
+    }
+}
+impl fmt::Display for Circuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        crate::emit::Emitter::new(f).emit_circuit(self)
+    }
+}
+
+
+/// Version of the FIRRTL specification (`FIRRTL version <x>.<y>.<z>`)
+///
+/// See [crate::version] for the syntax allowed by each version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+}
+impl std::str::FromStr for Version {
+    type Err = crate::lex::ParseErrorKind;
+
+    /// Parse a version number (ie. `3.3.0`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::lex::ParseErrorKind::InvalidLiteral(
+            s.to_string());
+        let parts = s.split('.').map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
             }
+            part.parse().ok()
+        }).collect::<Option<Vec<u32>>>().ok_or_else(invalid)?;
+        let [major, minor, patch] = parts[..] else {
+            return Err(invalid());
+        };
+        Ok(Self::new(major, minor, patch))
+    }
+}
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+
+/// FIRRTL layer declaration (`layer <id>, <convention> :`), along with 
+/// any layers nested inside it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layer {
+    pub id: String,
+    pub convention: LayerConvention,
+    /// Optional output directory for the layer's collateral 
+    ///
+    /// NOTE: This is kept *without* its quotes.
+    pub output_dir: Option<String>,
+    pub layers: Vec<Layer>,
+    pub info: Option<SourceInfo>,
+}
+impl Layer {
+    pub fn new(id: impl ToString, convention: LayerConvention) -> Self {
+        Self { 
+            id: id.to_string(), 
+            convention, 
+            output_dir: None, 
+            layers: Vec::new(), 
+            info: None 
+        }
+    }
+}
+
+/// How a layer is lowered into the output of a compiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LayerConvention {
+    /// In a separate module, bound into the design
+    Bind,
+    /// Inline in the design (guarded by a preprocessor macro)
+    Inline,
+}
+impl LayerConvention {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "bind" => Some(Self::Bind),
+            "inline" => Some(Self::Inline),
+            _ => None,
         }
     }
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Bind => "bind",
+            Self::Inline => "inline",
+        }
+    }
+}
+impl fmt::Display for LayerConvention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", Self::to_str(self))
+    }
+}
+
+/// FIRRTL option group declaration (`option <id> :`), along with the 
+/// cases that can be selected for it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionGroup {
+    pub id: String,
+    pub cases: Vec<String>,
+    pub info: Option<SourceInfo>,
+}
+impl OptionGroup {
+    pub fn new(id: impl ToString, cases: Vec<String>) -> Self {
+        Self { id: id.to_string(), cases, info: None }
+    }
+
+    /// Returns 'true' if `case` is one of the cases of this option.
+    pub fn has_case(&self, case: &str) -> bool {
+        self.cases.iter().any(|c| c == case)
+    }
 }
 
+/// FIRRTL type alias declaration (`type <id> = <type>`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypeAlias {
+    pub id: String,
+    pub ty: FirrtlType,
+    pub info: Option<SourceInfo>,
+}
+impl TypeAlias {
+    pub fn new(id: impl ToString, ty: FirrtlType) -> Self {
+        Self { id: id.to_string(), ty, info: None }
+    }
+}
+impl fmt::Display for TypeAlias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "type {} = {}", self.id, self.ty)
+    }
+}
 
 /// FIRRTL module (`module`)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Module {
     pub id: String,
+    /// Whether this module is part of the public interface of the circuit
+    /// (`public module`)
+    pub public: bool,
+    /// Paths of the layers enabled in this module (`enablelayer A.B`)
+    pub enabled_layers: Vec<String>,
     pub ports: Vec<PortDecl>,
     pub statements: Vec<Statement>,
+    pub info: Option<SourceInfo>,
 }
 impl Module {
     pub fn new(id: impl ToString, ports: Vec<PortDecl>, 
                statements: Vec<Statement>) -> Self 
     {
-        Self { id: id.to_string(), ports, statements }
+        Self { 
+            id: id.to_string(), 
+            public: false,
+            enabled_layers: Vec::new(), 
+            ports, 
+            statements, 
+            info: None 
+        }
     }
 }
 
 /// FIRRTL intrinsic module (`intmodule`)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IntModule {
     pub id: String,
     pub ports: Vec<PortDecl>,
+    /// Name of the intrinsic implemented by this module
+    pub intrinsic: String,
+    pub params: Vec<Parameter>,
+    pub info: Option<SourceInfo>,
 }
 impl IntModule {
-    pub fn new(id: impl ToString, ports: Vec<PortDecl>) -> Self {
-        Self { id: id.to_string(), ports }
+    pub fn new(id: impl ToString, ports: Vec<PortDecl>, 
+               intrinsic: impl ToString) -> Self 
+    {
+        Self { 
+            id: id.to_string(), 
+            ports, 
+            intrinsic: intrinsic.to_string(), 
+            params: Vec::new(),
+            info: None 
+        }
+    }
+
+    /// Get the value of a parameter by name.
+    pub fn param(&self, id: &str) -> Option<&ParamValue> {
+        self.params.iter().find(|p| p.id == id).map(|p| &p.value)
     }
 }
 
 
 /// FIRRTL external module (`extmodule`)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExtModule {
     pub id: String,
     pub ports: Vec<PortDecl>,
+    /// Name of the module that this refers to (if different from `id`)
+    pub defname: Option<String>,
+    pub params: Vec<Parameter>,
+    /// Paths for probe ports into the module's implementation
+    pub refs: Vec<RefDecl>,
+    pub info: Option<SourceInfo>,
 }
 impl ExtModule {
     pub fn new(id: impl ToString, ports: Vec<PortDecl>) -> Self {
-        Self { id: id.to_string(), ports, }
+        Self { 
+            id: id.to_string(), 
+            ports, 
+            defname: None, 
+            params: Vec::new(), 
+            refs: Vec::new(),
+            info: None 
+        }
+    }
+
+    /// Resolve a probe port (ie. `mem_probe` or `io.probe`) to the path 
+    /// it refers to inside the external module.
+    pub fn probe_path(&self, port: &str) -> Option<&str> {
+        self.refs.iter().find(|r| r.port.to_string() == port)
+            .map(|r| r.path.as_str())
+    }
+
+    /// Get the value of a parameter by name.
+    pub fn param(&self, id: &str) -> Option<&ParamValue> {
+        self.params.iter().find(|p| p.id == id).map(|p| &p.value)
+    }
+}
+
+
+/// FIRRTL class (`class`), describing objects whose ports and statements 
+/// only carry properties
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Class {
+    pub id: String,
+    pub ports: Vec<PortDecl>,
+    pub statements: Vec<Statement>,
+    pub info: Option<SourceInfo>,
+}
+impl Class {
+    pub fn new(id: impl ToString, ports: Vec<PortDecl>, 
+               statements: Vec<Statement>) -> Self 
+    {
+        Self { id: id.to_string(), ports, statements, info: None }
+    }
+}
+
+/// FIRRTL external class (`extclass`), which only declares ports
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtClass {
+    pub id: String,
+    pub ports: Vec<PortDecl>,
+    pub info: Option<SourceInfo>,
+}
+impl ExtClass {
+    pub fn new(id: impl ToString, ports: Vec<PortDecl>) -> Self {
+        Self { id: id.to_string(), ports, info: None }
+    }
+}
+
+
+/// Declares the path that a probe port on an external module refers to
+/// (`ref <port> is "<path>"`).
+///
+/// NOTE: The path is kept *without* its quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RefDecl {
+    pub port: StaticReference,
+    pub path: String,
+}
+impl RefDecl {
+    pub fn new(port: StaticReference, path: impl ToString) -> Self {
+        Self { port, path: path.to_string() }
+    }
+}
+impl fmt::Display for RefDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "ref {} is \"{}\"", self.port, self.path)
+    }
+}
+
+/// A parameter on an external/intrinsic module (`parameter <id> = <value>`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Parameter {
+    pub id: String,
+    pub value: ParamValue,
+}
+impl Parameter {
+    pub fn new(id: impl ToString, value: ParamValue) -> Self {
+        Self { id: id.to_string(), value }
+    }
+}
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "parameter {} = {}", self.id, self.value)
+    }
+}
+
+/// The value of a module parameter.
+///
+/// NOTE: Floating-point values are kept in their original textual form, and
+/// strings are kept *without* their quotes (escapes are left as-is).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParamValue {
+    Int(
+        #[cfg_attr(feature = "serde", serde(with = "serde_decimal"))]
+        BigUint
+    ),
+    SInt(
+        #[cfg_attr(feature = "serde", serde(with = "serde_decimal"))]
+        BigInt
+    ),
+    Float(String),
+    String(String),
+    RawString(String),
+}
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Int(v) => write!(f, "{}", v),
+            // Positive values need an explicit sign
+            Self::SInt(v) => write!(f, "{:+}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "\"{}\"", v),
+            Self::RawString(v) => write!(f, "'{}'", v),
+        }
     }
 }
 
 
 /// FIRRTL module port declaration
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PortDecl {
     pub id: String,
     pub dir: Direction,
     pub ty: FirrtlType,
+    pub info: Option<SourceInfo>,
 }
 impl PortDecl {
     pub fn new(id: impl ToString, dir: Direction, ty: FirrtlType) -> Self { 
-        Self { id: id.to_string(), dir, ty }
+        Self { id: id.to_string(), dir, ty, info: None }
     }
 }
 impl fmt::Display for PortDecl {
@@ -234,10 +500,123 @@ impl fmt::Display for PortDecl {
     }
 }
 
+/// A single FIRRTL source locator, ie. `Foo.scala 12:7`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceLocator {
+    pub file: String,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+impl SourceLocator {
+    pub fn new(file: impl ToString, line: Option<usize>, col: Option<usize>)
+        -> Self
+    {
+        Self { file: file.to_string(), line, col }
+    }
+}
+impl fmt::Display for SourceLocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match (self.line, self.col) {
+            (Some(l), Some(c)) => write!(f, "{} {}:{}", self.file, l, c),
+            (Some(l), None) => write!(f, "{} {}", self.file, l),
+            _ => write!(f, "{}", self.file),
+        }
+    }
+}
+
+/// FIRRTL source info (`@[...]`) pointing back to the code that generated 
+/// some part of a circuit. 
+///
+/// The following forms are recognized: 
+///
+/// - `@[Foo.scala 12:7]`
+/// - `@[Foo.scala:12:7]`
+/// - `@[Foo.scala 12:7 Bar.scala 3:4]` (multiple locators)
+/// - `@[Foo.scala 12:{7,9} 13:4]` (compressed locators from the SFC)
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceInfo {
+    pub locators: Vec<SourceLocator>,
+}
+impl SourceInfo {
+    pub fn new(locators: Vec<SourceLocator>) -> Self {
+        Self { locators }
+    }
+
+    /// Parse the contents of a FIRRTL `@[...]` source info string.
+    pub fn parse(s: &str) -> Option<Self> {
+        let inner = s.trim().strip_prefix("@[")?.strip_suffix(']')?;
+        let mut locators: Vec<SourceLocator> = Vec::new();
+
+        // A filename that hasn't been given a position yet
+        let mut file: Option<&str> = None;
+
+        let words = inner.split_whitespace()
+            .map(|w| w.trim_end_matches(','))
+            .filter(|w| !w.is_empty());
+        for word in words {
+            // A position for the most recent filename, ie. 'line:col', 
+            // 'line', or 'line:{col,col}'
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                let fname = match file.take() {
+                    Some(f) => f.to_string(),
+                    None => locators.last()?.file.clone(),
+                };
+                let (line, cols) = match word.split_once(':') {
+                    Some((l, c)) => (l, c),
+                    None => (word, ""),
+                };
+                let line = line.parse().ok()?;
+                let cols = cols.trim_start_matches('{').trim_end_matches('}');
+                if cols.is_empty() {
+                    locators.push(SourceLocator::new(&fname, Some(line), None));
+                }
+                for col in cols.split(',').filter(|c| !c.is_empty()) {
+                    let col = col.parse().ok()?;
+                    locators.push(
+                        SourceLocator::new(&fname, Some(line), Some(col))
+                    );
+                }
+                continue;
+            }
+
+            // Any pending filename without a position
+            if let Some(f) = file.take() {
+                locators.push(SourceLocator::new(f, None, None));
+            }
+
+            // A complete 'file:line:col' locator
+            let mut parts = word.rsplitn(3, ':');
+            let (c, l, f) = (parts.next(), parts.next(), parts.next());
+            if let (Some(c), Some(l), Some(f)) = (c, l, f) {
+                if let (Ok(l), Ok(c)) = (l.parse(), c.parse()) {
+                    locators.push(SourceLocator::new(f, Some(l), Some(c)));
+                    continue;
+                }
+            }
+            file = Some(word);
+        }
+        if let Some(f) = file.take() {
+            locators.push(SourceLocator::new(f, None, None));
+        }
+        Some(Self::new(locators))
+    }
+}
+impl fmt::Display for SourceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let llist: String = self.locators.iter()
+            .map(|x| x.to_string() + " ").collect::<String>();
+        write!(f, "@[{}]", llist.trim_end())
+    }
+}
+
 /// FIRRTL ground datatypes
 ///
 /// NOTE: Unspecified widths are *inferred* widths. 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FirrtlTypeGround {
     Clock, Reset, AsyncReset, 
     UInt(Option<usize>), 
@@ -262,34 +641,106 @@ impl fmt::Display for FirrtlTypeGround {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FirrtlTypeRef {
-    Probe(Box<FirrtlType>),
-    RWProbe(Box<FirrtlType>),
+    /// A probe, optionally colored with the layer (ie. `A.B`) that it 
+    /// may be used in
+    Probe(Box<FirrtlType>, Option<String>),
+    RWProbe(Box<FirrtlType>, Option<String>),
+}
+impl FirrtlTypeRef {
+    /// Get the layer that this probe is colored with (if any).
+    pub fn layer(&self) -> Option<&str> {
+        match self {
+            Self::Probe(_, layer) | Self::RWProbe(_, layer) => {
+                layer.as_deref()
+            },
+        }
+    }
 }
 impl fmt::Display for FirrtlTypeRef {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        match self { 
-            Self::Probe(ty) => write!(f, "Probe<{}>", ty),
-            Self::RWProbe(ty) => write!(f, "RWProbe<{}>", ty),
+        let (kw, ty, layer) = match self { 
+            Self::Probe(ty, layer) => ("Probe", ty, layer),
+            Self::RWProbe(ty, layer) => ("RWProbe", ty, layer),
+        };
+        match layer {
+            Some(layer) => write!(f, "{}<{}, {}>", kw, ty, layer),
+            None => write!(f, "{}<{}>", kw, ty),
+        }
+    }
+}
+
+/// FIRRTL property types, which describe values that only exist at 
+/// compile-time (ie. metadata) and never become hardware
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PropertyType {
+    Integer, 
+    String, 
+    Bool, 
+    Double, 
+    Path, 
+    /// A reference to an object of any class
+    AnyRef,
+    List(Box<Self>),
+    /// A reference to an object of a particular class (`Inst<Class>`)
+    Inst(String),
+}
+impl fmt::Display for PropertyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Integer => write!(f, "Integer"),
+            Self::String => write!(f, "String"),
+            Self::Bool => write!(f, "Bool"),
+            Self::Double => write!(f, "Double"),
+            Self::Path => write!(f, "Path"),
+            Self::AnyRef => write!(f, "AnyRef"),
+            Self::List(ty) => write!(f, "List<{}>", ty),
+            Self::Inst(class) => write!(f, "Inst<{}>", class),
         }
     }
 }
 
 /// FIRRTL datatypes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FirrtlType {
     Ground(FirrtlTypeGround),
     Vector(Box<Self>, usize),
     Bundle(Vec<BundleField>),
+    /// A type whose value can't change at runtime (`const <type>`)
+    ///
+    /// NOTE: This applies to the whole type, ie. `const UInt<1>[2]` is 
+    /// a const vector.
+    Const(Box<Self>),
+    Enum(Vec<EnumVariant>),
     Ref(FirrtlTypeRef),
+    Property(PropertyType),
+    /// A reference to a type alias declared in the circuit
+    /// (see [Circuit::resolve_type])
+    Alias(String),
     None,
 }
 impl FirrtlType {
+    /// Returns 'true' if this is a `const` type.
+    pub fn is_const(&self) -> bool {
+        matches!(self, Self::Const(_))
+    }
+
+    /// Get this type without any `const` qualifier.
+    pub fn without_const(&self) -> &FirrtlType {
+        match self {
+            Self::Const(ty) => ty.without_const(),
+            _ => self,
+        }
+    }
+
     pub fn bundle_field_type(&self, field_name: &str) 
         -> Option<&FirrtlType>
     {
-        if let Self::Bundle(fields) = self {
+        if let Self::Bundle(fields) = self.without_const() {
             if let Some(field) = fields.iter().find(|f| f.id == field_name) {
                 Some(&field.ty)
             } else {
@@ -300,7 +751,81 @@ impl FirrtlType {
         }
     }
 
-    /// Get the bitwidth of this type. `None` indicates an inferred width.
+    /// Get the type of some part of a value with this type, where `r` is a
+    /// reference to the whole value (ie. `io.a[1]` for `io`).
+    pub fn reference_type(&self, r: &StaticReference) -> Option<&FirrtlType> {
+        match r {
+            StaticReference::Static(_) => Some(self),
+            StaticReference::Subfield(inner, field) => {
+                self.reference_type(inner)?.bundle_field_type(field)
+            },
+            StaticReference::Subindex(inner, _) => {
+                match self.reference_type(inner)?.without_const() {
+                    Self::Vector(ty, _) => Some(ty),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// Expand all type aliases in this type, looking up the type for each 
+    /// alias with `lookup`.
+    ///
+    /// Returns `None` if an alias cannot be found.
+    ///
+    /// NOTE: Aliases are assumed not to be recursive (the parser only 
+    /// allows references to aliases that have already been declared).
+    pub fn resolve<'t>(&self, 
+        lookup: &impl Fn(&str) -> Option<&'t FirrtlType>) 
+        -> Option<FirrtlType>
+    {
+        let res = match self {
+            Self::Alias(id) => lookup(id)?.resolve(lookup)?,
+            Self::Vector(ty, sz) => {
+                Self::Vector(Box::new(ty.resolve(lookup)?), *sz)
+            },
+            Self::Const(ty) => Self::Const(Box::new(ty.resolve(lookup)?)),
+            Self::Bundle(fields) => Self::Bundle(fields.iter().map(|f| {
+                Some(BundleField::new(f.flip, &f.id, f.ty.resolve(lookup)?))
+            }).collect::<Option<_>>()?),
+            Self::Enum(variants) => Self::Enum(variants.iter().map(|v| {
+                let ty = match &v.ty { 
+                    Some(ty) => Some(ty.resolve(lookup)?),
+                    None => None,
+                };
+                Some(EnumVariant::new(&v.id, ty))
+            }).collect::<Option<_>>()?),
+            Self::Ref(r) => Self::Ref(match r {
+                FirrtlTypeRef::Probe(ty, layer) => FirrtlTypeRef::Probe(
+                    Box::new(ty.resolve(lookup)?), layer.clone()),
+                FirrtlTypeRef::RWProbe(ty, layer) => FirrtlTypeRef::RWProbe(
+                    Box::new(ty.resolve(lookup)?), layer.clone()),
+            }),
+            Self::Ground(_) | Self::Property(_) | Self::None => self.clone(),
+        };
+        Some(res)
+    }
+
+    /// Get the number of bits needed for the tag of an enumeration type 
+    /// with `num_variants` variants.
+    pub fn enum_tag_width(num_variants: usize) -> usize {
+        match num_variants {
+            0 | 1 => 0,
+            n => (usize::BITS - (n - 1).leading_zeros()) as usize,
+        }
+    }
+
+    /// Get a variant of this enumeration type by name.
+    pub fn enum_variant(&self, id: &str) -> Option<&EnumVariant> {
+        if let Self::Enum(variants) = self {
+            variants.iter().find(|v| v.id == id)
+        } else {
+            None
+        }
+    }
+
+    /// Get the bitwidth of this type. `None` indicates an inferred width
+    /// (or an unresolved type alias).
     pub fn width(&self) -> Option<usize> {
         match self { 
             Self::Ground(g) => match g {
@@ -314,18 +839,29 @@ impl FirrtlType {
             Self::Vector(ty, sz) => {
                 if let Some(w) = ty.width() { Some(w * sz) } else { None }
             },
+            Self::Const(ty) => ty.width(),
 
-            // FIXME: You're assuming that all fields have definite width
             Self::Bundle(fields) => {
-                let w = fields.iter().fold(0, |mut res, f| { 
-                    res += f.width(); res
-                });
-                Some(w)
+                fields.iter().try_fold(0, |res, f| Some(res + f.width()?))
+            },
+            // The tag, followed by enough bits for the widest variant
+            Self::Enum(variants) => {
+                let tag = Self::enum_tag_width(variants.len());
+                let mut data = 0;
+                for v in variants { 
+                    if let Some(ty) = &v.ty {
+                        data = data.max(ty.width()?);
+                    }
+                }
+                Some(tag + data)
             },
             Self::Ref(r) => match r {
-                FirrtlTypeRef::Probe(ty) |
-                FirrtlTypeRef::RWProbe(ty) => ty.width(),
+                FirrtlTypeRef::Probe(ty, _) |
+                FirrtlTypeRef::RWProbe(ty, _) => ty.width(),
             },
+            // Properties don't have a hardware representation
+            Self::Property(_) => None,
+            Self::Alias(_) => None,
             Self::None => None,
         }
     }
@@ -335,21 +871,29 @@ impl fmt::Display for FirrtlType {
         match self { 
             Self::Ground(g) => write!(f, "{}", g),
             Self::Vector(ty, sz) => write!(f, "{}[{}]", ty, sz),
+            Self::Const(ty) => write!(f, "const {}", ty),
             Self::Bundle(fields) => {
                 let flist: String = fields.iter().map(|x| x.to_string() + ", ")
                     .collect::<String>();
                 let s = flist.trim_end_matches(", ");
                 write!(f, "{{ {} }}", s)
             },
-            Self::Ref(rty) => write!(f, "{}", rty),
-            Self::None => { 
-                panic!("cant format nonetype?");
+            Self::Enum(variants) => {
+                let vlist: Vec<String> = variants.iter()
+                    .map(|v| v.to_string()).collect();
+                write!(f, "{{| {} |}}", vlist.join(", "))
             },
+            Self::Ref(rty) => write!(f, "{}", rty),
+            Self::Property(pty) => write!(f, "{}", pty),
+            Self::Alias(id) => write!(f, "{}", id),
+            // NOTE: This isn't valid FIRRTL
+            Self::None => Err(fmt::Error),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BundleField {
     pub flip: bool,
     pub id: String,
@@ -360,9 +904,10 @@ impl BundleField {
         Self { flip, id: id.to_string(), ty }
     }
 
-    // FIXME: You're *assuming* all fields have a width/are uninferred?
-    pub fn width(&self) -> usize { 
-        self.ty.width().unwrap()
+    /// Get the bitwidth of this field. `None` indicates an inferred width
+    /// (or an unresolved type alias).
+    pub fn width(&self) -> Option<usize> { 
+        self.ty.width()
     }
 }
 impl fmt::Display for BundleField {
@@ -376,7 +921,29 @@ impl fmt::Display for BundleField {
     }
 }
 
-#[derive(Debug)]
+/// A variant of an enumeration type, with an optional data type
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnumVariant {
+    pub id: String,
+    pub ty: Option<FirrtlType>,
+}
+impl EnumVariant {
+    pub fn new(id: impl ToString, ty: Option<FirrtlType>) -> Self {
+        Self { id: id.to_string(), ty }
+    }
+}
+impl fmt::Display for EnumVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match &self.ty {
+            Some(ty) => write!(f, "{} : {}", self.id, ty),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Reference {
     Static(StaticReference),
     DynamicIndex(StaticReference, Box<Expr>),
@@ -392,114 +959,402 @@ impl Reference {
     }
 
 }
-impl fmt::Display for Reference {
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self { 
+            Self::Static(r) => write!(f, "{}", r),
+            Self::DynamicIndex(r, expr) => write!(f, "{}[{}]", r, expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StaticReference { 
+    Static(String),
+    Subfield(Box<Self>, String),
+    Subindex(Box<Self>, usize),
+}
+impl StaticReference {
+    pub fn new_static(s: impl ToString) -> Self {
+        Self::Static(s.to_string())
+    }
+    /// Get the identifier at the base of this static reference
+    pub fn get_ident(&self) -> &str {
+        match self {
+            Self::Static(s) => s,
+            Self::Subfield(s, _) => Self::get_ident(s),
+            Self::Subindex(s, _) => Self::get_ident(s),
+        }
+    }
+}
+impl fmt::Display for StaticReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self { 
+            Self::Static(r) => write!(f, "{}", r),
+            Self::Subfield(r, field) => write!(f, "{}.{}", r, field),
+            Self::Subindex(r, idx) => write!(f, "{}[{}]", r, idx),
+        }
+    }
+}
+
+
+/// FIRRTL register with a reset (`regreset` statement). 
+///
+/// The legacy form (`reg <id> : <type>, <clock> with : (reset => (...))`) 
+/// is also parsed into this.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegResetDecl {
+    pub id: String,
+    pub ty: FirrtlType,
+    pub clock: Expr,
+    pub reset: Expr,
+    /// Value of the register while `reset` is asserted
+    pub init: Expr,
+}
+impl RegResetDecl {
+    pub fn new(id: impl ToString, ty: FirrtlType, clock: Expr, reset: Expr,
+               init: Expr) -> Self 
+    {
+        Self { id: id.to_string(), ty, clock, reset, init }
+    }
+}
+
+
+/// FIRRTL memory declaration (`mem` statement)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemDecl {
+    pub id: String,
+    pub ty: FirrtlType,
+    pub depth: usize,
+    pub write_latency: usize,
+    pub read_latency: usize,
+    pub rp_list: Vec<String>,
+    pub wp_list: Vec<String>,
+    pub rwp_list: Vec<String>,
+    pub read_under_write: ReadUnderWrite,
+}
+impl MemDecl {
+    pub fn new(
+        id: impl ToString, 
+        ty: FirrtlType,
+        depth: usize,
+        read_latency: usize,
+        write_latency: usize,
+        read_under_write: ReadUnderWrite,
+        rp_list: Vec<String>,
+        wp_list: Vec<String>,
+        rwp_list: Vec<String>,
+    ) -> Self {
+        Self { 
+            id: id.to_string(), 
+            depth, 
+            ty, 
+            read_latency, 
+            write_latency, 
+            read_under_write,
+            rp_list, 
+            wp_list, 
+            rwp_list
+        }
+    }
+}
+
+
+/// CHIRRTL memory declaration (`cmem`/`smem` statement)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChirrtlMemDecl {
+    pub id: String,
+    /// Type of each element in the memory
+    pub ty: FirrtlType,
+    pub depth: usize,
+    pub kind: ChirrtlMemKind,
+}
+impl ChirrtlMemDecl {
+    pub fn new(id: impl ToString, ty: FirrtlType, depth: usize, 
+               kind: ChirrtlMemKind) -> Self 
+    {
+        Self { id: id.to_string(), ty, depth, kind }
+    }
+}
+
+/// The kind of CHIRRTL memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChirrtlMemKind {
+    /// Combinational read, sequential write (`cmem`)
+    Comb,
+    /// Sequential read and write (`smem`), with an optional 
+    /// read-under-write behavior
+    Seq(Option<ReadUnderWrite>),
+}
+
+/// CHIRRTL memory port (`<dir> mport <id> = <mem>[<addr>], <clock>`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemPortDecl {
+    pub dir: MemPortDir,
+    pub id: String,
+    pub mem: String,
+    pub addr: Expr,
+    pub clock: Expr,
+}
+impl MemPortDecl {
+    pub fn new(dir: MemPortDir, id: impl ToString, mem: impl ToString, 
+               addr: Expr, clock: Expr) -> Self 
+    {
+        Self { dir, id: id.to_string(), mem: mem.to_string(), addr, clock }
+    }
+}
+
+/// Direction of a CHIRRTL memory port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemPortDir {
+    /// The direction is inferred from how the port is used
+    Infer, 
+    Read, 
+    Write, 
+    ReadWrite,
+}
+impl MemPortDir {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s { 
+            "infer" => Some(Self::Infer),
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "rdwr" => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+    pub fn to_str(&self) -> &'static str {
+        match self { 
+            Self::Infer => "infer",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::ReadWrite => "rdwr",
+        }
+    }
+}
+impl fmt::Display for MemPortDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", Self::to_str(self))
+    }
+}
+
+/// The operands of a verification statement (`assert`, `assume` or `cover`)
+///
+/// NOTE: Like `printf`, the message is kept *with* its quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Verification {
+    pub clock: Expr,
+    /// The property being checked
+    pub pred: Expr,
+    /// The property is only checked while this is true
+    pub en: Expr,
+    pub msg: String,
+    /// Format arguments for `msg`
+    pub args: Vec<Expr>,
+    /// Optional label (`: <name>`)
+    pub name: Option<String>,
+}
+impl Verification {
+    pub fn new(clock: Expr, pred: Expr, en: Expr, msg: impl ToString, 
+               args: Vec<Expr>) -> Self 
+    {
+        Self { clock, pred, en, msg: msg.to_string(), args, name: None }
+    }
+}
+
+/// The operands of a `printf` statement
+///
+/// NOTE: The format string is kept *with* its quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Printf {
+    pub clock: Expr,
+    /// Nothing is printed unless this is true
+    pub en: Expr,
+    pub fmt: String,
+    /// Format arguments for `fmt`
+    pub args: Vec<Expr>,
+    /// Optional label (`: <name>`)
+    pub name: Option<String>,
+}
+impl Printf {
+    pub fn new(clock: Expr, en: Expr, fmt: impl ToString, args: Vec<Expr>) 
+        -> Self 
+    {
+        Self { clock, en, fmt: fmt.to_string(), args, name: None }
+    }
+}
+
+
+/// A use of an intrinsic (`intrinsic(<name><<params>> : <type>, <args>)`)
+///
+/// The result type is required when this is used as an expression. Tools 
+/// can provide implementations for particular intrinsics with an 
+/// [IntrinsicRegistry](crate::intrinsic::IntrinsicRegistry).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Intrinsic {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub ty: Option<FirrtlType>,
+    pub args: Vec<Expr>,
+}
+impl Intrinsic {
+    pub fn new(name: impl ToString, ty: Option<FirrtlType>, args: Vec<Expr>) 
+        -> Self 
+    {
+        Self { name: name.to_string(), params: Vec::new(), ty, args }
+    }
+
+    /// Get the value of a parameter by name.
+    pub fn param(&self, id: &str) -> Option<&ParamValue> {
+        self.params.iter().find(|p| p.id == id).map(|p| &p.value)
+    }
+}
+impl fmt::Display for Intrinsic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        match self { 
-            Self::Static(r) => write!(f, "{}", r),
-            Self::DynamicIndex(r, expr) => write!(f, "{}[{}]", r, expr),
+        write!(f, "intrinsic({}", self.name)?;
+        if !self.params.is_empty() {
+            let plist: Vec<String> = self.params.iter()
+                .map(|p| format!("{} = {}", p.id, p.value)).collect();
+            write!(f, "<{}>", plist.join(", "))?;
+        }
+        if let Some(ty) = &self.ty {
+            write!(f, " : {}", ty)?;
         }
+        for arg in &self.args {
+            write!(f, ", {}", arg)?;
+        }
+        write!(f, ")")
     }
 }
 
-#[derive(Debug)]
-pub enum StaticReference { 
-    Static(String),
-    Subfield(Box<Self>, String),
-    Subindex(Box<Self>, usize),
+
+/// FIRRTL statement along with any source info that was attached to it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub info: Option<SourceInfo>,
 }
-impl StaticReference {
-    pub fn new_static(s: impl ToString) -> Self {
-        Self::Static(s.to_string())
-    }
-    /// Get the identifier at the base of this static reference
-    pub fn get_ident(&self) -> &str {
-        match self {
-            Self::Static(s) => s,
-            Self::Subfield(s, _) => Self::get_ident(s),
-            Self::Subindex(s, _) => Self::get_ident(s),
-        }
+impl Statement {
+    pub fn new(kind: StatementKind, info: Option<SourceInfo>) -> Self {
+        Self { kind, info }
     }
 }
-impl fmt::Display for StaticReference {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        match self { 
-            Self::Static(r) => write!(f, "{}", r),
-            Self::Subfield(r, field) => write!(f, "{}.{}", r, field),
-            Self::Subindex(r, idx) => write!(f, "{}[{}]", r, idx),
-        }
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Self::new(kind, None)
     }
 }
 
+/// An arm of a `match` statement, selected when the value has the given 
+/// variant. The variant's data (if any) is bound to `binding`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchArm {
+    pub variant: String,
+    pub binding: Option<String>,
+    pub body: Vec<Statement>,
+}
+impl MatchArm {
+    pub fn new(variant: impl ToString, binding: Option<String>, 
+               body: Vec<Statement>) -> Self 
+    {
+        Self { variant: variant.to_string(), binding, body }
+    }
+}
 
-/// FIRRTL memory declaration (`mem` statement)
-#[derive(Debug)]
-pub struct MemDecl {
+/// Instantiates one of several modules, depending on the case selected 
+/// for an option group (`instchoice <id> of <default>, <option> :`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstChoice {
     pub id: String,
-    pub ty: FirrtlType,
-    pub depth: usize,
-    pub write_latency: usize,
-    pub read_latency: usize,
-    pub rp_list: Vec<String>,
-    pub wp_list: Vec<String>,
-    pub rwp_list: Vec<String>,
-    pub read_under_write: ReadUnderWrite,
+    /// Module instantiated when none of the cases are selected
+    pub default: String,
+    pub option: String,
+    /// Module instantiated for each case (`<case> => <module>`)
+    pub cases: Vec<(String, String)>,
 }
-impl MemDecl {
-    pub fn new(
-        id: impl ToString, 
-        ty: FirrtlType,
-        depth: usize,
-        read_latency: usize,
-        write_latency: usize,
-        read_under_write: ReadUnderWrite,
-        rp_list: Vec<String>,
-        wp_list: Vec<String>,
-        rwp_list: Vec<String>,
-    ) -> Self {
+impl InstChoice {
+    pub fn new(id: impl ToString, default: impl ToString, 
+               option: impl ToString) -> Self 
+    {
         Self { 
             id: id.to_string(), 
-            depth, 
-            ty, 
-            read_latency, 
-            write_latency, 
-            read_under_write,
-            rp_list, 
-            wp_list, 
-            rwp_list
+            default: default.to_string(), 
+            option: option.to_string(), 
+            cases: Vec::new() 
         }
     }
-}
 
+    /// Get the module instantiated when `case` is selected (or when no 
+    /// case is selected, for `None`).
+    pub fn target(&self, case: Option<&str>) -> &str {
+        case.and_then(|case| self.cases.iter().find(|(c, _)| c == case))
+            .map_or(&self.default, |(_, m)| m)
+    }
+}
 
 /// FIRRTL statements
-#[derive(Debug)]
-pub enum Statement {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatementKind {
     Wire(String, FirrtlType),
-    Reg(String, FirrtlType, Expr, Option<(Expr, Expr)>),
+    Reg(String, FirrtlType, Expr),
+    RegReset(RegResetDecl),
     Inst(String, String),
+    InstChoice(InstChoice),
+    /// Instantiate a class (`object <id> of <class>`)
+    Object(String, String),
     Mem(MemDecl),
     Node(String, Expr),
 
+    /// CHIRRTL memory (see [crate::pass::chirrtl])
+    ChirrtlMem(ChirrtlMemDecl),
+    /// CHIRRTL memory port (see [crate::pass::chirrtl])
+    MemPort(MemPortDecl),
+
     Attach(Vec<Reference>),
     PartialConnect(Reference, Expr),
     Connect(Reference, Expr),
+    /// Assign a property (`propassign <ref>, <expr>`)
+    PropAssign(StaticReference, Expr),
     Invalidate(Reference),
-    When(Expr, Vec<Self>, Vec<Self>),
+    When(Expr, Vec<Statement>, Vec<Statement>),
+    Match(Expr, Vec<MatchArm>),
+    /// Statements that only exist when a layer is enabled (`layerblock`).
+    /// Nested blocks refer to layers nested inside this one.
+    LayerBlock(String, Vec<Statement>),
 
-    Stop(Expr, Expr, usize),
+    /// `stop(clk, en, code)`, with an optional label (`: <name>`)
+    Stop(Expr, Expr, usize, Option<String>),
     Force(Expr, Expr, RefExpr, Expr),
     Release(Expr, Expr, RefExpr),
     ForceInitial(RefExpr, Expr),
     ReleaseInitial(RefExpr),
     Define(StaticReference, RefExpr),
-    Printf(Expr, Expr, String, Vec<Expr>),
+    Printf(Printf),
+    Assert(Verification),
+    Assume(Verification),
+    Cover(Verification),
+    Intrinsic(Intrinsic),
 
     Skip,
-    Unimplemented(String),
 }
 
 /// FIRRTL expressions
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Ref(Reference),
     Const(FirrtlType, LiteralNumeric),
@@ -509,8 +1364,61 @@ pub enum Expr {
     PrimOp1Expr(PrimOp1Expr, Box<Self>),
     PrimOp1Expr1Int(PrimOp1Expr1Int, Box<Self>, usize),
     PrimOp1Expr2Int(PrimOp1Expr2Int, Box<Self>, usize, usize),
+    /// Construct a value of an enumeration type (`<type>(<variant>, <expr>)`)
+    Enum(FirrtlType, String, Option<Box<Self>>),
+    /// Property literal (ie. `Integer(1)` or `String("a")`)
+    Property(PropertyLiteral),
+    /// List of properties (`List<<type>>(<expr>, ...)`)
+    List(PropertyType, Vec<Self>),
+    /// Property primitive operation (ie. `integer_add(a, b)`)
+    PropPrimOp(PropPrimOp, Vec<Self>),
+    Intrinsic(Intrinsic),
+    /// Field of an aggregate expression (ie. `read(probe(x)).a`)
+    Subfield(Box<Self>, String),
+    /// Constant index into an aggregate expression (ie. `mux(c, a, b)[0]`)
+    Subindex(Box<Self>, usize),
+    /// Dynamic index into an aggregate expression (ie. `a[i][j]`)
+    Subaccess(Box<Self>, Box<Self>),
     None,
 }
+impl Expr {
+    /// Get the field `field` of this expression.
+    ///
+    /// NOTE: Fields of a static reference stay part of the reference, so 
+    /// `a.b` is always represented the same way.
+    pub fn subfield(self, field: impl ToString) -> Self {
+        match self {
+            Self::Ref(Reference::Static(sr)) => {
+                Self::Ref(Reference::Static(StaticReference::Subfield(
+                    Box::new(sr), field.to_string()
+                )))
+            },
+            e => Self::Subfield(Box::new(e), field.to_string()),
+        }
+    }
+
+    /// Get the element at constant index `idx` of this expression.
+    pub fn subindex(self, idx: usize) -> Self {
+        match self {
+            Self::Ref(Reference::Static(sr)) => {
+                Self::Ref(Reference::Static(StaticReference::Subindex(
+                    Box::new(sr), idx
+                )))
+            },
+            e => Self::Subindex(Box::new(e), idx),
+        }
+    }
+
+    /// Get the element at dynamic index `idx` of this expression.
+    pub fn subaccess(self, idx: Expr) -> Self {
+        match self {
+            Self::Ref(Reference::Static(sr)) => {
+                Self::Ref(Reference::DynamicIndex(sr, Box::new(idx)))
+            },
+            e => Self::Subaccess(Box::new(e), Box::new(idx)),
+        }
+    }
+}
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -528,14 +1436,74 @@ impl fmt::Display for Expr {
             Self::PrimOp1Expr2Int(op, e1, lit1, lit2) => {
                 write!(f, "{}({}, {}, {})", op, e1, lit1, lit2)
             },
-            Self::None => panic!("format none expr?"),
+            Self::Enum(ty, variant, None) => write!(f, "{}({})", ty, variant),
+            Self::Enum(ty, variant, Some(e)) => {
+                write!(f, "{}({}, {})", ty, variant, e)
+            },
+            Self::Property(lit) => write!(f, "{}", lit),
+            Self::Intrinsic(intr) => write!(f, "{}", intr),
+            Self::Subfield(e, field) => write!(f, "{}.{}", e, field),
+            Self::Subindex(e, idx) => write!(f, "{}[{}]", e, idx),
+            Self::Subaccess(e, idx) => write!(f, "{}[{}]", e, idx),
+            Self::List(ty, elems) => {
+                let elist: Vec<String> = elems.iter()
+                    .map(|e| e.to_string()).collect();
+                write!(f, "List<{}>({})", ty, elist.join(", "))
+            },
+            Self::PropPrimOp(op, args) => {
+                let alist: Vec<String> = args.iter()
+                    .map(|e| e.to_string()).collect();
+                write!(f, "{}({})", op, alist.join(", "))
+            },
+            // NOTE: This isn't valid FIRRTL
+            Self::None => Err(fmt::Error),
+        }
+    }
+}
 
+/// FIRRTL property literals
+///
+/// NOTE: Like module parameters, `Double` values are kept in their original
+/// textual form, and strings/paths are kept *without* their quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PropertyLiteral {
+    Integer(
+        #[cfg_attr(feature = "serde", serde(with = "serde_decimal"))]
+        BigInt
+    ),
+    Bool(bool),
+    Double(String),
+    String(String),
+    Path(String),
+}
+impl PropertyLiteral {
+    /// Get the type of this literal.
+    pub fn ty(&self) -> PropertyType {
+        match self {
+            Self::Integer(_) => PropertyType::Integer,
+            Self::Bool(_) => PropertyType::Bool,
+            Self::Double(_) => PropertyType::Double,
+            Self::String(_) => PropertyType::String,
+            Self::Path(_) => PropertyType::Path,
+        }
+    }
+}
+impl fmt::Display for PropertyLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Integer(v) => write!(f, "Integer({})", v),
+            Self::Bool(v) => write!(f, "Bool({})", v),
+            Self::Double(v) => write!(f, "Double({})", v),
+            Self::String(v) => write!(f, "String(\"{}\")", v),
+            Self::Path(v) => write!(f, "Path(\"{}\")", v),
         }
     }
 }
 
 /// FIRRTL reference expressions
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RefExpr {
     Static(StaticReference),
     RwProbe(StaticReference),
@@ -551,10 +1519,56 @@ impl fmt::Display for RefExpr {
     }
 }
 
-/// FIRRTL numeric literals
-#[derive(Debug)]
+/// Numeric literals are serialized as decimal strings.
+#[cfg(feature = "serde")]
+mod serde_decimal {
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use serde::{ Deserialize, Deserializer, Serializer };
+
+    pub fn serialize<S: Serializer, T: Display>(v: &T, s: S) 
+        -> Result<S::Ok, S::Error> 
+    {
+        s.collect_str(v)
+    }
+
+    pub fn deserialize<'de, D, T>(d: D) -> Result<T, D::Error>
+        where D: Deserializer<'de>, T: FromStr, T::Err: Display
+    {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// FIRRTL numeric literals (of any width)
+///
+/// NOTE: These are the same types used by `num::BigUint`/`num::BigInt`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LiteralNumeric {
-    UInt(usize), SInt(isize),
+    UInt(
+        #[cfg_attr(feature = "serde", serde(with = "serde_decimal"))]
+        BigUint
+    ), 
+    SInt(
+        #[cfg_attr(feature = "serde", serde(with = "serde_decimal"))]
+        BigInt
+    ),
+}
+impl LiteralNumeric {
+    /// The minimum number of bits needed to represent this value.
+    pub fn min_width(&self) -> usize {
+        match self {
+            Self::UInt(v) => v.bits() as usize,
+            Self::SInt(v) if v.sign() == Sign::NoSign => 0,
+            Self::SInt(v) if v.sign() == Sign::Minus => {
+                // ie. -1 is '1', -2 is '10'
+                let mag = v.magnitude() - 1u32;
+                mag.bits() as usize + 1
+            },
+            Self::SInt(v) => v.bits() as usize + 1,
+        }
+    }
 }
 impl fmt::Display for LiteralNumeric {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
@@ -565,7 +1579,8 @@ impl fmt::Display for LiteralNumeric {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReadUnderWrite { 
     Old, New, Undefined
 }
@@ -593,7 +1608,8 @@ impl fmt::Display for ReadUnderWrite {
 }
 
 /// FIRRTL port direction
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Direction { 
     Input, Output 
 }
@@ -623,7 +1639,8 @@ impl fmt::Display for Direction {
 /// Primitive operations (2 expressions)
 ///
 /// NOTE: 'dshlw' only occurs in SFC output?
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimOp2Expr {
     Add, Sub, Mul, Div, Mod,
     Lt, Leq, Gt, Geq, Eq, Neq,
@@ -631,6 +1648,14 @@ pub enum PrimOp2Expr {
     And, Or, Xor, Cat
 }
 impl PrimOp2Expr {
+    /// All binary primitive operations
+    pub const ALL: &'static [Self] = &[
+        Self::Add, Self::Sub, Self::Mul, Self::Div, Self::Mod,
+        Self::Lt, Self::Leq, Self::Gt, Self::Geq, Self::Eq, Self::Neq,
+        Self::Dshl, Self::Dshlw, Self::Dshr,
+        Self::And, Self::Or, Self::Xor, Self::Cat,
+    ];
+
     pub fn from_str(s: &str) -> Option<Self> { 
         match s {
             "add"  => Some(Self::Add),
@@ -666,10 +1691,10 @@ impl PrimOp2Expr {
             Self::Gt => "gt",
             Self::Geq => "geq",
             Self::Eq => "eq",
-            Self::Neq => "ne",
+            Self::Neq => "neq",
             Self::Dshl => "dshl",
             Self::Dshlw => "dshlw",
-            Self::Dshr => "dsh",
+            Self::Dshr => "dshr",
             Self::And => "and",
             Self::Or => "or",
             Self::Xor => "xor",
@@ -685,13 +1710,20 @@ impl fmt::Display for PrimOp2Expr {
 
 
 /// Primitive operations (1 expression)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimOp1Expr {
     AsUInt, AsSInt, AsClock, AsAsyncReset, Cvt,
     Neg, Not,
     Andr, Orr, Xorr
 }
 impl PrimOp1Expr {
+    /// All unary primitive operations
+    pub const ALL: &'static [Self] = &[
+        Self::AsUInt, Self::AsSInt, Self::AsClock, Self::AsAsyncReset, 
+        Self::Cvt, Self::Neg, Self::Not, Self::Andr, Self::Orr, Self::Xorr,
+    ];
+
     pub fn from_str(s: &str) -> Option<Self> { 
         match s {
             "asUInt"  => Some(Self::AsUInt),
@@ -730,11 +1762,17 @@ impl fmt::Display for PrimOp1Expr {
 
 
 /// Primitive operations (1 expression, 1 integer literal)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimOp1Expr1Int {
     Pad, Shl, Shr, Head, Tail
 }
 impl PrimOp1Expr1Int {
+    /// All primitive operations with one expression and one integer
+    pub const ALL: &'static [Self] = &[
+        Self::Pad, Self::Shl, Self::Shr, Self::Head, Self::Tail,
+    ];
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "pad"  => Some(Self::Pad),
@@ -763,11 +1801,15 @@ impl fmt::Display for PrimOp1Expr1Int {
 
 
 /// Primitive operations (1 expression, 2 integer literals)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimOp1Expr2Int {
     Bits
 }
 impl PrimOp1Expr2Int {
+    /// All primitive operations with one expression and two integers
+    pub const ALL: &'static [Self] = &[ Self::Bits ];
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "bits" => Some(Self::Bits),
@@ -787,4 +1829,585 @@ impl fmt::Display for PrimOp1Expr2Int {
 }
 
 
+/// Primitive operations on properties
+///
+/// NOTE: `list_concat` takes any number of lists, the others take exactly
+/// two integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PropPrimOp {
+    IntegerAdd, IntegerMul, IntegerShr, IntegerShl, ListConcat
+}
+impl PropPrimOp {
+    /// All property primitive operations
+    pub const ALL: &'static [Self] = &[
+        Self::IntegerAdd, Self::IntegerMul, Self::IntegerShr,
+        Self::IntegerShl, Self::ListConcat,
+    ];
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "integer_add" => Some(Self::IntegerAdd),
+            "integer_mul" => Some(Self::IntegerMul),
+            "integer_shr" => Some(Self::IntegerShr),
+            "integer_shl" => Some(Self::IntegerShl),
+            "list_concat" => Some(Self::ListConcat),
+            _ => None,
+        }
+    }
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::IntegerAdd => "integer_add",
+            Self::IntegerMul => "integer_mul",
+            Self::IntegerShr => "integer_shr",
+            Self::IntegerShl => "integer_shl",
+            Self::ListConcat => "list_concat",
+        }
+    }
+}
+impl fmt::Display for PropPrimOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", Self::to_str(self))
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FirrtlFile;
+
+    /// Tiny deterministic PRNG (xorshift64), good enough for generating 
+    /// arbitrary ASTs without pulling in another dependency.
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+        fn chance(&mut self, n: usize) -> bool {
+            self.below(n) == 0
+        }
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.below(items.len())]
+        }
+    }
+
+    /// Names of the ports declared by [wrap_statements].
+    const PORTS: &[&str] = &["clock", "a", "b", "v", "s"];
+    const VERSIONS: &[Version] = &[
+        Version::new(1, 0, 0), Version::new(2, 0, 0), Version::new(3, 0, 0),
+        Version::new(3, 3, 0), Version::new(4, 0, 0),
+    ];
+
+    fn gen_info(rng: &mut Rng) -> Option<SourceInfo> {
+        if rng.chance(2) {
+            return None;
+        }
+        let line = Some(rng.below(100) + 1);
+        let col = if rng.chance(3) { None } else { Some(rng.below(80) + 1) };
+        Some(SourceInfo::new(vec![SourceLocator::new("Gen.scala", line, col)]))
+    }
+
+    /// NOTE: Probe types are only generated at the top level
+    /// Only the outermost type can be `const`, since `const` in front of 
+    /// a vector type applies to the whole vector.
+    fn gen_const_type(rng: &mut Rng, depth: usize) -> FirrtlType {
+        FirrtlType::Const(Box::new(gen_type(rng, depth.min(1))))
+    }
+
+    fn gen_type(rng: &mut Rng, depth: usize) -> FirrtlType {
+        let width = if rng.chance(4) { None } else { Some(rng.below(64) + 1) };
+        let choices = match depth { 0 => 6, 1 => 9, _ => 10 };
+        match rng.below(choices) {
+            0 => FirrtlType::Ground(FirrtlTypeGround::UInt(width)),
+            1 => FirrtlType::Ground(FirrtlTypeGround::SInt(width)),
+            2 => FirrtlType::Ground(FirrtlTypeGround::Clock),
+            3 => FirrtlType::Ground(FirrtlTypeGround::Reset),
+            4 => FirrtlType::Ground(FirrtlTypeGround::AsyncReset),
+            5 => FirrtlType::Ground(FirrtlTypeGround::Analog(width)),
+            6 => FirrtlType::Vector(
+                Box::new(gen_type(rng, depth - 1)), rng.below(8) + 1
+            ),
+            7 => {
+                let fields = (0..rng.below(3) + 1).map(|i| {
+                    let ty = if rng.chance(4) {
+                        gen_const_type(rng, depth - 1)
+                    } else {
+                        gen_type(rng, depth - 1)
+                    };
+                    BundleField::new(rng.chance(2), format!("f{}", i), ty)
+                }).collect();
+                FirrtlType::Bundle(fields)
+            },
+            8 => gen_enum(rng, depth),
+            _ => {
+                let ty = Box::new(gen_type(rng, depth - 1));
+                let layer = match rng.below(3) {
+                    0 => Some("L0".to_string()),
+                    1 => Some("L0.L1".to_string()),
+                    _ => None,
+                };
+                if rng.chance(2) {
+                    FirrtlType::Ref(FirrtlTypeRef::Probe(ty, layer))
+                } else {
+                    FirrtlType::Ref(FirrtlTypeRef::RWProbe(ty, layer))
+                }
+            },
+        }
+    }
+
+    fn gen_enum(rng: &mut Rng, depth: usize) -> FirrtlType {
+        let variants = (0..rng.below(3) + 1).map(|i| {
+            let ty = if rng.chance(2) { 
+                Some(gen_type(rng, depth - 1)) 
+            } else { 
+                None 
+            };
+            EnumVariant::new(format!("V{}", i), ty)
+        }).collect();
+        FirrtlType::Enum(variants)
+    }
+
+    fn gen_static_ref(rng: &mut Rng) -> StaticReference {
+        let mut r = StaticReference::new_static(rng.pick(PORTS));
+        for _ in 0..rng.below(3) {
+            r = if rng.chance(2) {
+                StaticReference::Subfield(Box::new(r), 
+                    rng.pick(&["x", "y", "0"]).to_string())
+            } else {
+                StaticReference::Subindex(Box::new(r), rng.below(4))
+            };
+        }
+        r
+    }
+
+    fn gen_ref(rng: &mut Rng, depth: usize) -> Reference {
+        if depth > 0 && rng.chance(4) {
+            Reference::DynamicIndex(gen_static_ref(rng), 
+                Box::new(gen_expr(rng, depth - 1)))
+        } else {
+            Reference::Static(gen_static_ref(rng))
+        }
+    }
+
+    fn gen_ref_expr(rng: &mut Rng) -> RefExpr {
+        match rng.below(3) {
+            0 => RefExpr::Static(gen_static_ref(rng)),
+            1 => RefExpr::Probe(gen_static_ref(rng)),
+            _ => RefExpr::RwProbe(gen_static_ref(rng)),
+        }
+    }
+
+    fn gen_const(rng: &mut Rng) -> Expr {
+        let (ty, lit) = if rng.chance(2) {
+            let lit = LiteralNumeric::UInt(BigUint::from(rng.next()) << 
+                rng.below(100));
+            (FirrtlTypeGround::UInt as fn(_) -> _, lit)
+        } else {
+            let lit = LiteralNumeric::SInt(BigInt::from(rng.next() as i64) << 
+                rng.below(100));
+            (FirrtlTypeGround::SInt as fn(_) -> _, lit)
+        };
+        let width = if rng.chance(3) { None } 
+                    else { Some(lit.min_width() + rng.below(8)) };
+        Expr::Const(FirrtlType::Ground(ty(width)), lit)
+    }
+
+    fn gen_expr(rng: &mut Rng, depth: usize) -> Expr {
+        let b = |rng: &mut Rng| Box::new(gen_expr(rng, depth - 1));
+        match rng.below(if depth == 0 { 3 } else { 11 }) {
+            0 => Expr::Ref(gen_ref(rng, depth)),
+            1 => gen_const(rng),
+            2 => Expr::Read(gen_ref_expr(rng)),
+            3 => Expr::Mux(b(rng), b(rng), b(rng)),
+            4 => {
+                let op = *rng.pick(PrimOp2Expr::ALL);
+                Expr::PrimOp2Expr(op, b(rng), b(rng))
+            },
+            5 => Expr::PrimOp1Expr(*rng.pick(PrimOp1Expr::ALL), b(rng)),
+            6 => {
+                let op = *rng.pick(PrimOp1Expr1Int::ALL);
+                Expr::PrimOp1Expr1Int(op, b(rng), rng.below(16))
+            },
+            7 => {
+                let op = *rng.pick(PrimOp1Expr2Int::ALL);
+                Expr::PrimOp1Expr2Int(op, b(rng), rng.below(16), rng.below(16))
+            },
+            8 => {
+                let ty = gen_type(rng, 1);
+                Expr::Intrinsic(gen_intrinsic(rng, depth - 1, Some(ty)))
+            },
+            9 => {
+                let e = gen_expr(rng, depth - 1);
+                match rng.below(3) {
+                    0 => e.subfield(*rng.pick(&["a", "b", "0"])),
+                    1 => e.subindex(rng.below(4)),
+                    _ => e.subaccess(gen_expr(rng, depth - 1)),
+                }
+            },
+            _ => {
+                let ty = gen_enum(rng, 1);
+                let FirrtlType::Enum(variants) = &ty else { unreachable!() };
+                let v = rng.pick(variants).clone();
+                let data = v.ty.map(|_| b(rng));
+                Expr::Enum(ty, v.id, data)
+            },
+        }
+    }
+
+    fn gen_intrinsic(rng: &mut Rng, depth: usize, ty: Option<FirrtlType>)
+        -> Intrinsic
+    {
+        let args = (0..rng.below(3)).map(|_| gen_expr(rng, depth)).collect();
+        let mut intr = Intrinsic::new(format!("i{}", rng.below(10)), ty, args);
+        for i in 0..rng.below(3) {
+            let value = match rng.below(5) {
+                0 => ParamValue::Int(BigUint::from(rng.next())),
+                1 => ParamValue::SInt(BigInt::from(rng.next() as i64)),
+                2 => ParamValue::Float("0.5".to_string()),
+                3 => ParamValue::String("x=%d".to_string()),
+                _ => ParamValue::RawString("y".to_string()),
+            };
+            intr.params.push(Parameter::new(format!("P{}", i), value));
+        }
+        intr
+    }
+
+    fn gen_property_type(rng: &mut Rng, depth: usize) -> PropertyType {
+        match rng.below(if depth == 0 { 7 } else { 8 }) {
+            0 => PropertyType::Integer,
+            1 => PropertyType::String,
+            2 => PropertyType::Bool,
+            3 => PropertyType::Double,
+            4 => PropertyType::Path,
+            5 => PropertyType::AnyRef,
+            6 => PropertyType::Inst("Meta".to_string()),
+            _ => {
+                let elem = gen_property_type(rng, depth - 1);
+                PropertyType::List(Box::new(elem))
+            },
+        }
+    }
+
+    fn gen_prop_expr(rng: &mut Rng, depth: usize) -> Expr {
+        let args = |rng: &mut Rng| {
+            (0..rng.below(3)).map(|_| gen_prop_expr(rng, depth - 1)).collect()
+        };
+        match rng.below(if depth == 0 { 6 } else { 8 }) {
+            0 => Expr::Ref(Reference::Static(gen_static_ref(rng))),
+            1 => Expr::Property(PropertyLiteral::Integer(
+                BigInt::from(rng.next() as i64) << rng.below(100))),
+            2 => Expr::Property(PropertyLiteral::Bool(rng.chance(2))),
+            3 => Expr::Property(PropertyLiteral::Double(
+                rng.pick(&["0.5", "-1.25", "1.0E-3"]).to_string())),
+            4 => Expr::Property(PropertyLiteral::String("x".to_string())),
+            5 => Expr::Property(PropertyLiteral::Path(
+                "OMReferenceTarget:~Top|Top>a".to_string())),
+            6 => Expr::List(gen_property_type(rng, 1), args(rng)),
+            _ => Expr::PropPrimOp(*rng.pick(PropPrimOp::ALL), args(rng)),
+        }
+    }
+
+    fn gen_block(rng: &mut Rng, depth: usize) -> Vec<Statement> {
+        (0..rng.below(3) + 1).map(|_| gen_statement(rng, depth)).collect()
+    }
+
+    fn gen_statement(rng: &mut Rng, depth: usize) -> Statement {
+        let id = format!("n{}", rng.below(1000));
+        let kind = match rng.below(if depth == 0 { 26 } else { 29 }) {
+            0 => StatementKind::Wire(id, gen_type(rng, 2)),
+            1 => StatementKind::Reg(id, gen_type(rng, 2), gen_expr(rng, 1)),
+            2 => StatementKind::RegReset(RegResetDecl::new(id, 
+                gen_type(rng, 2), gen_expr(rng, 1), gen_expr(rng, 2), 
+                gen_expr(rng, 2))),
+            3 => StatementKind::Inst(id, "Sub".to_string()),
+            4 => StatementKind::Mem(MemDecl::new(id, gen_type(rng, 2), 
+                rng.below(1024) + 1, rng.below(2), rng.below(2) + 1,
+                *rng.pick(&[ReadUnderWrite::Old, ReadUnderWrite::New, 
+                    ReadUnderWrite::Undefined]),
+                vec!["r0".to_string()], vec!["w0".to_string()],
+                if rng.chance(2) { vec!["rw0".to_string()] } else { vec![] },
+            )),
+            5 => StatementKind::Node(id, gen_expr(rng, 3)),
+            6 => StatementKind::Attach(
+                (0..rng.below(3) + 1).map(|_| gen_ref(rng, 0)).collect()
+            ),
+            7 => StatementKind::PartialConnect(gen_ref(rng, 2), 
+                gen_expr(rng, 3)),
+            8 => StatementKind::Connect(gen_ref(rng, 2), gen_expr(rng, 3)),
+            9 => StatementKind::Invalidate(gen_ref(rng, 2)),
+            10 => StatementKind::Stop(gen_expr(rng, 1), gen_expr(rng, 1), 
+                rng.below(4), rng.chance(2).then_some(id)),
+            11 => StatementKind::Force(gen_expr(rng, 1), gen_expr(rng, 1), 
+                gen_ref_expr(rng), gen_expr(rng, 2)),
+            12 => StatementKind::Release(gen_expr(rng, 1), gen_expr(rng, 1),
+                gen_ref_expr(rng)),
+            13 => StatementKind::ForceInitial(gen_ref_expr(rng), 
+                gen_expr(rng, 2)),
+            14 => StatementKind::ReleaseInitial(gen_ref_expr(rng)),
+            15 => StatementKind::Define(gen_static_ref(rng), 
+                gen_ref_expr(rng)),
+            16 => {
+                let mut p = Printf::new(gen_expr(rng, 1), gen_expr(rng, 1),
+                    "\"x=%d\\n\"", 
+                    (0..rng.below(3)).map(|_| gen_expr(rng, 2)).collect());
+                p.name = rng.chance(2).then_some(id);
+                StatementKind::Printf(p)
+            },
+            17 => StatementKind::ChirrtlMem(ChirrtlMemDecl::new(id, 
+                gen_type(rng, 1), rng.below(1024) + 1, *rng.pick(&[
+                    ChirrtlMemKind::Comb, ChirrtlMemKind::Seq(None),
+                    ChirrtlMemKind::Seq(Some(ReadUnderWrite::Old)),
+                ]))),
+            18 => StatementKind::MemPort(MemPortDecl::new(*rng.pick(&[
+                    MemPortDir::Infer, MemPortDir::Read, MemPortDir::Write,
+                    MemPortDir::ReadWrite,
+                ]), id, "m", gen_expr(rng, 2), gen_expr(rng, 1))),
+            19 | 20 => {
+                let mut v = Verification::new(gen_expr(rng, 1), 
+                    gen_expr(rng, 2), gen_expr(rng, 1), "\"x=%d\"", 
+                    (0..rng.below(3)).map(|_| gen_expr(rng, 2)).collect());
+                if rng.chance(2) {
+                    v.name = Some(id);
+                }
+                match rng.below(3) {
+                    0 => StatementKind::Assert(v),
+                    1 => StatementKind::Assume(v),
+                    _ => StatementKind::Cover(v),
+                }
+            },
+            21 => StatementKind::Object(id, "Meta".to_string()),
+            22 => StatementKind::PropAssign(gen_static_ref(rng), 
+                gen_prop_expr(rng, 2)),
+            23 => StatementKind::Intrinsic(gen_intrinsic(rng, 2, None)),
+            24 => {
+                let mut choice = InstChoice::new(id, "Sub", "Opt");
+                for case in ["A", "B"] {
+                    if rng.chance(2) {
+                        let module = format!("Sub{}", case);
+                        choice.cases.push((case.to_string(), module));
+                    }
+                }
+                StatementKind::InstChoice(choice)
+            },
+            25 => StatementKind::Skip,
+            26 => {
+                let eblk = match rng.below(3) {
+                    0 => Vec::new(),
+                    1 => vec![gen_statement(rng, depth - 1)],
+                    _ => gen_block(rng, depth - 1),
+                };
+                StatementKind::When(gen_expr(rng, 2), 
+                    gen_block(rng, depth - 1), eblk)
+            },
+            // Nested blocks use the child layer (see 'gen_layers')
+            27 => StatementKind::LayerBlock(format!("L{}", depth), 
+                gen_block(rng, depth - 1)),
+            _ => {
+                // Arms must agree with the variants of the matched type
+                let ty = gen_enum(rng, 1);
+                let FirrtlType::Enum(variants) = &ty else { unreachable!() };
+                let v = rng.pick(variants).clone();
+                let data = v.ty.map(|_| Box::new(gen_expr(rng, 1)));
+                let mut arms = Vec::new();
+                for (i, v) in variants.iter().enumerate() {
+                    if i == 0 || rng.chance(2) {
+                        let binding = v.ty.as_ref().map(|_| format!("b{}", i));
+                        arms.push(MatchArm::new(&v.id, binding, 
+                            gen_block(rng, depth - 1)));
+                    }
+                }
+                StatementKind::Match(Expr::Enum(ty.clone(), v.id, data), arms)
+            },
+        };
+        Statement::new(kind, gen_info(rng))
+    }
+
+    /// A chain of nested layers 'L<depth>' for each depth of a block.
+    fn gen_layers(rng: &mut Rng, depth: usize) -> Layer {
+        let convention = *rng.pick(&[LayerConvention::Bind, 
+            LayerConvention::Inline]);
+        let mut layer = Layer::new(format!("L{}", depth), convention);
+        if convention == LayerConvention::Bind && rng.chance(2) {
+            layer.output_dir = Some(format!("layers/l{}", depth));
+        }
+        if depth > 1 {
+            layer.layers.push(gen_layers(rng, depth - 1));
+        }
+        layer
+    }
+
+    fn gen_params(rng: &mut Rng) -> Vec<Parameter> {
+        (0..rng.below(4)).map(|i| {
+            let value = match rng.below(5) {
+                0 => ParamValue::Int(BigUint::from(rng.below(1 << 16))),
+                1 => ParamValue::SInt(BigInt::from(rng.below(256)) - 128),
+                2 => ParamValue::Float(
+                    rng.pick(&["0.5", "-1.25", "1.0E-3"]).to_string()),
+                3 => ParamValue::String(
+                    rng.pick(&["", "x=%d", "a\\nb"]).to_string()),
+                _ => ParamValue::RawString(
+                    rng.pick(&["y", "a\\b"]).to_string()),
+            };
+            Parameter::new(format!("P{}", i), value)
+        }).collect()
+    }
+
+    fn gen_extmodule(rng: &mut Rng) -> ExtModule {
+        let mut ports = vec![
+            PortDecl::new("a", Direction::Input, gen_type(rng, 1)),
+        ];
+        let has_ref = rng.chance(2);
+        if has_ref {
+            ports.push(PortDecl::new("r", Direction::Output, FirrtlType::Ref(
+                FirrtlTypeRef::Probe(Box::new(gen_type(rng, 0)), None))));
+        }
+        let mut m = ExtModule::new("BlackBox", ports);
+        if rng.chance(2) {
+            m.defname = Some("BlackBoxImpl".to_string());
+        }
+        m.params = gen_params(rng);
+        if has_ref {
+            m.refs.push(RefDecl::new(StaticReference::new_static("r"), 
+                "impl.mem"));
+        }
+        m.info = gen_info(rng);
+        m
+    }
+
+    fn gen_intmodule(rng: &mut Rng) -> IntModule {
+        let ports = vec![
+            PortDecl::new("o", Direction::Output, gen_type(rng, 1)),
+        ];
+        let mut m = IntModule::new("Intr", ports, "circt_plusargs_value");
+        m.params = gen_params(rng);
+        m.info = gen_info(rng);
+        m
+    }
+
+    fn wrap_statements(statements: Vec<Statement>) -> Circuit {
+        let ty = FirrtlType::Ground(FirrtlTypeGround::UInt(Some(8)));
+        let ports = PORTS.iter().map(|id| {
+            PortDecl::new(id, Direction::Input, ty.clone())
+        }).collect();
+        let mut circuit = Circuit::new("Top");
+        circuit.add_module(Module::new("Top", ports, statements));
+        circuit
+    }
+
+    /// The oldest version that can express everything in `circuit`.
+    fn oldest_version(circuit: &Circuit) -> Option<Version> {
+        VERSIONS.iter().copied().find(|v| {
+            crate::version::check_circuit(circuit, *v).is_ok()
+        })
+    }
+
+    /// Print a circuit, parse it back, and check that nothing changed.
+    fn check_roundtrip(circuit: &Circuit) {
+        let text = circuit.to_string();
+        let parsed = FirrtlFile::from_str("gen.fir", &text)
+            .and_then(|ff| ff.parse())
+            .unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(&parsed, circuit, "\n{}", text);
+    }
+
+    #[test]
+    fn roundtrip_primops() {
+        let a = || Box::new(Expr::Ref(Reference::Static(
+            StaticReference::new_static("a")
+        )));
+        let b = || Box::new(gen_const(&mut Rng(7)));
+        let mut exprs = Vec::new();
+        for op in PrimOp2Expr::ALL {
+            exprs.push(Expr::PrimOp2Expr(*op, a(), b()));
+        }
+        for op in PrimOp1Expr::ALL {
+            exprs.push(Expr::PrimOp1Expr(*op, a()));
+        }
+        for op in PrimOp1Expr1Int::ALL {
+            exprs.push(Expr::PrimOp1Expr1Int(*op, a(), 3));
+        }
+        for op in PrimOp1Expr2Int::ALL {
+            exprs.push(Expr::PrimOp1Expr2Int(*op, a(), 3, 1));
+        }
+        let statements = exprs.into_iter().enumerate().map(|(i, e)| {
+            StatementKind::Node(format!("n{}", i), e).into()
+        }).collect();
+        check_roundtrip(&wrap_statements(statements));
+    }
+
+    #[test]
+    fn roundtrip_arbitrary() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let statements = gen_block(&mut rng, 3);
+            let mut circuit = wrap_statements(statements);
+            circuit.add_layer(gen_layers(&mut rng, 3));
+            let dir = if rng.chance(2) { Direction::Input } 
+                      else { Direction::Output };
+            let mut port = PortDecl::new("p", dir, gen_type(&mut rng, 2));
+            port.info = gen_info(&mut rng);
+            circuit.modules[0].ports.push(port);
+            circuit.modules[0].public = rng.chance(2);
+            if rng.chance(2) {
+                circuit.modules[0].enabled_layers.push("L3".to_string());
+            }
+            if rng.chance(2) {
+                let cases = vec!["A".to_string(), "B".to_string()];
+                let mut option = OptionGroup::new("Opt", cases);
+                option.info = gen_info(&mut rng);
+                circuit.add_option(option);
+            }
+            if rng.chance(2) {
+                let mut alias = TypeAlias::new("T0", gen_type(&mut rng, 1));
+                alias.info = gen_info(&mut rng);
+                circuit.add_type_alias(alias);
+                circuit.modules[0].ports.push(PortDecl::new("q", 
+                    Direction::Input, FirrtlType::Vector(
+                        Box::new(FirrtlType::Alias("T0".to_string())), 2)));
+            }
+            if rng.chance(2) {
+                let ports = PORTS.iter().map(|id| {
+                    let ty = gen_property_type(&mut rng, 2);
+                    PortDecl::new(id, Direction::Input, FirrtlType::Property(ty))
+                }).collect();
+                let statements = (0..rng.below(3)).map(|_| {
+                    StatementKind::PropAssign(gen_static_ref(&mut rng),
+                        gen_prop_expr(&mut rng, 2)).into()
+                }).collect();
+                let mut class = Class::new("Meta", ports, statements);
+                class.info = gen_info(&mut rng);
+                circuit.add_class(class);
+                circuit.add_extclass(ExtClass::new("Ext", vec![
+                    PortDecl::new("d", Direction::Output,
+                        FirrtlType::Property(PropertyType::Double))
+                ]));
+            }
+            if rng.chance(2) {
+                circuit.add_extmodule(gen_extmodule(&mut rng));
+            }
+            if rng.chance(2) {
+                circuit.add_intmodule(gen_intmodule(&mut rng));
+            }
+            circuit.info = gen_info(&mut rng);
+
+            if !rng.chance(3) {
+                circuit.version = oldest_version(&circuit);
+            }
+            check_roundtrip(&circuit);
 
+            // Smaller circuits are more likely to fit in older versions
+            let mut circuit = wrap_statements(gen_block(&mut rng, 1));
+            circuit.version = oldest_version(&circuit);
+            check_roundtrip(&circuit);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Reporting errors against the original FIRRTL source.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::lex::{ FirrtlParseError, ParseErrorKind };
+
+/// A message associated with some location in a FIRRTL source file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The main message
+    pub message: String,
+    /// Source filename
+    pub filename: String,
+    /// Line number in the source file (starting at 1).
+    ///
+    /// NOTE: This is `0` when the message isn't associated with any line.
+    pub line: usize,
+    /// Range of columns on the line (starting at 1)
+    pub span: Range<usize>,
+    /// Additional notes printed after the source snippet
+    pub notes: Vec<String>,
+}
+impl Diagnostic {
+    pub fn new(filename: impl ToString, line: usize, span: Range<usize>,
+               message: impl ToString) -> Self
+    {
+        Self {
+            message: message.to_string(),
+            filename: filename.to_string(),
+            line,
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Create a diagnostic from a [FirrtlParseError].
+    pub fn from_parse_error(filename: impl ToString, e: &FirrtlParseError)
+        -> Self
+    {
+        let mut diag = Self::new(filename, e.line, e.span.clone(),
+            e.kind.message());
+        if let ParseErrorKind::UnexpectedEof = e.kind {
+            diag.add_note("the input ended before this statement was complete");
+        }
+        diag
+    }
+
+    /// Attach a note to this diagnostic.
+    pub fn add_note(&mut self, note: impl ToString) {
+        self.notes.push(note.to_string());
+    }
+
+    /// Render this diagnostic along with the offending line from `source`,
+    /// ie.
+    ///
+    /// ```text
+    /// error: Expected end of line
+    ///  --> Top.fir:3:23
+    ///   |
+    /// 3 |     input a : UInt<1> x
+    ///   |                       ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut res = format!("error: {}\n", self.message);
+
+        // Nothing to point at
+        let Some(line) = source.lines().nth(self.line.wrapping_sub(1)) else {
+            res.push_str(&format!(" --> {}\n", self.filename));
+            for note in &self.notes {
+                res.push_str(&format!(" = note: {}\n", note));
+            }
+            return res;
+        };
+
+        let gutter = self.line.to_string().len();
+        res.push_str(&format!("{:w$}--> {}:{}:{}\n", "",
+            self.filename, self.line, self.span.start, w=gutter));
+        res.push_str(&format!("{:w$} |\n", "", w=gutter));
+        res.push_str(&format!("{} | {}\n", self.line, line));
+
+        // Preserve any tabs so that the underline lines up with the source
+        let start = self.span.start.saturating_sub(1);
+        let padding: String = line.get(..start).unwrap_or(line).chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(self.span.len().max(1));
+        res.push_str(&format!("{:w$} | {}{}\n", "", padding, carets,
+            w=gutter));
+
+        for note in &self.notes {
+            res.push_str(&format!("{:w$} = note: {}\n", "", note, w=gutter));
+        }
+        res
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.filename, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.filename, self.line,
+                self.span.start, self.message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::FirrtlFile;
+
+    #[test]
+    fn render_snippet() {
+        let src = "circuit Top :\n  module Top :\n    input a : UInt<1> x\n";
+        let ff = FirrtlFile::from_str("Top.fir", src).unwrap();
+        let e = ff.parse().err().unwrap();
+        let diag = ff.diagnostic(&e);
+        assert_eq!(diag.line, 3);
+        assert_eq!(diag.render(&ff.raw_contents), "\
+error: Expected end of line
+ --> Top.fir:3:23
+  |
+3 |     input a : UInt<1> x
+  |                       ^
+");
+    }
+}
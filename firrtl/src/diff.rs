@@ -0,0 +1,228 @@
+//! Structural comparison between two FIRRTL circuits.
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// The first place where two circuits differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Path to the differing node, ie. `modules[Top].statements[3]`
+    pub path: String,
+    /// Debug representation of the node in the left-hand circuit
+    pub left: String,
+    /// Debug representation of the node in the right-hand circuit
+    pub right: String,
+}
+impl Difference {
+    fn new(path: impl ToString, left: impl fmt::Debug, right: impl fmt::Debug)
+        -> Self
+    {
+        Self {
+            path: path.to_string(),
+            left: format!("{:?}", left),
+            right: format!("{:?}", right),
+        }
+    }
+}
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}:\n  left:  {}\n  right: {}",
+            self.path, self.left, self.right)
+    }
+}
+
+/// Compare two leaf values, producing a [Difference] at `path`.
+fn leaf<T: PartialEq + fmt::Debug>(path: &str, a: &T, b: &T)
+    -> Option<Difference>
+{
+    if a == b { None } else { Some(Difference::new(path, a, b)) }
+}
+
+/// Compare two lists element-wise with `f`, naming each element with `name`.
+fn list<T: fmt::Debug>(path: &str, a: &[T], b: &[T],
+    name: impl Fn(usize, &T) -> String,
+    f: impl Fn(&str, &T, &T) -> Option<Difference>) -> Option<Difference>
+{
+    for (idx, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+        let elem_path = format!("{}[{}]", path, name(idx, x));
+        if let Some(d) = f(&elem_path, x, y) {
+            return Some(d);
+        }
+    }
+    if a.len() != b.len() {
+        let idx = a.len().min(b.len());
+        return Some(Difference::new(format!("{}[{}]", path, idx),
+            a.get(idx), b.get(idx)));
+    }
+    None
+}
+
+fn by_index<T>(idx: usize, _: &T) -> String {
+    idx.to_string()
+}
+
+fn diff_ports(path: &str, a: &[PortDecl], b: &[PortDecl])
+    -> Option<Difference>
+{
+    list(path, a, b, |_, p| p.id.clone(), leaf)
+}
+
+fn diff_statements(path: &str, a: &[Statement], b: &[Statement])
+    -> Option<Difference>
+{
+    list(path, a, b, by_index, diff_statement)
+}
+
+fn diff_statement(path: &str, a: &Statement, b: &Statement)
+    -> Option<Difference>
+{
+    // Descend into the bodies of 'when' statements
+    if let (StatementKind::When(c1, w1, e1), StatementKind::When(c2, w2, e2))
+        = (&a.kind, &b.kind)
+    {
+        return leaf(&format!("{}.cond", path), c1, c2)
+            .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+            .or_else(|| diff_statements(&format!("{}.when", path), w1, w2))
+            .or_else(|| diff_statements(&format!("{}.else", path), e1, e2));
+    }
+    // ... the bodies of 'layerblock' statements
+    if let (StatementKind::LayerBlock(l1, b1), StatementKind::LayerBlock(l2, b2))
+        = (&a.kind, &b.kind)
+    {
+        return leaf(&format!("{}.layer", path), l1, l2)
+            .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+            .or_else(|| diff_statements(&format!("{}.body", path), b1, b2));
+    }
+    // ... and into the arms of 'match' statements
+    if let (StatementKind::Match(e1, arms1), StatementKind::Match(e2, arms2))
+        = (&a.kind, &b.kind)
+    {
+        return leaf(&format!("{}.expr", path), e1, e2)
+            .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+            .or_else(|| list(&format!("{}.arms", path), arms1, arms2,
+                |_, arm| arm.variant.clone(), diff_arm));
+    }
+    leaf(&format!("{}.kind", path), &a.kind, &b.kind)
+        .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+}
+
+fn diff_arm(path: &str, a: &MatchArm, b: &MatchArm) -> Option<Difference> {
+    leaf(&format!("{}.variant", path), &a.variant, &b.variant)
+        .or_else(|| leaf(&format!("{}.binding", path), &a.binding, &b.binding))
+        .or_else(|| diff_statements(&format!("{}.body", path), &a.body, 
+            &b.body))
+}
+
+fn diff_module(path: &str, a: &Module, b: &Module) -> Option<Difference> {
+    leaf(&format!("{}.id", path), &a.id, &b.id)
+        .or_else(|| leaf(&format!("{}.public", path), &a.public, &b.public))
+        .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+        .or_else(|| leaf(&format!("{}.enabled_layers", path), 
+            &a.enabled_layers, &b.enabled_layers))
+        .or_else(|| diff_ports(&format!("{}.ports", path), &a.ports, &b.ports))
+        .or_else(|| diff_statements(&format!("{}.statements", path),
+            &a.statements, &b.statements))
+}
+
+fn diff_class(path: &str, a: &Class, b: &Class) -> Option<Difference> {
+    leaf(&format!("{}.id", path), &a.id, &b.id)
+        .or_else(|| leaf(&format!("{}.info", path), &a.info, &b.info))
+        .or_else(|| diff_ports(&format!("{}.ports", path), &a.ports, &b.ports))
+        .or_else(|| diff_statements(&format!("{}.statements", path),
+            &a.statements, &b.statements))
+}
+
+/// Find the first structural difference between two circuits (if any).
+///
+/// Source info is compared like any other field.
+pub fn diff_circuits(a: &Circuit, b: &Circuit) -> Option<Difference> {
+    leaf("id", &a.id, &b.id)
+        .or_else(|| leaf("version", &a.version, &b.version))
+        .or_else(|| leaf("info", &a.info, &b.info))
+        .or_else(|| list("layers", &a.layers, &b.layers,
+            |_, l| l.id.clone(), leaf))
+        .or_else(|| list("options", &a.options, &b.options,
+            |_, o| o.id.clone(), leaf))
+        .or_else(|| list("type_aliases", &a.type_aliases, &b.type_aliases,
+            |_, t| t.id.clone(), leaf))
+        .or_else(|| list("modules", &a.modules, &b.modules,
+            |_, m| m.id.clone(), diff_module))
+        .or_else(|| list("intmodules", &a.intmodules, &b.intmodules,
+            |_, m| m.id.clone(), leaf))
+        .or_else(|| list("extmodules", &a.extmodules, &b.extmodules,
+            |_, m| m.id.clone(), leaf))
+        .or_else(|| list("classes", &a.classes, &b.classes,
+            |_, c| c.id.clone(), diff_class))
+        .or_else(|| list("extclasses", &a.extclasses, &b.extclasses,
+            |_, c| c.id.clone(), leaf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+circuit Top :
+  module Top :
+    input a : UInt<1>
+    output b : UInt<1>
+    wire w : UInt<1>
+    when a :
+      connect w, a
+    else :
+      invalidate w
+    connect b, w
+";
+
+    #[test]
+    fn diff_equal() {
+        let a = parse(SAMPLE);
+        let b = a.clone();
+        assert_eq!(a, b);
+        assert!(a.diff(&b).is_none());
+    }
+
+    #[test]
+    fn diff_first_path() {
+        let a = parse(SAMPLE);
+        let b = parse(&SAMPLE.replace("invalidate w", "connect w, b"));
+        let d = diff_circuits(&a, &b).unwrap();
+        assert_eq!(d.path, "modules[Top].statements[1].else[0].kind");
+
+        let src = "\
+circuit Top :
+  module Top :
+    input e : {| A, B : UInt<1> |}
+    output b : UInt<1>
+    match e :
+      A :
+        connect b, UInt<1>(0)
+      B(x) :
+        connect b, x
+";
+        let d = diff_circuits(&parse(src), 
+            &parse(&src.replace("b, x", "b, UInt<1>(1)"))).unwrap();
+        assert_eq!(d.path, "modules[Top].statements[0].arms[B].body[0].kind");
+
+        let b = parse(&SAMPLE.replace("output b", "input b"));
+        let d = diff_circuits(&a, &b).unwrap();
+        assert_eq!(d.path, "modules[Top].ports[b]");
+
+        let b = parse(&SAMPLE.replace("    connect b, w\n", ""));
+        let d = diff_circuits(&a, &b).unwrap();
+        assert_eq!(d.path, "modules[Top].statements[2]");
+        assert_eq!(d.right, "None");
+    }
+
+    #[test]
+    fn hash_dedup() {
+        use std::collections::HashSet;
+        let a = parse(SAMPLE);
+        let stmts: HashSet<&Statement> = a.modules[0].statements.iter()
+            .chain(a.modules[0].statements.iter())
+            .collect();
+        assert_eq!(stmts.len(), a.modules[0].statements.len());
+    }
+}
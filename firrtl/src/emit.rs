@@ -0,0 +1,657 @@
+//! Writing FIRRTL text from the AST.
+//!
+//! The output of the [Emitter] is always expected to re-parse into an
+//! equivalent AST. For instance:
+//!
+//! ```ignore
+//! let circuit = FirrtlFile::from_file("Top.fir")?.parse()?;
+//! let text = firrtl::emit::emit_to_string(&circuit)?;
+//! ```
+//!
+//! The `FIRRTL version` header is only written when the version is known,
+//! either from the circuit itself or from [Emitter::with_version]. When
+//! writing for a version before 3.0.0, connects, invalidation and reset
+//! registers use the legacy syntax. Use [emit_for_version] to also check
+//! that nothing in the circuit is missing from the target version.
+
+use std::fmt;
+use std::io;
+
+use crate::ast::*;
+use crate::version::{ self, Feature, VersionError };
+
+/// Number of spaces used for each level of indentation.
+const INDENT_WIDTH: usize = 2;
+
+/// Writes FIRRTL for some part of an AST to any [fmt::Write].
+pub struct Emitter<W: fmt::Write> {
+    out: W,
+    /// The target version (if any)
+    version: Option<Version>,
+}
+impl<W: fmt::Write> Emitter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, version: None }
+    }
+
+    /// Write syntax for a particular version (instead of the version 
+    /// recorded in the circuit).
+    pub fn with_version(self, version: Version) -> Self {
+        Self { version: Some(version), ..self }
+    }
+
+    /// Get back the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Write some indentation (in levels, not spaces).
+    fn indent(&mut self, level: usize) -> fmt::Result {
+        write!(self.out, "{:idt$}", "", idt=level * INDENT_WIDTH)
+    }
+
+    /// Terminate a line, appending source info when present.
+    fn end_line(&mut self, info: &Option<SourceInfo>) -> fmt::Result {
+        if let Some(info) = info {
+            write!(self.out, " {}", info)?;
+        }
+        writeln!(self.out)
+    }
+
+    /// Returns 'true' when writing for a version without `connect`.
+    fn legacy(&self) -> bool {
+        self.version.is_some_and(|v| !Feature::Connect.supported(v))
+    }
+
+    /// Write a complete [Circuit], including the version header.
+    pub fn emit_circuit(&mut self, circuit: &Circuit) -> fmt::Result {
+        let target = self.version;
+        self.version = target.or(circuit.version);
+        let res = self.emit_circuit_inner(circuit);
+        self.version = target;
+        res
+    }
+
+    fn emit_circuit_inner(&mut self, circuit: &Circuit) -> fmt::Result {
+        if let Some(version) = self.version {
+            writeln!(self.out, "FIRRTL version {}", version)?;
+        }
+        write!(self.out, "circuit {} :", circuit.id)?;
+        self.end_line(&circuit.info)?;
+        for l in &circuit.layers {
+            self.emit_layer(l, 1)?;
+        }
+        for o in &circuit.options {
+            self.emit_option(o, 1)?;
+        }
+        // Aliases must be declared before they are used
+        for t in &circuit.type_aliases {
+            self.indent(1)?;
+            write!(self.out, "{}", t)?;
+            self.end_line(&t.info)?;
+        }
+        for m in &circuit.modules {
+            self.emit_module(m, 1)?;
+        }
+        for m in &circuit.intmodules {
+            self.emit_intmodule(m, 1)?;
+        }
+        for m in &circuit.extmodules {
+            self.emit_extmodule(m, 1)?;
+        }
+        for c in &circuit.classes {
+            self.emit_class(c, 1)?;
+        }
+        for c in &circuit.extclasses {
+            self.emit_extclass(c, 1)?;
+        }
+        Ok(())
+    }
+
+    /// Write a layer declaration (and any layers nested inside it).
+    pub fn emit_layer(&mut self, l: &Layer, level: usize) -> fmt::Result {
+        self.indent(level)?;
+        write!(self.out, "layer {}, {}", l.id, l.convention)?;
+        if let Some(dir) = &l.output_dir {
+            write!(self.out, ", \"{}\"", dir)?;
+        }
+        write!(self.out, " :")?;
+        self.end_line(&l.info)?;
+        for child in &l.layers {
+            self.emit_layer(child, level + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Write an option group declaration along with its cases.
+    pub fn emit_option(&mut self, o: &OptionGroup, level: usize)
+        -> fmt::Result
+    {
+        self.indent(level)?;
+        write!(self.out, "option {} :", o.id)?;
+        self.end_line(&o.info)?;
+        for case in &o.cases {
+            self.indent(level + 1)?;
+            writeln!(self.out, "{}", case)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_module(&mut self, m: &Module, level: usize) -> fmt::Result {
+        self.indent(level)?;
+        if m.public {
+            write!(self.out, "public ")?;
+        }
+        write!(self.out, "module {}", m.id)?;
+        for layer in &m.enabled_layers {
+            write!(self.out, " enablelayer {}", layer)?;
+        }
+        write!(self.out, " :")?;
+        self.end_line(&m.info)?;
+        for port in &m.ports {
+            self.emit_port(port, level + 1)?;
+        }
+        for s in &m.statements {
+            self.emit_statement(s, level + 1)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_intmodule(&mut self, m: &IntModule, level: usize)
+        -> fmt::Result
+    {
+        self.indent(level)?;
+        write!(self.out, "intmodule {} :", m.id)?;
+        self.end_line(&m.info)?;
+        for port in &m.ports {
+            self.emit_port(port, level + 1)?;
+        }
+        self.indent(level + 1)?;
+        writeln!(self.out, "intrinsic = {}", m.intrinsic)?;
+        self.emit_params(&m.params, level + 1)
+    }
+
+    pub fn emit_extmodule(&mut self, m: &ExtModule, level: usize)
+        -> fmt::Result
+    {
+        self.indent(level)?;
+        write!(self.out, "extmodule {} :", m.id)?;
+        self.end_line(&m.info)?;
+        for port in &m.ports {
+            self.emit_port(port, level + 1)?;
+        }
+        if let Some(defname) = &m.defname {
+            self.indent(level + 1)?;
+            writeln!(self.out, "defname = {}", defname)?;
+        }
+        self.emit_params(&m.params, level + 1)?;
+        for r in &m.refs {
+            self.indent(level + 1)?;
+            writeln!(self.out, "{}", r)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_class(&mut self, c: &Class, level: usize) -> fmt::Result {
+        self.indent(level)?;
+        write!(self.out, "class {} :", c.id)?;
+        self.end_line(&c.info)?;
+        for port in &c.ports {
+            self.emit_port(port, level + 1)?;
+        }
+        for s in &c.statements {
+            self.emit_statement(s, level + 1)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_extclass(&mut self, c: &ExtClass, level: usize)
+        -> fmt::Result
+    {
+        self.indent(level)?;
+        write!(self.out, "extclass {} :", c.id)?;
+        self.end_line(&c.info)?;
+        for port in &c.ports {
+            self.emit_port(port, level + 1)?;
+        }
+        Ok(())
+    }
+
+    fn emit_params(&mut self, params: &[Parameter], level: usize) 
+        -> fmt::Result
+    {
+        for param in params {
+            self.indent(level)?;
+            writeln!(self.out, "{}", param)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_port(&mut self, port: &PortDecl, level: usize) -> fmt::Result {
+        self.indent(level)?;
+        write!(self.out, "{}", port)?;
+        self.end_line(&port.info)
+    }
+
+    /// Write a block of statements.
+    ///
+    /// NOTE: An empty block is written as `skip`, since FIRRTL has no way
+    /// of expressing an empty indented block.
+    fn emit_block(&mut self, block: &[Statement], level: usize) -> fmt::Result {
+        if block.is_empty() {
+            self.indent(level)?;
+            return writeln!(self.out, "skip");
+        }
+        for s in block {
+            self.emit_statement(s, level)?;
+        }
+        Ok(())
+    }
+
+    pub fn emit_statement(&mut self, s: &Statement, level: usize)
+        -> fmt::Result
+    {
+        self.indent(level)?;
+        self.emit_statement_kind(&s.kind, &s.info, level)
+    }
+
+    /// Write a statement, assuming that we're already indented.
+    fn emit_statement_kind(&mut self, kind: &StatementKind,
+                           info: &Option<SourceInfo>, level: usize)
+        -> fmt::Result
+    {
+        match kind {
+            StatementKind::Wire(id, ty) => {
+                write!(self.out, "wire {} : {}", id, ty)?;
+            },
+            StatementKind::Reg(id, ty, clkexpr) => {
+                write!(self.out, "reg {} : {}, {}", id, ty, clkexpr)?;
+            },
+            StatementKind::RegReset(decl) if self.legacy() => {
+                write!(self.out, "reg {} : {}, {} with : (reset => ({}, {}))",
+                       decl.id, decl.ty, decl.clock, decl.reset, decl.init)?;
+            },
+            StatementKind::RegReset(decl) => {
+                write!(self.out, "regreset {} : {}, {}, {}, {}", decl.id, 
+                       decl.ty, decl.clock, decl.reset, decl.init)?;
+            },
+            StatementKind::Inst(id, mid) => {
+                write!(self.out, "inst {} of {}", id, mid)?;
+            },
+            StatementKind::InstChoice(choice) => {
+                write!(self.out, "instchoice {} of {}, {} :", choice.id, 
+                       choice.default, choice.option)?;
+                self.end_line(info)?;
+                for (case, module) in &choice.cases {
+                    self.indent(level + 1)?;
+                    writeln!(self.out, "{} => {}", case, module)?;
+                }
+                return Ok(());
+            },
+            StatementKind::Object(id, class) => {
+                write!(self.out, "object {} of {}", id, class)?;
+            },
+            StatementKind::Mem(decl) => {
+                write!(self.out, "mem {} :", decl.id)?;
+                self.end_line(info)?;
+                return self.emit_mem(decl, level + 1);
+            },
+            StatementKind::Node(id, expr) => {
+                write!(self.out, "node {} = {}", id, expr)?;
+            },
+            StatementKind::ChirrtlMem(decl) => {
+                let kw = match decl.kind {
+                    ChirrtlMemKind::Comb => "cmem",
+                    ChirrtlMemKind::Seq(_) => "smem",
+                };
+                write!(self.out, "{} {} : {}[{}]", kw, decl.id, decl.ty, 
+                       decl.depth)?;
+                if let ChirrtlMemKind::Seq(Some(ruw)) = decl.kind {
+                    write!(self.out, ", {}", ruw)?;
+                }
+            },
+            StatementKind::MemPort(decl) => {
+                write!(self.out, "{} mport {} = {}[{}], {}", decl.dir, 
+                       decl.id, decl.mem, decl.addr, decl.clock)?;
+            },
+            StatementKind::Attach(refs) => {
+                write!(self.out, "attach(")?;
+                self.emit_list(refs)?;
+                write!(self.out, ")")?;
+            },
+            StatementKind::PartialConnect(r, e) => {
+                write!(self.out, "{} <- {}", r, e)?;
+            },
+            StatementKind::Connect(r, e) if self.legacy() => {
+                write!(self.out, "{} <= {}", r, e)?;
+            },
+            StatementKind::Connect(r, e) => {
+                write!(self.out, "connect {}, {}", r, e)?;
+            },
+            StatementKind::PropAssign(r, e) => {
+                write!(self.out, "propassign {}, {}", r, e)?;
+            },
+            StatementKind::Invalidate(r) if self.legacy() => {
+                write!(self.out, "{} is invalid", r)?;
+            },
+            StatementKind::Invalidate(r) => {
+                write!(self.out, "invalidate {}", r)?;
+            },
+            StatementKind::When(condexpr, wblk, eblk) => {
+                write!(self.out, "when {} :", condexpr)?;
+                self.end_line(info)?;
+                self.emit_block(wblk, level + 1)?;
+                return self.emit_else(eblk, level);
+            },
+            StatementKind::LayerBlock(layer, blk) => {
+                write!(self.out, "layerblock {} :", layer)?;
+                self.end_line(info)?;
+                return self.emit_block(blk, level + 1);
+            },
+            StatementKind::Match(expr, arms) => {
+                write!(self.out, "match {} :", expr)?;
+                self.end_line(info)?;
+                for arm in arms {
+                    self.indent(level + 1)?;
+                    write!(self.out, "{}", arm.variant)?;
+                    if let Some(binding) = &arm.binding {
+                        write!(self.out, "({})", binding)?;
+                    }
+                    writeln!(self.out, " :")?;
+                    self.emit_block(&arm.body, level + 2)?;
+                }
+                return Ok(());
+            },
+            StatementKind::Stop(e1, e2, val, name) => {
+                write!(self.out, "stop({}, {}, {})", e1, e2, val)?;
+                self.emit_stmt_name(name)?;
+            },
+            StatementKind::Force(e1, e2, re, e3) => {
+                write!(self.out, "force({}, {}, {}, {})", e1, e2, re, e3)?;
+            },
+            StatementKind::Release(e1, e2, re) => {
+                write!(self.out, "release({}, {}, {})", e1, e2, re)?;
+            },
+            StatementKind::ForceInitial(re, e) => {
+                write!(self.out, "force_initial({}, {})", re, e)?;
+            },
+            StatementKind::ReleaseInitial(re) => {
+                write!(self.out, "release_initial({})", re)?;
+            },
+            StatementKind::Define(sr, re) => {
+                write!(self.out, "define {} = {}", sr, re)?;
+            },
+            StatementKind::Printf(p) => {
+                write!(self.out, "printf({}, {}, {}", p.clock, p.en, p.fmt)?;
+                for arg in &p.args {
+                    write!(self.out, ", {}", arg)?;
+                }
+                write!(self.out, ")")?;
+                self.emit_stmt_name(&p.name)?;
+            },
+            StatementKind::Assert(v) => self.emit_verification("assert", v)?,
+            StatementKind::Assume(v) => self.emit_verification("assume", v)?,
+            StatementKind::Cover(v) => self.emit_verification("cover", v)?,
+            StatementKind::Intrinsic(intr) => write!(self.out, "{}", intr)?,
+            StatementKind::Skip => {
+                write!(self.out, "skip")?;
+            },
+        }
+        self.end_line(info)
+    }
+
+    /// Write the 'else' part of a 'when' statement (if any).
+    ///
+    /// An 'else' block that only contains another 'when' is written as
+    /// `else when ...`.
+    fn emit_else(&mut self, eblk: &[Statement], level: usize) -> fmt::Result {
+        match eblk {
+            [] => Ok(()),
+            [s] if matches!(s.kind, StatementKind::When(..)) => {
+                self.indent(level)?;
+                write!(self.out, "else ")?;
+                self.emit_statement_kind(&s.kind, &s.info, level)
+            },
+            _ => {
+                self.indent(level)?;
+                writeln!(self.out, "else :")?;
+                self.emit_block(eblk, level + 1)
+            },
+        }
+    }
+
+    /// Write an `assert`, `assume` or `cover` statement.
+    fn emit_verification(&mut self, kw: &str, v: &Verification) 
+        -> fmt::Result 
+    {
+        write!(self.out, "{}({}, {}, {}, {}", kw, v.clock, v.pred, v.en, 
+               v.msg)?;
+        for arg in &v.args {
+            write!(self.out, ", {}", arg)?;
+        }
+        write!(self.out, ")")?;
+        self.emit_stmt_name(&v.name)
+    }
+
+    /// Write the optional label (`: <name>`) for a statement.
+    fn emit_stmt_name(&mut self, name: &Option<String>) -> fmt::Result {
+        if let Some(name) = name {
+            write!(self.out, " : {}", name)?;
+        }
+        Ok(())
+    }
+
+    /// Write the body of a memory declaration.
+    fn emit_mem(&mut self, decl: &MemDecl, level: usize) -> fmt::Result {
+        self.indent(level)?;
+        writeln!(self.out, "data-type => {}", decl.ty)?;
+        self.indent(level)?;
+        writeln!(self.out, "depth => {}", decl.depth)?;
+        self.indent(level)?;
+        writeln!(self.out, "read-latency => {}", decl.read_latency)?;
+        self.indent(level)?;
+        writeln!(self.out, "write-latency => {}", decl.write_latency)?;
+        self.indent(level)?;
+        writeln!(self.out, "read-under-write => {}", decl.read_under_write)?;
+        for rp in &decl.rp_list {
+            self.indent(level)?;
+            writeln!(self.out, "reader => {}", rp)?;
+        }
+        for wp in &decl.wp_list {
+            self.indent(level)?;
+            writeln!(self.out, "writer => {}", wp)?;
+        }
+        for rwp in &decl.rwp_list {
+            self.indent(level)?;
+            writeln!(self.out, "readwriter => {}", rwp)?;
+        }
+        Ok(())
+    }
+
+    /// Write a comma-separated list.
+    fn emit_list<T: fmt::Display>(&mut self, items: &[T]) -> fmt::Result {
+        for (idx, item) in items.iter().enumerate() {
+            if idx != 0 {
+                write!(self.out, ", ")?;
+            }
+            write!(self.out, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adapter for using an [io::Write] as a [fmt::Write].
+struct IoAdapter<W: io::Write> {
+    inner: W,
+    /// The last I/O error (if any)
+    error: Option<io::Error>,
+}
+impl<W: io::Write> fmt::Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Emit FIRRTL for a [Circuit] into a new [String].
+pub fn emit_to_string(circuit: &Circuit) -> Result<String, fmt::Error> {
+    let mut emitter = Emitter::new(String::new());
+    emitter.emit_circuit(circuit)?;
+    Ok(emitter.into_inner())
+}
+
+/// Emit FIRRTL for a [Circuit] into a new [String], using the syntax for 
+/// a particular version.
+///
+/// Fails if the circuit uses something that isn't part of `version`.
+pub fn emit_for_version(circuit: &Circuit, version: Version)
+    -> Result<String, VersionError>
+{
+    version::check_circuit(circuit, version)?;
+    let mut emitter = Emitter::new(String::new()).with_version(version);
+    emitter.emit_circuit(circuit)
+        .expect("formatting into a String doesn't fail");
+    Ok(emitter.into_inner())
+}
+
+/// Emit FIRRTL for a [Circuit] to some [io::Write].
+pub fn emit_io<W: io::Write>(circuit: &Circuit, out: W) -> io::Result<()> {
+    let mut emitter = Emitter::new(IoAdapter { inner: out, error: None });
+    let res = emitter.emit_circuit(circuit);
+    let adapter = emitter.into_inner();
+    match (res, adapter.error) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(e)) => Err(e),
+        (Err(_), None) => Err(io::Error::new(io::ErrorKind::InvalidData,
+            "circuit cannot be formatted as FIRRTL")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+FIRRTL version 3.0.0
+circuit Top : @[Top.scala 1:1]
+  module Top : @[Top.scala 2:3]
+    input clock : Clock
+    input reset : UInt<1>
+    input a : { flip x : UInt<8>, y : SInt<4>[2] } @[Top.scala 3:4]
+    output b : UInt
+    output c : Probe<UInt<8>>
+    wire w : UInt<8>
+    regreset r : UInt<8>, clock, reset, UInt<8>(0) @[Top.scala 5:6]
+    reg r2 : UInt<8>, clock
+    inst sub of Sub
+    mem m : @[Top.scala 7:8]
+      data-type => UInt<8>
+      depth => 16
+      read-latency => 0
+      write-latency => 1
+      read-under-write => undefined
+      reader => r0
+      writer => w0
+    node n = add(a.x, UInt<8>(3))
+    when eq(n, UInt(0)) : @[Top.scala 9:10]
+      connect w, n
+    else when reset :
+      invalidate w
+    else :
+      connect w, mux(reset, bits(n, 3, 0), shl(n, 2))
+      skip
+    connect b, w
+    printf(clock, reset, \"hello %d\\n\", w, r) @[Top.scala 11:12]
+    stop(clock, reset, 1)
+    define c = probe(w)
+  intmodule Plus :
+    output out : UInt<8>
+    intrinsic = circt_plus
+    parameter FORMAT = \"%d\"
+  extmodule Sub :
+    input in : UInt<8>
+    output p : Probe<UInt<8>>
+    defname = SubImpl
+    parameter WIDTH = 8
+    parameter OFFSET = -3
+    parameter BIAS = +3
+    parameter SCALE = 0.5
+    parameter PATH = 'a\\b'
+    ref p is \"ram.mem\"
+";
+
+    fn roundtrip(src: &str) -> String {
+        emit_to_string(&parse(src)).unwrap()
+    }
+
+    #[test]
+    fn emit_roundtrip() {
+        let first = roundtrip(SAMPLE);
+        assert_eq!(first, SAMPLE);
+        assert_eq!(roundtrip(&first), first);
+    }
+
+    #[test]
+    fn emit_single_line_when() {
+        let src = "circuit Top :\n  module Top :\n    input a : UInt<1>\n    \
+                   wire w : UInt<1>\n    \
+                   when a : connect w, a else : invalidate w\n";
+        let out = roundtrip(src);
+        assert!(out.contains("    when a :\n      connect w, a\n    else :\n      invalidate w\n"));
+        assert_eq!(roundtrip(&out), out);
+    }
+
+    #[test]
+    fn emit_legacy_reset() {
+        let src = "circuit Top :\n  module Top :\n    input clock : Clock\n    \
+                   input rst : UInt<1>\n    \
+                   reg r : UInt<8>, clock with : (reset => (rst, UInt(0)))\n";
+        let out = roundtrip(src);
+        assert!(out.contains("regreset r : UInt<8>, clock, rst, UInt(0)\n"));
+        assert_eq!(roundtrip(&out), out);
+    }
+
+    #[test]
+    fn emit_versions() {
+        let src = "circuit Top :\n  module Top :\n    input clock : Clock\n    \
+                   input rst : UInt<1>\n    output o : UInt<8>\n    \
+                   regreset r : UInt<8>, clock, rst, UInt(0)\n    \
+                   invalidate o\n    connect o, r\n";
+        let circuit = parse(src);
+        assert!(!emit_to_string(&circuit).unwrap().contains("FIRRTL"));
+
+        let old = emit_for_version(&circuit, Version::new(2, 0, 0)).unwrap();
+        assert!(old.starts_with("FIRRTL version 2.0.0\n"), "{}", old);
+        assert!(old.contains(
+            "    reg r : UInt<8>, clock with : (reset => (rst, UInt(0)))\n"
+            ), "{}", old);
+        assert!(old.contains("    o is invalid\n    o <= r\n"), "{}", old);
+
+        // The version is kept, so it's written back out the same way
+        let reparsed = parse(&old);
+        assert_eq!(reparsed.version, Some(Version::new(2, 0, 0)));
+        assert_eq!(emit_to_string(&reparsed).unwrap(), old);
+
+        let new = emit_for_version(&circuit, version::LATEST).unwrap();
+        assert!(new.contains("    invalidate o\n    connect o, r\n"));
+
+        let mut circuit = circuit;
+        circuit.modules[0].public = true;
+        let err = emit_for_version(&circuit, Version::new(3, 0, 0));
+        assert_eq!(err, Err(VersionError {
+            feature: Feature::PublicModules,
+            version: Version::new(3, 0, 0),
+        }));
+    }
+
+    #[test]
+    fn emit_to_io() {
+        let circuit = parse(SAMPLE);
+        let mut buf: Vec<u8> = Vec::new();
+        emit_io(&circuit, &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), SAMPLE);
+    }
+}
@@ -0,0 +1,183 @@
+//! Finding named simulation and verification events.
+//!
+//! Statements like `printf`, `stop`, `assert`, `assume` and `cover` can
+//! have a label (ie. `cover(clk, a, en, "") : a_seen`). Tools use these
+//! names to identify events in simulation logs and coverage reports, along
+//! with the path through the instance hierarchy to the statement.
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// The kind of statement for a [NamedEvent].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Printf,
+    Stop,
+    Assert,
+    Assume,
+    Cover,
+}
+impl EventKind {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Printf => "printf",
+            Self::Stop => "stop",
+            Self::Assert => "assert",
+            Self::Assume => "assume",
+            Self::Cover => "cover",
+        }
+    }
+}
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A labelled statement somewhere in the instance hierarchy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NamedEvent {
+    /// Hierarchical path to the event (ie. `Top.sub.a_seen`)
+    pub path: String,
+    /// The module containing the statement
+    pub module: String,
+    pub kind: EventKind,
+    pub info: Option<SourceInfo>,
+}
+impl NamedEvent {
+    /// The label on the statement.
+    pub fn name(&self) -> &str {
+        self.path.rsplit('.').next().unwrap_or(&self.path)
+    }
+}
+
+/// List the named events in every instance below the top module of
+/// `circuit` (in the order that they appear).
+///
+/// An event in a module that is instantiated more than once appears once
+/// for each instance. Each `instchoice` is followed to its default module
+/// (use [crate::pass::specialize] to choose some other case first).
+pub fn named_events(circuit: &Circuit) -> Vec<NamedEvent> {
+    let mut res = Vec::new();
+    if let Some(top) = circuit.top_module() {
+        let mut stack = vec![top.id.as_str()];
+        visit_block(circuit, &top.id, &top.id, &top.statements, &mut stack,
+            &mut res);
+    }
+    res
+}
+
+/// Collect the events in `stmts` (in `module`, at hierarchical `path`).
+///
+/// `stack` holds the modules being visited, so that (illegal) recursive
+/// instances don't recurse forever here.
+fn visit_block<'c>(circuit: &'c Circuit, module: &str, path: &str,
+    stmts: &'c [Statement], stack: &mut Vec<&'c str>,
+    res: &mut Vec<NamedEvent>)
+{
+    for stmt in stmts {
+        let (kind, name) = match &stmt.kind {
+            StatementKind::Printf(p) => (EventKind::Printf, &p.name),
+            StatementKind::Stop(.., name) => (EventKind::Stop, name),
+            StatementKind::Assert(v) => (EventKind::Assert, &v.name),
+            StatementKind::Assume(v) => (EventKind::Assume, &v.name),
+            StatementKind::Cover(v) => (EventKind::Cover, &v.name),
+            StatementKind::Inst(id, mid) => {
+                visit_instance(circuit, path, id, mid, stack, res);
+                continue;
+            },
+            StatementKind::InstChoice(choice) => {
+                visit_instance(circuit, path, &choice.id, &choice.default,
+                    stack, res);
+                continue;
+            },
+            StatementKind::When(_, when_blk, else_blk) => {
+                visit_block(circuit, module, path, when_blk, stack, res);
+                visit_block(circuit, module, path, else_blk, stack, res);
+                continue;
+            },
+            StatementKind::Match(_, arms) => {
+                for arm in arms {
+                    visit_block(circuit, module, path, &arm.body, stack, res);
+                }
+                continue;
+            },
+            StatementKind::LayerBlock(_, blk) => {
+                visit_block(circuit, module, path, blk, stack, res);
+                continue;
+            },
+            _ => continue,
+        };
+        let Some(name) = name else { continue };
+        res.push(NamedEvent {
+            path: format!("{}.{}", path, name),
+            module: module.to_string(),
+            kind,
+            info: stmt.info.clone(),
+        });
+    }
+}
+
+fn visit_instance<'c>(circuit: &'c Circuit, path: &str, id: &str, mid: &str,
+    stack: &mut Vec<&'c str>, res: &mut Vec<NamedEvent>)
+{
+    // External and intrinsic modules don't have any statements
+    let Some(m) = circuit.modules.iter().find(|m| m.id == mid) else {
+        return;
+    };
+    if stack.contains(&m.id.as_str()) {
+        return;
+    }
+    stack.push(&m.id);
+    let path = format!("{}.{}", path, id);
+    visit_block(circuit, &m.id, &path, &m.statements, stack, res);
+    stack.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn hierarchical_names() {
+        let src = "\
+circuit Top :
+  option Platform :
+    FPGA
+  module Top :
+    input clock : Clock
+    input a : UInt<1>
+    inst x of Leaf
+    inst y of Leaf
+    when a :
+      printf(clock, a, \"a\\n\") : saw_a
+    stop(clock, a, 0)
+    instchoice z of Mid, Platform :
+      FPGA => Leaf
+  module Mid :
+    input clock : Clock
+    inst leaf of Leaf
+    stop(clock, UInt<1>(1), 1) : done @[Mid.scala 4:2]
+  module Leaf :
+    input clock : Clock
+    layerblock Verif :
+      assert(clock, UInt<1>(1), UInt<1>(1), \"\") : ok
+    cover(clock, UInt<1>(1), UInt<1>(1), \"\")
+  layer Verif, bind :
+";
+        let circuit = parse(src);
+        let events = circuit.named_events();
+        let paths: Vec<_> = events.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, [
+            "Top.x.ok", "Top.y.ok", "Top.saw_a", "Top.z.leaf.ok", "Top.z.done",
+        ]);
+        assert_eq!(events[0].kind, EventKind::Assert);
+        assert_eq!(events[0].module, "Leaf");
+        assert_eq!(events[2].kind, EventKind::Printf);
+        assert_eq!(events[4].name(), "done");
+        assert_eq!(events[4].module, "Mid");
+        assert!(events[4].info.is_some());
+    }
+}
@@ -6,6 +6,7 @@ use crate::lex;
 use crate::token;
 use crate::parse;
 use crate::ast;
+use crate::diagnostic::Diagnostic;
 
 /// Container for input from a FIRRTL (.fir) source file.
 pub struct FirrtlFile {
@@ -59,7 +60,7 @@ impl FirrtlFile {
 
     /// Tokenize a set of [FirrtlLine] into a list of [FirrtlTokenizedLine].
     fn tokenize_lines(lines: &[FirrtlLine]) 
-        -> Vec<lex::FirrtlTokenizedLine> 
+        -> Result<Vec<lex::FirrtlTokenizedLine>, lex::FirrtlParseError>
     {
         use logos::Logos;
         let mut tokenized_lines = Vec::new();
@@ -71,7 +72,9 @@ impl FirrtlFile {
 
             // FIRRTL "file info" optionally comes at the end of a line. 
             // Separate meaningful line content from any file info.
-            let (content, info) = if let Some(idx) = sfl.contents().find('@') {
+            let info_idx = sfl.contents().rfind("@[")
+                .filter(|_| sfl.contents().trim_end().ends_with(']'));
+            let (content, info) = if let Some(idx) = info_idx {
                 (&sfl.contents()[..idx], Some(sfl.contents()[idx..].to_string()))
             } else {
                 (sfl.contents(), None)
@@ -92,49 +95,59 @@ impl FirrtlFile {
                         spans.push(token_span);
                     },
                     // Some error occured while tokenizing this line.
-                    // FIXME: Proper error-handling instead of panic!()
-                    Err(e) => {
-                        println!("{:?}", e);
-                        panic!("unknown token at line {}, offset {:?}",
-                               sf_line, token_span);
+                    Err(_) => {
+                        return Err(lex::FirrtlParseError {
+                            kind: lex::ParseErrorKind::Lexical(
+                                lexer.slice().to_string()
+                            ),
+                            line: sf_line,
+                            span: token_span,
+                        });
                     },
                 }
             }
+            // Lines without any tokens (ie. only file info or separators)
+            // don't contribute anything to the stream
+            if tokens.is_empty() {
+                continue;
+            }
             let tokenized_line = lex::FirrtlTokenizedLine {
                 tokens, spans, sf_line, info, indent_level,
                 content: content.to_string(),
             };
             tokenized_lines.push(tokenized_line);
         }
-        tokenized_lines
+        Ok(tokenized_lines)
     }
 }
 
 /// This is the public interface to a [FirrtlFile]. 
 impl FirrtlFile {
     /// Import FIRRTL from some file
-    pub fn from_file(filename: &str) -> Self { 
+    pub fn from_file(filename: &str) -> Result<Self, lex::FirrtlParseError> { 
         use std::fs::File;
         use std::io::Read;
-        let mut f = File::open(filename).unwrap();
+        let mut f = File::open(filename)?;
         let mut s = String::new();
-        f.read_to_string(&mut s).unwrap();
+        f.read_to_string(&mut s)?;
         Self::from_str(filename, &s)
     }
 
     /// Import FIRRTL from a string. 
-    pub fn from_str(filename: &str, contents: &str) -> Self {
+    pub fn from_str(filename: &str, contents: &str) 
+        -> Result<Self, lex::FirrtlParseError> 
+    {
         // Preprocess into a set of [FirrtlLine]
         let raw_lines = Self::read_lines(contents);
 
         // Produce a set of [FirrtlTokenizedLine]
-        let lines = Self::tokenize_lines(&raw_lines);
+        let lines = Self::tokenize_lines(&raw_lines)?;
 
-        Self { 
+        Ok(Self { 
             raw_contents: contents.to_string(),
             filename: filename.to_string(),
             lines
-        }
+        })
     }
 
     /// Convert this [FirrtlFile] into the corresponding [Circuit].
@@ -144,7 +157,28 @@ impl FirrtlFile {
         Ok(circuit)
     }
 
+    /// Convert this [FirrtlFile] into the corresponding [Circuit], reporting
+    /// every error instead of stopping at the first one. 
+    ///
+    /// The resulting [Circuit] omits anything that could not be parsed.
+    pub fn parse_recovering(&self) -> (Option<ast::Circuit>, Vec<Diagnostic>) {
+        let mut stream = lex::FirrtlStream::new(self);
+        let (circuit, errors) = parse::FirrtlParser::parse_recovering(
+            &mut stream
+        );
+        let diags = errors.iter().map(|e| self.diagnostic(e)).collect();
+        (circuit, diags)
+    }
 
+    /// Create a [Diagnostic] for some error that occured in this file.
+    pub fn diagnostic(&self, e: &lex::FirrtlParseError) -> Diagnostic {
+        Diagnostic::from_parse_error(&self.filename, e)
+    }
+
+    /// Render an error along with the offending line from this file.
+    pub fn render_error(&self, e: &lex::FirrtlParseError) -> String {
+        self.diagnostic(e).render(&self.raw_contents)
+    }
 }
 
 /// A suitably-trimmed "line" (without comments).
@@ -0,0 +1,182 @@
+//! Plugging in implementations for intrinsics.
+//!
+//! FIRRTL doesn't say anything about what a particular intrinsic does;
+//! that's left up to whatever tool consumes the circuit. Tools describe
+//! the intrinsics they understand with an [IntrinsicRegistry], which maps
+//! the name of an intrinsic to an [IntrinsicImpl].
+//!
+//! ```
+//! use firrtl::ast::*;
+//! use firrtl::intrinsic::*;
+//!
+//! let mut intrinsics = Intrinsics::new();
+//! intrinsics.register("circt_plusargs_test",
+//!     |_: &Intrinsic, _: &[FirrtlType]| {
+//!         Ok(Some(FirrtlType::Ground(FirrtlTypeGround::UInt(Some(1)))))
+//!     }
+//! );
+//! assert!(intrinsics.lookup("circt_plusargs_test").is_some());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ast::*;
+
+/// An implementation of some intrinsic.
+pub trait IntrinsicImpl {
+    /// Get the result type for a use of this intrinsic, given the types of
+    /// its operands.
+    ///
+    /// Returns `None` for an intrinsic without a result, or an error
+    /// message if the parameters/operands are invalid.
+    fn result_type(&self, intr: &Intrinsic, args: &[FirrtlType])
+        -> Result<Option<FirrtlType>, String>;
+}
+impl <F> IntrinsicImpl for F
+    where F: Fn(&Intrinsic, &[FirrtlType])
+        -> Result<Option<FirrtlType>, String>
+{
+    fn result_type(&self, intr: &Intrinsic, args: &[FirrtlType])
+        -> Result<Option<FirrtlType>, String>
+    {
+        self(intr, args)
+    }
+}
+
+/// An error encountered while checking a use of an intrinsic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntrinsicError {
+    /// There is no implementation for the intrinsic with this name
+    Unknown(String),
+    /// The implementation rejected this use of the intrinsic
+    Invalid(String, String),
+}
+impl fmt::Display for IntrinsicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Unknown(name) => write!(f, "Unknown intrinsic '{}'", name),
+            Self::Invalid(name, msg) => {
+                write!(f, "Invalid use of intrinsic '{}': {}", name, msg)
+            },
+        }
+    }
+}
+impl std::error::Error for IntrinsicError {}
+
+/// A set of intrinsic implementations, looked up by name.
+pub trait IntrinsicRegistry {
+    /// Get the implementation for the intrinsic called `name`.
+    fn lookup(&self, name: &str) -> Option<&dyn IntrinsicImpl>;
+
+    /// Check a use of an intrinsic against its implementation, returning
+    /// the type of the result (if any).
+    ///
+    /// When `intr` declares a result type, the implementation must agree
+    /// with it.
+    fn check(&self, intr: &Intrinsic, args: &[FirrtlType])
+        -> Result<Option<FirrtlType>, IntrinsicError>
+    {
+        let invalid = |msg: String| {
+            IntrinsicError::Invalid(intr.name.clone(), msg)
+        };
+        let imp = self.lookup(&intr.name)
+            .ok_or_else(|| IntrinsicError::Unknown(intr.name.clone()))?;
+        let ty = imp.result_type(intr, args).map_err(invalid)?;
+        match (&intr.ty, ty) {
+            (Some(decl), Some(ty)) if *decl != ty => Err(invalid(format!(
+                "result type {} doesn't match declared type {}", ty, decl
+            ))),
+            (Some(_), None) => Err(invalid("no result".to_string())),
+            (Some(decl), _) => Ok(Some(decl.clone())),
+            (None, ty) => Ok(ty),
+        }
+    }
+}
+
+/// An [IntrinsicRegistry] built up by registering implementations.
+#[derive(Default)]
+pub struct Intrinsics {
+    impls: BTreeMap<String, Box<dyn IntrinsicImpl>>,
+}
+impl Intrinsics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the implementation for the intrinsic called `name` (replacing
+    /// any existing implementation).
+    pub fn register(&mut self, name: impl ToString,
+        imp: impl IntrinsicImpl + 'static)
+    {
+        self.impls.insert(name.to_string(), Box::new(imp));
+    }
+
+    /// Names of all registered intrinsics.
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.impls.keys().map(String::as_str)
+    }
+}
+impl IntrinsicRegistry for Intrinsics {
+    fn lookup(&self, name: &str) -> Option<&dyn IntrinsicImpl> {
+        self.impls.get(name).map(|imp| imp.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uint(width: usize) -> FirrtlType {
+        FirrtlType::Ground(FirrtlTypeGround::UInt(Some(width)))
+    }
+
+    /// `sizeof<BITS = n>` produces a `UInt<n>`; `trace` has no result.
+    fn registry() -> Intrinsics {
+        let mut res = Intrinsics::new();
+        res.register("sizeof", |intr: &Intrinsic, _: &[FirrtlType]| {
+            match intr.param("BITS") {
+                Some(ParamValue::Int(n)) => {
+                    let n = usize::try_from(n).map_err(|e| e.to_string())?;
+                    Ok(Some(uint(n)))
+                },
+                _ => Err("expected an integer 'BITS'".to_string()),
+            }
+        });
+        res.register("trace", |_: &Intrinsic, args: &[FirrtlType]| {
+            if args.len() == 1 {
+                Ok(None)
+            } else {
+                Err(format!("expected 1 operand, got {}", args.len()))
+            }
+        });
+        res
+    }
+
+    #[test]
+    fn check() {
+        let reg = registry();
+        assert_eq!(reg.names().collect::<Vec<_>>(), ["sizeof", "trace"]);
+
+        let mut intr = Intrinsic::new("sizeof", Some(uint(4)), Vec::new());
+        let bits = ParamValue::Int(4u32.into());
+        intr.params.push(Parameter::new("BITS", bits));
+        assert_eq!(reg.check(&intr, &[]), Ok(Some(uint(4))));
+
+        intr.ty = Some(uint(8));
+        assert!(matches!(reg.check(&intr, &[]),
+            Err(IntrinsicError::Invalid(..))));
+        intr.params.clear();
+        assert_eq!(reg.check(&intr, &[]), Err(IntrinsicError::Invalid(
+            "sizeof".to_string(), "expected an integer 'BITS'".to_string()
+        )));
+
+        let intr = Intrinsic::new("trace", None, vec![Expr::None]);
+        assert_eq!(reg.check(&intr, &[uint(1)]), Ok(None));
+        assert!(reg.check(&intr, &[]).is_err());
+
+        let intr = Intrinsic::new("nope", None, Vec::new());
+        assert_eq!(reg.check(&intr, &[]),
+            Err(IntrinsicError::Unknown("nope".to_string())));
+    }
+}
@@ -1,11 +1,14 @@
 //! FIRRTL lexing/tokenization
 
 use std::ops::Range;
-use std::collections::BTreeSet;
+use std::fmt;
+use std::collections::{ BTreeMap, BTreeSet };
 use logos::Logos;
 
 use crate::file::*;
 use crate::token::*;
+use crate::ast::{ FirrtlType, LiteralNumeric, SourceInfo, Version };
+use crate::version::{ Feature, VersionError };
 
 
 /// A fully-tokenized FIRRTL line, corresponding to a single [FirrtlLine] 
@@ -56,6 +59,28 @@ pub enum ParseErrorKind {
     ExpectedToken(String),
     ExpectedKeyword(String),
     ExpectedPunctuation(String),
+    /// Some text on a line could not be converted into a [Token]
+    Lexical(String),
+    /// The input could not be read
+    Io(std::io::Error),
+    /// The stream ended while more input was expected
+    UnexpectedEof,
+    /// Extra tokens were found where a line should have ended
+    ExpectedEndOfLine,
+    /// Unexpected indentation
+    Indentation(String),
+    /// A keyword that is not valid in this context
+    UnexpectedKeyword(String),
+    /// An unknown type name
+    UnknownType(String),
+    /// A literal value that could not be converted
+    InvalidLiteral(String),
+    /// Some declaration occurs more than once
+    Duplicate(String),
+    /// Some required declaration is missing
+    Missing(String),
+    /// Syntax that isn't part of the declared FIRRTL version
+    Unsupported(String),
     Other(String),
 }
 impl ParseErrorKind {
@@ -70,6 +95,39 @@ impl ParseErrorKind {
             Self::ExpectedPunctuation(s) => { 
                 format!("Expected punctuation: '{}'", s)
             },
+            Self::Lexical(s) => { 
+                format!("Unknown token: '{}'", s)
+            },
+            Self::Io(e) => { 
+                format!("I/O error: {}", e)
+            },
+            Self::UnexpectedEof => { 
+                "Unexpected end of file".to_string()
+            },
+            Self::ExpectedEndOfLine => { 
+                "Expected end of line".to_string()
+            },
+            Self::Indentation(s) => { 
+                format!("Bad indentation: {}", s)
+            },
+            Self::UnexpectedKeyword(s) => { 
+                format!("Unexpected keyword: '{}'", s)
+            },
+            Self::UnknownType(s) => { 
+                format!("Unknown type: '{}'", s)
+            },
+            Self::InvalidLiteral(s) => { 
+                format!("Invalid literal: '{}'", s)
+            },
+            Self::Duplicate(s) => { 
+                format!("Multiple '{}' declarations", s)
+            },
+            Self::Missing(s) => { 
+                format!("Missing '{}' declaration", s)
+            },
+            Self::Unsupported(s) => { 
+                format!("Unsupported syntax: {}", s)
+            },
             Self::Other(s) => { 
                 format!("Parse error: '{}'", s)
             },
@@ -82,9 +140,29 @@ impl ParseErrorKind {
 pub struct FirrtlParseError {
     /// The type of error
     pub kind: ParseErrorKind,
+    /// The line number in the original source file.
+    ///
+    /// NOTE: This is `0` for errors that aren't associated with any line.
+    pub line: usize,
     /// The span/context for this error
     pub span: Range<usize>,
 }
+impl From<std::io::Error> for FirrtlParseError {
+    fn from(e: std::io::Error) -> Self {
+        Self { kind: ParseErrorKind::Io(e), line: 0, span: 0..0 }
+    }
+}
+impl fmt::Display for FirrtlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.line == 0 {
+            write!(f, "{}", self.kind.message())
+        } else {
+            write!(f, "line {}, column {}: {}", 
+                self.line, self.span.start, self.kind.message())
+        }
+    }
+}
+impl std::error::Error for FirrtlParseError {}
 
 /// State used to implement a parser over some set of [FirrtlTokenizedLine].
 pub struct FirrtlStream<'a> {
@@ -103,10 +181,26 @@ pub struct FirrtlStream<'a> {
     /// NOTE: At some point, it would be nice if we didn't need this
     module_ctx: BTreeSet<&'a str>,
 
+    /// Types of the ports and declarations seen so far in the current 
+    /// module
+    decl_types: BTreeMap<&'a str, FirrtlType>,
+
+    /// Type aliases declared so far in the circuit
+    type_aliases: BTreeMap<&'a str, FirrtlType>,
+
+    /// Version from the `FIRRTL version` header (if there was one)
+    version: Option<Version>,
+
     /// The index of the current line
     gcur: usize,
     /// The index of the current token [within the current line]
     lcur: usize,
+
+    /// When set, the parser records errors and resynchronizes instead of 
+    /// stopping at the first error
+    recovery: bool,
+    /// Errors recorded while recovering
+    errors: Vec<FirrtlParseError>,
 }
 impl <'a> FirrtlStream<'a> {
     //pub fn new(file: &'a FirrtlFile, lines: &'a [FirrtlTokenizedLine]) -> Self { 
@@ -115,13 +209,19 @@ impl <'a> FirrtlStream<'a> {
             file,
             length: file.lines.len(),
             module_ctx: BTreeSet::new(),
+            decl_types: BTreeMap::new(),
+            type_aliases: BTreeMap::new(),
+            version: None,
             gcur: 0,
             lcur: 0,
+            recovery: false,
+            errors: Vec::new(),
         }
     }
 
     pub fn clear_module_ctx(&mut self) {
         self.module_ctx.clear();
+        self.decl_types.clear();
     }
     pub fn check_module_ctx(&self, kw: &'a str) -> bool {
         self.module_ctx.get(kw).is_some()
@@ -129,12 +229,96 @@ impl <'a> FirrtlStream<'a> {
     pub fn add_module_ctx(&mut self, kw: &'a str) {
         self.module_ctx.insert(kw);
     }
+
+    /// Get the declared type of a port/wire/register in the current module.
+    pub fn decl_type(&self, id: &str) -> Option<&FirrtlType> {
+        self.decl_types.get(id)
+    }
+    pub fn add_decl_type(&mut self, id: &'a str, ty: FirrtlType) {
+        self.decl_types.insert(id, ty);
+    }
+
+    /// Get the type for a previously-declared type alias.
+    pub fn type_alias(&self, id: &str) -> Option<&FirrtlType> {
+        self.type_aliases.get(id)
+    }
+    pub fn add_type_alias(&mut self, id: &'a str, ty: FirrtlType) {
+        self.type_aliases.insert(id, ty);
+    }
+
+    /// Get the version declared by the `FIRRTL version` header.
+    pub fn version(&self) -> Option<Version> {
+        self.version
+    }
+    pub fn set_version(&mut self, version: Version) {
+        self.version = Some(version);
+    }
+
+    /// Returns an error (at the current token) if `feature` isn't part of 
+    /// the declared version. 
+    ///
+    /// NOTE: Anything is allowed when there is no declared version.
+    pub fn require(&self, feature: Feature) -> Result<(), FirrtlParseError> {
+        let Some(version) = self.version else { return Ok(()) };
+        VersionError::check(feature, version).map_err(|e| {
+            self.error(ParseErrorKind::Unsupported(e.to_string()))
+        })
+    }
 }
 
+/// Support for recovering from errors.
 impl <'a> FirrtlStream<'a> {
+    /// Enable/disable error recovery
+    pub fn set_recovery(&mut self, enable: bool) {
+        self.recovery = enable;
+    }
+    /// Returns 'true' if the parser should try to recover from errors
+    pub fn is_recovering(&self) -> bool {
+        self.recovery
+    }
+    /// Record an error
+    pub fn push_error(&mut self, e: FirrtlParseError) {
+        self.errors.push(e);
+    }
+    /// Take all of the recorded errors
+    pub fn take_errors(&mut self) -> Vec<FirrtlParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the index of the current line in the stream.
+    pub fn line_index(&self) -> usize {
+        self.gcur
+    }
+
+    /// Move to the first line after `start_line` that isn't indented past 
+    /// `indent`. 
+    ///
+    /// NOTE: Lines that were only partially consumed are always skipped.
+    pub fn resync(&mut self, start_line: usize, indent: usize) {
+        let mut target = if self.lcur > 0 { self.gcur + 1 } else { self.gcur };
+        target = target.max(start_line + 1);
+        self.gcur = target.min(self.length);
+        self.lcur = 0;
+        while !self.is_eof() && self.indent_level() > indent {
+            self.gcur += 1;
+        }
+    }
+}
+
+impl <'a> FirrtlStream<'a> {
+    /// Returns 'true' when the stream has no more lines.
+    pub fn is_eof(&self) -> bool {
+        self.gcur >= self.length
+    }
+
     /// Move to the next token in the stream. 
+    ///
+    /// NOTE: This does nothing if the stream has reached EOF.
     pub fn next_token(&mut self) {
-        if self.lcur == self.line().len() - 1 {
+        let Some(line) = self.line() else {
+            return;
+        };
+        if self.lcur + 1 >= line.len() {
             self.gcur += 1;
             self.lcur  = 0;
         } else { 
@@ -143,15 +327,18 @@ impl <'a> FirrtlStream<'a> {
     }
 
     /// Explicitly move to the next line in the stream. 
-    pub fn next_line(&mut self) {
+    pub fn next_line(&mut self) -> Result<(), FirrtlParseError> {
+        if self.is_eof() {
+            return Err(self.error(ParseErrorKind::UnexpectedEof));
+        }
         self.gcur += 1;
         self.lcur  = 0;
-        assert!(self.gcur < self.length);
+        Ok(())
     }
 
-    /// Get the current line
-    pub fn line(&self) -> &'a FirrtlTokenizedLine {
-        &self.file.lines[self.gcur]
+    /// Get the current line (or `None` if the stream has reached EOF)
+    pub fn line(&self) -> Option<&'a FirrtlTokenizedLine> {
+        self.file.lines.get(self.gcur)
     }
 
     /// Returns 'true' when the current cursor points to the start of a line.
@@ -160,76 +347,107 @@ impl <'a> FirrtlStream<'a> {
     }
 
     /// Get the current token
-    pub fn token(&self) -> &'a Token {
-        &self.file.lines[self.gcur].tokens[self.lcur]
+    pub fn token(&self) -> Result<&'a Token, FirrtlParseError> {
+        if let Some(line) = self.line() {
+            Ok(&line.tokens[self.lcur])
+        } else {
+            Err(self.error(ParseErrorKind::UnexpectedEof))
+        }
     }
 
     /// Get a slice of the remaining tokens on the current line
     pub fn remaining_tokens(&self) -> &'a [Token] {
-        &self.file.lines[self.gcur].tokens[self.lcur..]
+        if let Some(line) = self.line() {
+            &line.tokens[self.lcur..]
+        } else {
+            &[]
+        }
     }
 
     /// Get the current indentation level.
     ///
     /// NOTE: Returns `0` if the stream has reached EOF.
     pub fn indent_level(&self) -> usize {
-        if self.gcur >= self.length {
-            0
+        if let Some(line) = self.line() {
+            line.indent_level()
         } else {
-            self.file.lines[self.gcur].indent_level()
+            0
         }
     }
 
-    /// Peek at the token 'N'-steps ahead of the cursor. 
-    pub fn peekn_token(&self, n: usize) -> &'a Token {
-        assert!(self.lcur + n < self.line().len());
-        &self.file.lines[self.gcur].tokens[self.lcur + n]
+    /// Get the FIRRTL source info attached to the current line.
+    pub fn source_info(&self) -> Option<SourceInfo> {
+        self.line()?.info.as_deref().and_then(SourceInfo::parse)
+    }
+
+    /// Peek at the token 'N'-steps ahead of the cursor on the current line.
+    pub fn peekn_token(&self, n: usize) -> Option<&'a Token> {
+        self.remaining_tokens().get(n)
     }
 
 }
 
 /// For recovering the span from the original file during error-handling.
 impl <'a> FirrtlStream<'a> {
-    fn get_source_line(&self) -> usize { 
-        self.line().sf_line
+    /// Returns the line number of the current token in the original file.
+    pub fn get_source_line(&self) -> usize { 
+        if let Some(line) = self.line() {
+            line.sf_line
+        } else {
+            self.file.lines.last().map_or(0, |l| l.sf_line)
+        }
     }
 
-    fn get_source_span(&self) -> Range<usize> {
-        let s = self.line().spans[self.lcur].start;
-        let e = self.line().spans[self.lcur].end;
-        s..e
+    /// Returns the span of the current token. At EOF, this is an empty 
+    /// span at the end of the last line. 
+    pub fn get_source_span(&self) -> Range<usize> {
+        if let Some(line) = self.line() {
+            line.spans[self.lcur].clone()
+        } else if let Some(line) = self.file.lines.last() {
+            let end = line.spans.last().map_or(0, |s| s.end);
+            end..end
+        } else {
+            0..0
+        }
     }
 
-    fn err_keyword(&self, kw: &str) -> FirrtlParseError {
-        FirrtlParseError {
-            kind: ParseErrorKind::ExpectedKeyword(kw.to_string()),
-            span: self.get_source_span(),
+    /// Create an error associated with the current token.
+    ///
+    /// NOTE: A token that was expected at the start of a line was really 
+    /// missing from the end of the previous line.
+    pub fn error(&self, kind: ParseErrorKind) -> FirrtlParseError {
+        let missing = matches!(kind, ParseErrorKind::ExpectedToken(_) 
+            | ParseErrorKind::ExpectedPunctuation(_));
+        if missing && self.is_sol() && self.gcur > 0 {
+            if let Some(prev) = self.file.lines.get(self.gcur - 1) {
+                let end = prev.spans.last().map_or(0, |s| s.end);
+                return FirrtlParseError { 
+                    kind, line: prev.sf_line, span: end..end 
+                };
+            }
         }
+        FirrtlParseError { 
+            kind, 
+            line: self.get_source_line(),
+            span: self.get_source_span() 
+        }
+    }
+
+    fn err_keyword(&self, kw: &str) -> FirrtlParseError {
+        self.error(ParseErrorKind::ExpectedKeyword(kw.to_string()))
     }
     fn err_punct(&self, kw: &str) -> FirrtlParseError {
-        FirrtlParseError {
-            kind: ParseErrorKind::ExpectedPunctuation(kw.to_string()),
-            span: self.get_source_span(),
-        }
+        self.error(ParseErrorKind::ExpectedPunctuation(kw.to_string()))
     }
     fn err_token(&self, kw: &str) -> FirrtlParseError {
-        FirrtlParseError {
-            kind: ParseErrorKind::ExpectedToken(kw.to_string()),
-            span: self.get_source_span(),
-        }
-    }
-    fn err_other(&self, kw: &str) -> FirrtlParseError {
-        FirrtlParseError {
-            kind: ParseErrorKind::Other(kw.to_string()),
-            span: self.get_source_span(),
-        }
+        self.error(ParseErrorKind::ExpectedToken(kw.to_string()))
     }
 }
 
 /// All of these methods attempt to *match* the underlying data from a [Token]. 
 impl <'a> FirrtlStream<'a> {
     pub fn match_punc(&self, p: &'a str) -> Result<(), FirrtlParseError> {
-        if self.token() == &Token::punctuation_from_str(p) {
+        if self.token()? == &Token::punctuation_from_str(p) {
             Ok(())
         } else { 
             Err(self.err_punct(p))
@@ -256,52 +474,89 @@ impl <'a> FirrtlStream<'a> {
             Err(self.err_keyword(&format!("{:?}", kw)))
         }
     }
+
+    /// Expect that the cursor has moved to the start of a new line.
+    pub fn match_sol(&self) -> Result<(), FirrtlParseError> {
+        if self.is_sol() {
+            Ok(())
+        } else {
+            Err(self.error(ParseErrorKind::ExpectedEndOfLine))
+        }
+    }
 }
 
 /// All of these methods attempt to *read* the underlying data from a [Token]. 
 impl <'a> FirrtlStream<'a> {
     pub fn get_identkw(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Token::IdentKw(s) = self.token() {
+        if let Token::IdentKw(s) = self.token()? {
             Ok(s)
         } else { 
             Err(self.err_token("identifier/keyword"))
         }
     }
     pub fn get_lit_int(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Some(lit) = self.token().get_lit_int() {
+        if let Some(lit) = self.token()?.get_lit_int() {
             Ok(lit)
         } else { 
             Err(self.err_token("integer literal"))
         }
     }
     pub fn get_lit_sint(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Some(lit) = self.token().get_lit_sint() {
+        if let Some(lit) = self.token()?.get_lit_sint() {
             Ok(lit)
         } else { 
             Err(self.err_token("signed integer literal"))
         }
     }
     pub fn get_lit_float(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Some(lit) = self.token().get_lit_float() {
+        if let Some(lit) = self.token()?.get_lit_float() {
             Ok(lit)
         } else { 
             Err(self.err_token("floating-point literal"))
         }
     }
     pub fn get_lit_str(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Some(lit) = self.token().get_lit_str() {
+        if let Some(lit) = self.token()?.get_lit_str() {
             Ok(lit)
         } else {
             Err(self.err_token("string literal"))
         }
     }
     pub fn get_lit_raw_str(&self) -> Result<&'a str, FirrtlParseError> {
-        if let Some(lit) = self.token().get_raw_str() {
+        if let Some(lit) = self.token()?.get_raw_str() {
             Ok(lit)
         } else {
             Err(self.err_token("raw string literal"))
         }
     }
+
+    /// Read an integer literal as a `usize`.
+    pub fn get_lit_usize(&self) -> Result<usize, FirrtlParseError> {
+        let lit = self.get_lit_int()?;
+        lit.parse::<usize>().map_err(|_| {
+            self.error(ParseErrorKind::InvalidLiteral(lit.to_string()))
+        })
+    }
+
+    /// Read the value of an unsigned integer literal (ie. for `UInt(...)`).
+    pub fn get_unsigned_numeric_literal(&self) 
+        -> Result<LiteralNumeric, FirrtlParseError>
+    {
+        let tok = self.token()?;
+        tok.get_unsigned_numeric_literal().ok_or_else(|| {
+            self.error(ParseErrorKind::InvalidLiteral(tok.text().to_string()))
+        })
+    }
+
+    /// Read the value of a signed integer literal (ie. for `SInt(...)`).
+    pub fn get_signed_numeric_literal(&self) 
+        -> Result<LiteralNumeric, FirrtlParseError>
+    {
+        let tok = self.token()?;
+        tok.get_signed_numeric_literal().ok_or_else(|| {
+            self.error(ParseErrorKind::InvalidLiteral(tok.text().to_string()))
+        })
+    }
 }
 
 
@@ -9,9 +9,20 @@ mod parse;
 pub mod pass;
 pub mod file;
 pub mod ast;
+pub mod diagnostic;
+pub mod emit;
+pub mod diff;
+pub mod event;
+pub mod intrinsic;
+pub mod version;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(test)]
+mod test_util;
 
 pub use lex::FirrtlParseError;
 pub use file::FirrtlFile;
+pub use diagnostic::Diagnostic;
 
 #[cfg(test)]
 mod tests {
@@ -26,10 +37,27 @@ mod tests {
 
         //let filename = "../parse-basic.fir";
         let filename = "../chisel-tests/firrtl/MyAlu.fir";
-        let sf = FirrtlFile::from_file(filename);
+        let sf = FirrtlFile::from_file(filename)?;
         let circuit = sf.parse()?;
         Ok(())
     }
+
+    #[test]
+    fn lexical_error() {
+        let src = "circuit Foo :\n  module Foo :\n    node x = #bad\n";
+        let Err(e) = FirrtlFile::from_str("bad.fir", src) else {
+            panic!("expected a lexical error");
+        };
+        match &e.kind {
+            ParseErrorKind::Lexical(text) => assert_eq!(text, "#"),
+            _ => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(e.line, 3);
+        assert_eq!(e.span, 14..15);
+
+        let e = FirrtlFile::from_file("/nonexistent/foo.fir").err().unwrap();
+        assert!(matches!(e.kind, ParseErrorKind::Io(_)));
+    }
 }
 
 
@@ -2,6 +2,7 @@
 
 use crate::lex::*;
 use crate::ast::*;
+use crate::version::Feature;
 use crate::token::Token;
 
 pub mod module;
@@ -12,34 +13,73 @@ pub mod datatype;
 pub struct FirrtlParser;
 impl <'a> FirrtlParser {
     pub fn parse_firrtl_version(stream: &mut FirrtlStream<'a>)
-        -> Result<(), FirrtlParseError>
+        -> Result<Version, FirrtlParseError>
     {
-        assert!(stream.indent_level() == 0);
+        FirrtlParser::check_indent(stream, 0)?;
         stream.match_identkw("FIRRTL")?;
         stream.next_token();
         stream.match_identkw("version")?;
         stream.next_token();
-        stream.next_line();
-        Ok(())
+
+        // The lexer splits '<x>.<y>.<z>' into a float, '.', and an integer
+        let major_minor = stream.get_lit_float()?;
+        if stream.remaining_tokens().len() < 2 {
+            return Err(stream.error(
+                ParseErrorKind::InvalidLiteral(major_minor.to_string())
+            ));
+        }
+        stream.next_token();
+        stream.match_punc(".")?;
+        stream.next_token();
+        let patch = stream.get_lit_int()?;
+        let text = format!("{}.{}", major_minor, patch);
+        let version = text.parse::<Version>()
+            .map_err(|kind| stream.error(kind))?;
+        if stream.remaining_tokens().len() > 1 {
+            stream.next_token();
+            return Err(stream.error(ParseErrorKind::ExpectedEndOfLine));
+        }
+        stream.next_line()?;
+        Ok(version)
+    }
+
+    /// Expect that the current line has some particular indentation level.
+    pub fn check_indent(stream: &FirrtlStream<'a>, level: usize)
+        -> Result<(), FirrtlParseError>
+    {
+        if stream.indent_level() == level {
+            Ok(())
+        } else {
+            Err(stream.error(ParseErrorKind::Indentation(
+                format!("expected indentation level {}, found {}", 
+                    level, stream.indent_level())
+            )))
+        }
     }
 
     pub fn parse_circuit(stream: &mut FirrtlStream<'a>)
         -> Result<Circuit, FirrtlParseError>
     {
-        assert!(stream.indent_level() == 0);
+        FirrtlParser::check_indent(stream, 0)?;
+        let info = stream.source_info();
         stream.match_identkw("circuit")?;
         stream.next_token();
         let circuit_id = stream.get_identkw()?;
         stream.next_token();
         stream.match_punc(":")?;
         stream.next_token();
-        assert!(stream.is_sol());
+        stream.match_sol()?;
 
         let mut circuit = Circuit::new(circuit_id);
+        circuit.info = info;
 
         // This should be the indentation level for all module declarations
         let module_indent = stream.indent_level();
-        assert!(module_indent > 0);
+        if module_indent == 0 {
+            return Err(stream.error(ParseErrorKind::Indentation(
+                "expected an indented module declaration".to_string()
+            )));
+        }
 
         loop {
             // There are no more module declarations left
@@ -47,45 +87,1234 @@ impl <'a> FirrtlParser {
                 break;
             }
 
-            assert!(stream.is_sol());
-            let m_indent = stream.indent_level();
-            assert!(m_indent == module_indent);
-            stream.clear_module_ctx();
-
-            match stream.get_identkw()? {
-                "module" => {
-                    let m = FirrtlParser::parse_module(stream)?;
-                    circuit.add_module(m);
-                },
-                "extmodule" => {
-                    let m = FirrtlParser::parse_extmodule(stream)?;
-                    circuit.add_extmodule(m);
-                },
-                "intmodule" => {
-                    let m = FirrtlParser::parse_intmodule(stream)?;
-                    circuit.add_intmodule(m);
-                },
-                _ => {
-                    panic!("bad module keyword?");
-                }
+            let start_line = stream.line_index();
+            if let Err(e) = FirrtlParser::parse_circuit_decl(stream, 
+                &mut circuit, module_indent)
+            {
+                FirrtlParser::recover(stream, e, start_line, module_indent)?;
             }
         }
 
         Ok(circuit)
     }
 
+    /// Parse a single module declaration into some [Circuit].
+    pub fn parse_circuit_decl(stream: &mut FirrtlStream<'a>, 
+                              circuit: &mut Circuit, module_indent: usize)
+        -> Result<(), FirrtlParseError>
+    {
+        stream.match_sol()?;
+        FirrtlParser::check_indent(stream, module_indent)?;
+        stream.clear_module_ctx();
+
+        match stream.get_identkw()? {
+            "module" | "public" => {
+                let m = FirrtlParser::parse_module(stream)?;
+                circuit.add_module(m);
+            },
+            "extmodule" => {
+                let m = FirrtlParser::parse_extmodule(stream)?;
+                circuit.add_extmodule(m);
+            },
+            "intmodule" => {
+                let m = FirrtlParser::parse_intmodule(stream)?;
+                circuit.add_intmodule(m);
+            },
+            "class" => {
+                let c = FirrtlParser::parse_class(stream)?;
+                circuit.add_class(c);
+            },
+            "extclass" => {
+                let c = FirrtlParser::parse_extclass(stream)?;
+                circuit.add_extclass(c);
+            },
+            "type" => {
+                let t = FirrtlParser::parse_type_alias(stream)?;
+                circuit.add_type_alias(t);
+            },
+            "layer" => {
+                let l = FirrtlParser::parse_layer(stream)?;
+                circuit.add_layer(l);
+            },
+            "option" => {
+                let o = FirrtlParser::parse_option(stream)?;
+                circuit.add_option(o);
+            },
+            kw => {
+                return Err(stream.error(
+                    ParseErrorKind::UnexpectedKeyword(kw.to_string())
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a layer declaration (`layer <id>, <convention> :`) along with
+    /// any layers nested inside it.
+    pub fn parse_layer(stream: &mut FirrtlStream<'a>)
+        -> Result<Layer, FirrtlParseError>
+    {
+        let layer_indent = stream.indent_level();
+        let info = stream.source_info();
+        stream.match_identkw("layer")?;
+        stream.require(Feature::Layers)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.next_token();
+        let kw = stream.get_identkw()?;
+        let Some(convention) = LayerConvention::from_str(kw) else {
+            return Err(stream.error(
+                ParseErrorKind::UnexpectedKeyword(kw.to_string())
+            ));
+        };
+        stream.next_token();
+
+        let mut layer = Layer::new(id, convention);
+        layer.info = info;
+        if stream.match_punc(":").is_err() {
+            let dir = stream.get_lit_str()?;
+            layer.output_dir = Some(dir[1..dir.len()-1].to_string());
+            stream.next_token();
+        }
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        // Nested layers
+        let child_indent = stream.indent_level();
+        while !stream.is_eof() && child_indent > layer_indent &&
+            stream.indent_level() == child_indent
+        {
+            layer.layers.push(FirrtlParser::parse_layer(stream)?);
+        }
+        Ok(layer)
+    }
+
+    /// Parse an option group declaration (`option <id> :`) along with the
+    /// cases listed underneath it.
+    pub fn parse_option(stream: &mut FirrtlStream<'a>)
+        -> Result<OptionGroup, FirrtlParseError>
+    {
+        let option_indent = stream.indent_level();
+        let info = stream.source_info();
+        stream.match_identkw("option")?;
+        stream.require(Feature::OptionGroups)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        let mut option = OptionGroup::new(id, Vec::new());
+        option.info = info;
+        let case_indent = stream.indent_level();
+        while !stream.is_eof() && case_indent > option_indent &&
+            stream.indent_level() == case_indent
+        {
+            let case = stream.get_identkw()?;
+            if option.has_case(case) {
+                return Err(stream.error(
+                    ParseErrorKind::Duplicate(format!("case {}", case))
+                ));
+            }
+            option.cases.push(case.to_string());
+            stream.next_token();
+            stream.match_sol()?;
+        }
+        Ok(option)
+    }
+
+    /// Parse a type alias declaration (`type <id> = <type>`).
+    pub fn parse_type_alias(stream: &mut FirrtlStream<'a>)
+        -> Result<TypeAlias, FirrtlParseError>
+    {
+        let info = stream.source_info();
+        stream.match_identkw("type")?;
+        stream.require(Feature::TypeAliases)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        if stream.type_alias(id).is_some() {
+            return Err(stream.error(
+                ParseErrorKind::Duplicate(format!("type {}", id))
+            ));
+        }
+        stream.next_token();
+        stream.match_punc("=")?;
+        stream.next_token();
+        let ty = FirrtlParser::parse_type(stream)?;
+        stream.match_sol()?;
+
+        stream.add_type_alias(id, ty.clone());
+        let mut res = TypeAlias::new(id, ty);
+        res.info = info;
+        Ok(res)
+    }
+
     /// Convert a [FirrtlStream] into an AST
     pub fn parse(stream: &mut FirrtlStream<'a>) 
         -> Result<Circuit, FirrtlParseError> 
     {
-        // This is *optional* for now, I guess
-        if let Ok(v) = FirrtlParser::parse_firrtl_version(stream) {
-        } else { 
+        // The version header is optional. Without it, syntax from any 
+        // version is accepted.
+        if stream.match_identkw("FIRRTL").is_ok() {
+            let version = FirrtlParser::parse_firrtl_version(stream)?;
+            stream.set_version(version);
         }
 
-        let circuit = FirrtlParser::parse_circuit(stream)?;
+        let mut circuit = FirrtlParser::parse_circuit(stream)?;
+        circuit.version = stream.version();
         Ok(circuit)
     }
+
+    /// Convert a [FirrtlStream] into an AST, collecting errors instead of 
+    /// stopping at the first one. 
+    ///
+    /// Bad statements and declarations are left out of the resulting 
+    /// [Circuit]. Returns `None` if no circuit could be recovered at all.
+    pub fn parse_recovering(stream: &mut FirrtlStream<'a>) 
+        -> (Option<Circuit>, Vec<FirrtlParseError>)
+    {
+        stream.set_recovery(true);
+        let res = FirrtlParser::parse(stream);
+        let mut errors = stream.take_errors();
+        match res {
+            Ok(circuit) => (Some(circuit), errors),
+            Err(e) => {
+                errors.push(e);
+                (None, errors)
+            },
+        }
+    }
+
+    /// Handle an error that occured while parsing something that started 
+    /// on line `start_line` in a block with indentation level `indent`. 
+    ///
+    /// When recovering, the error is recorded and the stream moves to the 
+    /// next line that belongs to the enclosing block. Otherwise (or when 
+    /// the stream cannot be moved past the error), the error is returned.
+    pub fn recover(stream: &mut FirrtlStream<'a>, e: FirrtlParseError, 
+                   start_line: usize, indent: usize)
+        -> Result<(), FirrtlParseError>
+    {
+        if !stream.is_recovering() {
+            return Err(e);
+        }
+        // If we can't move past the line where the error started (ie. 
+        // at EOF), there's nothing left to recover into
+        stream.resync(start_line, indent);
+        if stream.line_index() <= start_line {
+            return Err(e);
+        }
+        stream.push_error(e);
+
+        // Any 'else' at this level belongs to a 'when' that we've skipped
+        while stream.indent_level() == indent && 
+            stream.match_identkw("else").is_ok() 
+        {
+            let else_line = stream.line_index();
+            stream.resync(else_line, indent);
+        }
+        Ok(())
+    }
 }
 
 
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::file::FirrtlFile;
+    use crate::lex::*;
+    use crate::test_util::check_err;
+
+    /// A small circuit exercising most of the syntax that we can parse.
+    const SAMPLE: &str = "\
+FIRRTL version 3.3.0
+circuit Top :
+  extmodule BlackBox :
+    input in : UInt<8>
+    output out : UInt<8>
+    defname = BlackBox
+    parameter WIDTH = 8
+  module Top : @[Top.scala 1:1]
+    input clock : Clock
+    input reset : UInt<1>
+    input io : { flip a : UInt<8>, b : SInt<4>[2] }
+    output out : UInt<8>
+
+    wire w : UInt<8>
+    regreset r : UInt<8>, clock, reset, UInt<8>(\"h0\")
+    node n = add(io.a, UInt<8>(3))
+    node m = mux(reset, bits(n, 7, 0), w)
+    inst bb of BlackBox
+    mem ram :
+      data-type => UInt<8>
+      depth => 16
+      read-latency => 0
+      write-latency => 1
+      reader => r0
+      read-under-write => undefined
+    connect bb.in, m
+    when eq(n, UInt(1)) :
+      connect w, io.b[0]
+    else :
+      connect w, SInt<4>(-1)
+    invalidate out
+    connect out, tail(r, 1)
+    printf(clock, reset, \"%d\\n\", n) : p0
+    stop(clock, reset, 1)
+";
+
+    fn parse_str(src: &str) -> Result<(), FirrtlParseError> {
+        let ff = FirrtlFile::from_str("fuzz.fir", src)?;
+        ff.parse()?;
+        Ok(())
+    }
+
+    /// Parse some input, failing with the offending input if anything panics.
+    fn parse_no_panic(src: &str) -> Result<(), FirrtlParseError> {
+        match std::panic::catch_unwind(|| parse_str(src)) {
+            Ok(res) => res,
+            Err(_) => panic!("parser panicked on input:\n{}", src),
+        }
+    }
+
+    #[test]
+    fn sample_parses() -> Result<(), FirrtlParseError> {
+        parse_str(SAMPLE)
+    }
+
+    #[test]
+    fn malformed_inputs() {
+        let corpus = [
+            "",
+            "circuit",
+            "circuit Top",
+            "circuit Top :",
+            "circuit Top :\nmodule Top :\n",
+            "circuit Top :\n  module Top\n",
+            "circuit Top :\n  foo Top :\n",
+            "circuit Top :\n  module Top :\n    input\n",
+            "circuit Top :\n  module Top :\n    inout a : UInt\n",
+            "circuit Top :\n  module Top :\n    input a : Foo<1>\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<99999999999999999999>\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>[\n",
+            "circuit Top :\n  module Top :\n    input a : { a : UInt\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1> extra\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    frob a\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    connect\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    connect a,\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    a <\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    a is valid\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    node x = frob(a)\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    node x = UInt<1>(\"q1\")\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    node x = UInt<8>(\"hfffffffffffffffffffff\")\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    node x = bits(a, 1)\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    when a :\n    skip\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    when a : skip skip\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    mem m :\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    mem m :\n      depth => 1\n      depth => 2\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    mem m :\n      depth => 1\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    mem m :\n      frob => 1\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    mem m :\n      read-under-write => sometimes\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    printf(a, a, \"x\"\n",
+            "circuit Top :\n  module Top :\n    input a : UInt<1>\n    stop(a, a, -1)\n",
+            "circuit Top :\n  extmodule E :\n    input a : UInt<1>\n    frob = 1\n",
+            "circuit Top :\n  extmodule E :\n    parameter X = \n",
+            "circuit Top :\n  extmodule E :\n    defname\n",
+            "circuit Top :\n  intmodule I :\n",
+            "circuit Top :\n  intmodule I :\n    input a : UInt<1>\n",
+            "FIRRTL version\n",
+        ];
+        for src in corpus {
+            assert!(parse_no_panic(src).is_err(), "accepted input:\n{}", src);
+        }
+    }
+
+    #[test]
+    fn recover_multiple_errors() {
+        let src = "\
+circuit Top :
+  module Bad :
+    input a : UInt<1>
+    input b : Foo
+    input c : UInt<1>
+    node x = frob(a)
+    when a :
+      frob b
+      connect c, a
+    when frob(a) :
+      connect c, a
+    else :
+      connect c, b
+    connect c, a
+  module Top :
+    input a : UInt<1>
+    output b : UInt<1>
+    connect b, a
+";
+        let ff = FirrtlFile::from_str("bad.fir", src).unwrap();
+        assert!(ff.parse().is_err());
+
+        let (circuit, diags) = ff.parse_recovering();
+        let lines: Vec<usize> = diags.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![4, 6, 8, 10]);
+
+        let circuit = circuit.unwrap();
+        assert_eq!(circuit.modules.len(), 2);
+        let bad = &circuit.modules[0];
+        assert_eq!(bad.ports.len(), 2);
+        assert_eq!(bad.statements.len(), 2);
+        let crate::ast::StatementKind::When(_, wblk, _) = &bad.statements[0].kind
+        else {
+            panic!("expected 'when' statement");
+        };
+        assert_eq!(wblk.len(), 1);
+        assert!(circuit.top_module().is_some());
+    }
+
+    #[test]
+    fn recover_truncated_input() {
+        let src = "\
+circuit Top :
+  module Top :
+    input a : UInt<1>
+    when a :
+      skip
+    else :
+";
+        let ff = FirrtlFile::from_str("trunc.fir", src).unwrap();
+        let err = ff.parse().unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::UnexpectedEof));
+
+        let (circuit, diags) = ff.parse_recovering();
+        assert_eq!(diags.len(), 1);
+        let top = circuit.unwrap();
+        let top = top.top_module().unwrap();
+        assert_eq!(top.ports.len(), 1);
+        assert!(top.statements.is_empty());
+    }
+
+    #[test]
+    fn source_info() -> Result<(), FirrtlParseError> {
+        use crate::ast::SourceInfo;
+        let src = "\
+circuit Top : @[Top.scala 1:1]
+  module Top : @[Top.scala 3:7]
+    input a : UInt<1> @[Top.scala:4:14]
+    output b : UInt<1>
+    node x = not(a) @[Top.scala 5:{10,12} Util.scala 9:2]
+    connect b, x @[Top.scala 6]
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let info = |i: &Option<SourceInfo>| i.as_ref().map(|i| i.to_string());
+        let top = circuit.top_module().unwrap();
+        assert_eq!(info(&circuit.info).as_deref(), Some("@[Top.scala 1:1]"));
+        assert_eq!(info(&top.info).as_deref(), Some("@[Top.scala 3:7]"));
+        assert_eq!(info(&top.ports[0].info).as_deref(), 
+            Some("@[Top.scala 4:14]"));
+        assert!(top.ports[1].info.is_none());
+        assert_eq!(info(&top.statements[0].info).as_deref(), 
+            Some("@[Top.scala 5:10 Top.scala 5:12 Util.scala 9:2]"));
+        assert_eq!(info(&top.statements[1].info).as_deref(), 
+            Some("@[Top.scala 6]"));
+
+        let loc = &top.statements[0].info.as_ref().unwrap().locators[2];
+        assert_eq!(loc.file, "Util.scala");
+        assert_eq!((loc.line, loc.col), (Some(9), Some(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn extmodule_params() -> Result<(), FirrtlParseError> {
+        use crate::ast::*;
+        let circuit = FirrtlFile::from_str("Top.fir", SAMPLE)?.parse()?;
+        let bb = &circuit.extmodules[0];
+        assert_eq!(bb.defname.as_deref(), Some("BlackBox"));
+        assert_eq!(bb.param("WIDTH"), Some(&ParamValue::Int(8u32.into())));
+
+        let src = "\
+circuit Top :
+  extmodule Ext :
+    input in : UInt<8>
+    defname = Impl
+    parameter A = -4
+    parameter B = 1.25
+    parameter C = \"hello\"
+    parameter D = 'raw'
+    defname = Again
+  module Top :
+    skip
+";
+        let err = FirrtlFile::from_str("Top.fir", src)?.parse().unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::Duplicate(_)));
+        assert_eq!(err.line, 9);
+
+        let src = src.replace("    defname = Again\n", "");
+        let circuit = FirrtlFile::from_str("Top.fir", &src)?.parse()?;
+        let ext = &circuit.extmodules[0];
+        assert_eq!(ext.params, vec![
+            Parameter::new("A", ParamValue::SInt((-4).into())),
+            Parameter::new("B", ParamValue::Float("1.25".to_string())),
+            Parameter::new("C", ParamValue::String("hello".to_string())),
+            Parameter::new("D", ParamValue::RawString("raw".to_string())),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn type_aliases() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  type Word = UInt<32>
+  type State = {| Idle, Busy : Word |}
+  type Regs = { state : State, data : Word[4] }
+  module Top :
+    input clock : Clock
+    input in : Regs
+    output out : Word
+    wire s : State
+    connect s, State(Busy, in.data[0])
+    connect out, in.data[1]
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        assert_eq!(circuit.type_aliases.len(), 3);
+        // Uses of an alias keep the alias name
+        let port = &circuit.modules[0].ports[1];
+        assert_eq!(port.ty, FirrtlType::Alias("Regs".to_string()));
+
+        // ... but can be expanded into a structural type
+        let word = FirrtlType::Ground(FirrtlTypeGround::UInt(Some(32)));
+        let regs = circuit.resolve_type(&port.ty).unwrap();
+        assert_eq!(regs.bundle_field_type("data"), 
+            Some(&FirrtlType::Vector(Box::new(word.clone()), 4)));
+        assert_eq!(regs.bundle_field_type("state").unwrap()
+            .enum_variant("Busy").unwrap().ty, Some(word));
+        assert_eq!(regs.width(), Some(33 + 128));
+        assert_eq!(circuit.resolve_type(&FirrtlType::Alias("Nope".into())), 
+            None);
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            // Aliases must be declared before they're used, and only once
+            (src.replace("  type Word = UInt<32>\n", ""), 2, 
+                UnknownType(String::new())),
+            (src.replace("type Regs", "type Word"), 4, 
+                Duplicate(String::new())),
+            (src.replace("State(Busy", "State(Done"), 10, 
+                Other(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn layers() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  layer Verif, bind, \"verif\" :
+    layer Assert, bind :
+    layer Cover, inline :
+  layer Debug, inline :
+  module Top enablelayer Verif enablelayer Debug :
+    input clock : Clock
+    input a : UInt<8>
+    output p : Probe<UInt<8>, Verif.Assert>
+    output q : RWProbe<UInt<8>>
+    layerblock Verif :
+      layerblock Assert :
+        node c = eq(a, UInt<8>(0))
+        define p = probe(c)
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let verif = circuit.layer("Verif").unwrap();
+        assert_eq!(verif.output_dir.as_deref(), Some("verif"));
+        assert_eq!(verif.layers.len(), 2);
+        assert_eq!(circuit.layer("Verif.Cover").unwrap().convention,
+            LayerConvention::Inline);
+        assert!(circuit.layer("Assert").is_none());
+        assert_eq!(circuit.modules[0].enabled_layers, ["Verif", "Debug"]);
+        match &circuit.modules[0].ports[2].ty {
+            FirrtlType::Ref(probe) =>
+                assert_eq!(probe.layer(), Some("Verif.Assert")),
+            ty => panic!("expected a probe, got {}", ty),
+        }
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            (src.replace("bind, \"verif\"", "sideways"), 2, 
+                UnexpectedKeyword(String::new())),
+            (src.replace("      layerblock Assert :\n",
+                "      layerblock Assert :\n    skip\n"), 13, 
+                Indentation(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn properties() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    output meta : AnyRef
+    output inst : Inst<Meta>
+    object m of Meta
+    propassign m.width, Integer(-8)
+    propassign m.flags, List<Bool>(Bool(true), Bool(false))
+    propassign meta, m
+    propassign inst, m
+    object e of Ext
+    propassign e.p, Path(\"OMReferenceTarget:~Top|Top>clock\")
+  class Meta :
+    input width : Integer
+    input flags : List<Bool>
+    output name : String
+    output widths : List<Integer>
+    output scale : Double
+    propassign name, String(\"top\")
+    propassign widths, List<Integer>(width, integer_shl(width, Integer(1)))
+    propassign scale, Double(-1.5E3)
+  extclass Ext :
+    input p : Path
+    output all : List<List<Integer>>
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        assert_eq!(circuit.classes.len(), 1);
+        assert_eq!(circuit.extclasses.len(), 1);
+        let top = &circuit.modules[0];
+        assert_eq!(top.ports[2].ty,
+            FirrtlType::Property(PropertyType::Inst("Meta".to_string())));
+        assert_eq!(top.statements[0].kind,
+            StatementKind::Object("m".to_string(), "Meta".to_string()));
+        match &top.statements[1].kind {
+            StatementKind::PropAssign(r, Expr::Property(lit)) => {
+                assert_eq!(r.to_string(), "m.width");
+                assert_eq!(lit, &PropertyLiteral::Integer(BigInt::from(-8)));
+            },
+            kind => panic!("expected a propassign, got {:?}", kind),
+        }
+        match &circuit.classes[0].statements[1].kind {
+            StatementKind::PropAssign(_, Expr::List(ty, elems)) => {
+                assert_eq!(ty, &PropertyType::Integer);
+                assert!(matches!(elems[1],
+                    Expr::PropPrimOp(PropPrimOp::IntegerShl, _)));
+            },
+            kind => panic!("expected a list, got {:?}", kind),
+        }
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            (src.replace("Bool(true)", "Bool(maybe)"), 8, 
+                InvalidLiteral(String::new())),
+            (src.replace("List<Bool>(", "List<UInt>("), 8, 
+                UnknownType(String::new())),
+            // External classes have no body
+            (src.replace("output all : List<List<Integer>>\n",
+                "object o of Meta\n"), 24, 
+                UnexpectedKeyword(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn options_and_instchoice() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  option Platform : @[Top.scala 1:1]
+    FPGA
+    ASIC
+  public module Top :
+    input clock : Clock
+    instchoice mem of SimMem, Platform : @[Top.scala 5:3]
+      FPGA => FpgaMem
+      ASIC => AsicMem
+    connect mem.clock, clock
+  module SimMem :
+    input clock : Clock
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let option = circuit.option("Platform").unwrap();
+        assert_eq!(option.cases, ["FPGA", "ASIC"]);
+        assert!(circuit.modules[0].public);
+        assert!(!circuit.modules[1].public);
+        match &circuit.modules[0].statements[0].kind {
+            StatementKind::InstChoice(choice) => {
+                assert_eq!(choice.option, "Platform");
+                assert_eq!(choice.target(Some("ASIC")), "AsicMem");
+                assert_eq!(choice.target(None), "SimMem");
+            },
+            kind => panic!("expected an instchoice, got {:?}", kind),
+        }
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            (src.replace("ASIC\n  public", "FPGA\n  public"), 4, 
+                Duplicate(String::new())),
+            (src.replace("public module", "public extmodule"), 5, 
+                ExpectedKeyword(String::new())),
+            (src.replace("FPGA => FpgaMem", "FPGA FpgaMem"), 8, 
+                ExpectedPunctuation(String::new())),
+            (src.replace("SimMem, Platform", "SimMem"), 7, 
+                ExpectedToken(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn intrinsics() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input a : UInt<8>
+    output out : UInt<1>
+    node test = intrinsic(circt_plusargs_test<FORMAT = \"foo\"> : UInt<1>)
+    node v = intrinsic(plusargs<FMT = \"v=%d\", DEFAULT = -1> : { ok : UInt<1> })
+    connect out, and(test, intrinsic(sizeof : UInt<1>, a))
+    intrinsic(circt_trace<DEPTH = 2, RATE = 0.5>, clock, add(a, UInt<8>(1)))
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let top = &circuit.modules[0];
+        match &top.statements[1].kind {
+            StatementKind::Node(_, Expr::Intrinsic(intr)) => {
+                assert_eq!(intr.name, "plusargs");
+                assert_eq!(intr.param("FMT"),
+                    Some(&ParamValue::String("v=%d".to_string())));
+                assert_eq!(intr.param("DEFAULT"),
+                    Some(&ParamValue::SInt(BigInt::from(-1))));
+                assert!(intr.args.is_empty());
+            },
+            kind => panic!("expected an intrinsic, got {:?}", kind),
+        }
+        match &top.statements[3].kind {
+            StatementKind::Intrinsic(intr) => {
+                assert_eq!(intr.ty, None);
+                assert_eq!(intr.params.len(), 2);
+                assert_eq!(intr.args.len(), 2);
+            },
+            kind => panic!("expected an intrinsic, got {:?}", kind),
+        }
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            // An intrinsic expression must have a result type
+            (src.replace("sizeof : UInt<1>", "sizeof"), 8, 
+                Missing(String::new())),
+            (src.replace("DEPTH = 2", "DEPTH = a"), 9, 
+                ExpectedToken(String::new())),
+            (src.replace("RATE = 0.5>", "RATE = 0.5"), 9, 
+                ExpectedPunctuation(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn enums_and_match() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input in : {| Idle, Busy : UInt<8>, Done : { a : UInt<1>, b : SInt<4> } |}
+    output out : UInt<8>
+    wire e : {| A, B : UInt<3> |}[2]
+    connect e[0], {| A, B : UInt<3> |}(A)
+    connect e[1], {| A, B : UInt<3> |}(B, UInt<3>(5))
+    match in :
+      Idle :
+        connect out, UInt<8>(0)
+      Busy(n) :
+        connect out, n
+      Done(d) :
+        connect out, pad(d.a, 8)
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let top = &circuit.modules[0];
+        // 2 bits of tag, and 8 bits for the widest variant
+        assert_eq!(top.ports[1].ty.width(), Some(10));
+        // Variants holding a bundle with an inferred width
+        let inferred = FirrtlType::Bundle(vec![BundleField::new(false, "x",
+            FirrtlType::Ground(FirrtlTypeGround::UInt(None)))]);
+        assert_eq!(inferred.width(), None);
+        let e = FirrtlType::Enum(vec![EnumVariant::new("A", Some(inferred))]);
+        assert_eq!(e.width(), None);
+        assert!(top.ports[1].ty.enum_variant("Busy").is_some());
+        let StatementKind::Match(_, arms) = &top.statements[3].kind else {
+            panic!("{:?}", top.statements[3])
+        };
+        assert_eq!(arms.len(), 3);
+        assert_eq!(arms[0].binding, None);
+        assert_eq!(arms[1].binding.as_deref(), Some("n"));
+        assert_eq!(arms[2].body.len(), 1);
+
+        assert_eq!(FirrtlType::enum_tag_width(1), 0);
+        assert_eq!(FirrtlType::enum_tag_width(2), 1);
+        assert_eq!(FirrtlType::enum_tag_width(5), 3);
+
+        use ParseErrorKind::*;
+        let done = "      Done(d) :\n        connect out, pad(d.a, 8)\n";
+        for (bad, line, kind) in [
+            // Unknown variant, and missing data for a variant
+            (src.replace("(B, UInt<3>(5))", "(C, UInt<3>(5))"), 8, 
+                Other(String::new())),
+            (src.replace("(B, UInt<3>(5))", "(B)"), 8, 
+                ExpectedToken(String::new())),
+            // Arms without a body
+            (src.replace("        connect out, n\n", ""), 13, 
+                Indentation(String::new())),
+            // No arms at all
+            (format!("{}    match in :\n    skip\n", 
+                src.split("    match").next().unwrap()), 10, 
+                Indentation(String::new())),
+            // Arms that don't agree with the variants
+            (src.replace("      Idle :", "      Z :"), 10, 
+                Other(String::new())),
+            (src.replace("      Idle :", "      Idle(x) :"), 10, 
+                Other(String::new())),
+            (src.replace("      Busy(n) :", "      Busy :"), 12, 
+                Other(String::new())),
+            (src.replace(done, "      Idle :\n        skip\n"), 14, 
+                Duplicate(String::new())),
+            (src.replace("{| A, B : UInt<3> |}[2]", "{| A, A |}[2]"), 6,
+                Duplicate(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn verification_statements() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input a : UInt<8>
+    input en : UInt<1>
+    assert(clock, neq(a, UInt<8>(0)), en, \"a was %d\", a) : a_nonzero
+    assume(clock, en, UInt<1>(1), \"\")
+    cover(clock, eq(a, UInt<8>(3)), en, \"a is three\") : a_three
+    printf(clock, en, \"a=%d\\n\", a) : print_a
+    stop(clock, en, 1) : halt
+    stop(clock, en, 0)
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let stmts = &circuit.modules[0].statements;
+        let StatementKind::Assert(v) = &stmts[0].kind else { 
+            panic!("{:?}", stmts[0]) 
+        };
+        assert_eq!(v.msg, "\"a was %d\"");
+        assert_eq!(v.args.len(), 1);
+        assert_eq!(v.name.as_deref(), Some("a_nonzero"));
+        assert!(matches!(&stmts[1].kind, 
+            StatementKind::Assume(v) if v.name.is_none() && v.args.is_empty()));
+        assert!(matches!(&stmts[2].kind, StatementKind::Cover(_)));
+        assert!(matches!(&stmts[3].kind, 
+            StatementKind::Printf(p) if p.name.as_deref() == Some("print_a")));
+        assert!(matches!(&stmts[4].kind, 
+            StatementKind::Stop(.., Some(name)) if name == "halt"));
+        assert!(matches!(&stmts[5].kind, StatementKind::Stop(.., None)));
+
+        // The enable and message are required
+        let bad = src.replace(", en, \"a is three\"", "");
+        check_err(&bad, 8, ParseErrorKind::ExpectedToken(String::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn reset_registers() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input rst : AsyncReset
+    regreset a : UInt<8>, clock, rst, UInt<8>(1) @[Top.scala 3:5]
+    reg b : UInt<8>, clock with : (reset => (rst, a))
+    reg c : UInt<8>, clock
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let stmts = &circuit.modules[0].statements;
+        let StatementKind::RegReset(a) = &stmts[0].kind else {
+            panic!("expected a regreset, got {:?}", stmts[0].kind);
+        };
+        assert_eq!(a.id, "a");
+        assert_eq!(a.clock.to_string(), "clock");
+        assert_eq!(a.reset.to_string(), "rst");
+        assert_eq!(a.init.to_string(), "UInt<8>(1)");
+        assert!(stmts[0].info.is_some());
+
+        // The legacy syntax is the same register
+        let StatementKind::RegReset(b) = &stmts[1].kind else {
+            panic!("expected a regreset, got {:?}", stmts[1].kind);
+        };
+        assert_eq!(b.reset.to_string(), "rst");
+        assert_eq!(b.init.to_string(), "a");
+        assert!(matches!(stmts[2].kind, StatementKind::Reg(..)));
+
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            // The reset value is missing
+            (src.replace("rst, UInt<8>(1)", "rst"), 5, 
+                ExpectedToken(String::new())),
+            (src.replace("clock, rst, UInt<8>(1)", "clock with : rst"), 5,
+                ExpectedToken(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn versions() -> Result<(), FirrtlParseError> {
+        let src = "\
+FIRRTL version 2.0.0
+circuit Top :
+  module Top :
+    input clock : Clock
+    input a : UInt<8>
+    output b : UInt<8>
+    reg r : UInt<8>, clock with : (reset => (a, UInt<8>(0)))
+    b <= r
+    r is invalid
+";
+        let parse = |src: &str| FirrtlFile::from_str("Top.fir", src)?.parse();
+        let circuit = parse(src)?;
+        assert_eq!(circuit.version, Some(Version::new(2, 0, 0)));
+        let stmts = &circuit.modules[0].statements;
+        assert!(matches!(stmts[0].kind, StatementKind::RegReset(_)));
+        assert!(matches!(stmts[1].kind, StatementKind::Connect(..)));
+
+        // The legacy syntax was removed in 3.0.0, but anything goes when 
+        // there is no version
+        let err = parse(&src.replace("2.0.0", "3.0.0")).unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::Unsupported(_)), "{}", err);
+        assert_eq!(err.line, 7);
+        let circuit = parse(&src.replacen("FIRRTL version 2.0.0\n", "", 1))?;
+        assert_eq!(circuit.version, None);
+
+        // Newer syntax is rejected in older versions
+        let modern = "\
+FIRRTL version 4.0.0
+circuit Top :
+  public module Top :
+    input a : UInt<8>
+    output b : UInt<8>
+    connect b, a
+    node t = intrinsic(circt_plusargs_test<FORMAT = \"foo\"> : UInt<1>)
+";
+        parse(modern)?;
+        for (version, line) in [("3.3.0", 7), ("3.0.0", 3), ("2.0.0", 3)] {
+            let err = parse(&modern.replace("4.0.0", version)).unwrap_err();
+            assert!(matches!(err.kind, ParseErrorKind::Unsupported(_)), 
+                "{}: {}", version, err);
+            assert_eq!(err.line, line, "{}", version);
+        }
+        let err = parse(&modern.replace("4.0.0", "2.0.0")
+            .replace("public ", "")).unwrap_err();
+        assert_eq!(err.line, 6);
+
+        // Probes were added in 2.0.0
+        let probes = "\
+FIRRTL version 2.0.0
+circuit Top :
+  module Top :
+    input a : UInt<1>
+    output p : Probe<UInt<1>>
+    define p = probe(a)
+";
+        let circuit = parse(probes)?;
+        let v1 = Version::new(1, 0, 0);
+        let err = parse(&probes.replace("2.0.0", "1.0.0")).unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::Unsupported(_)), "{}", err);
+        assert_eq!(err.line, 5);
+        let err = parse(&probes.replace("2.0.0", "1.0.0")
+            .replace("Probe<UInt<1>>", "UInt<1>")).unwrap_err();
+        assert_eq!(err.line, 6);
+        assert_eq!(crate::version::check_circuit(&circuit, v1).unwrap_err()
+            .feature, crate::version::Feature::Probes);
+
+        // The version must be well-formed
+        for bad in ["4.0", "4", "four", "4.0.0.0", "4.0.x"] {
+            let bad = modern.replace("4.0.0", bad);
+            assert_eq!(parse(&bad).unwrap_err().line, 1, "{}", bad);
+        }
+        let err = parse(&modern.replace("4.0.0", "4.0")).unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::InvalidLiteral(_)), 
+            "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn postfix_exprs() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input c : UInt<1>
+    input i : UInt<2>
+    output o : UInt<1>[4]
+    wire agg : { a : UInt<1>, flip b : UInt<1> }
+    wire vec : { b : UInt<1> }[4][2]
+    inst sub of Top
+    connect o[0], read(probe(agg)).b
+    connect o[1], vec[i][1].b
+    connect o[2], mux(c, vec[0], vec[1])[i].b
+    connect o[3], sub.o[2]
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let stmts = &circuit.modules[0].statements;
+        let exprs: Vec<&Expr> = stmts[3..].iter().map(|s| match &s.kind {
+            StatementKind::Connect(_, e) => e,
+            kind => panic!("expected a connect, got {:?}", kind),
+        }).collect();
+
+        let Expr::Subfield(e, field) = exprs[0] else { panic!() };
+        assert!(matches!(**e, Expr::Read(RefExpr::Probe(_))));
+        assert_eq!(field, "b");
+
+        let Expr::Subfield(e, _) = exprs[1] else { panic!() };
+        let Expr::Subindex(e, 1) = &**e else { panic!() };
+        assert!(matches!(**e, Expr::Ref(Reference::DynamicIndex(..))));
+
+        let Expr::Subfield(e, _) = exprs[2] else { panic!() };
+        let Expr::Subaccess(e, idx) = &**e else { panic!() };
+        assert!(matches!(**e, Expr::Mux(..)));
+        assert_eq!(idx.to_string(), "i");
+
+        // Static references are still static references
+        assert!(matches!(exprs[3], Expr::Ref(Reference::Static(_))));
+
+        // Nothing is silently dropped from the end of a statement
+        use ParseErrorKind::*;
+        for (bad, kind) in [
+            ("read(probe(agg)).", ExpectedToken(String::new())),
+            ("read(probe(agg))[i", ExpectedPunctuation(String::new())),
+            ("vec[i] b", ExpectedEndOfLine),
+        ] {
+            check_err(&src.replace("read(probe(agg)).b", bad), 9, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn const_types() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input cfg : const UInt<4>
+    input sel : const UInt<1>[2]
+    input io : { a : const UInt<1>, flip b : UInt<1> }
+    output o : const { a : UInt<1> }[2]
+    wire w : const SInt<8>
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let ports = &circuit.modules[0].ports;
+        assert_eq!(ports[0].ty,
+            FirrtlType::Const(Box::new(
+                FirrtlType::Ground(FirrtlTypeGround::UInt(Some(4))))));
+
+        // The qualifier applies to the whole vector
+        let FirrtlType::Const(ty) = &ports[1].ty else { panic!() };
+        assert!(matches!(**ty, FirrtlType::Vector(_, 2)));
+
+        let FirrtlType::Bundle(fields) = &ports[2].ty else { panic!() };
+        assert!(fields[0].ty.is_const() && !fields[1].ty.is_const());
+        assert!(ports[3].ty.is_const());
+
+        // 'const' was added in 3.0.0
+        use crate::version::{check_circuit, Feature};
+        let v2 = Version::new(2, 0, 0);
+        let old = format!("FIRRTL version 2.0.0\n{}", src);
+        let err = FirrtlFile::from_str("Top.fir", &old)?.parse().unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::Unsupported(_)), "{}", err);
+        assert_eq!(err.line, 4);
+        FirrtlFile::from_str("Top.fir", &old.replace("2.0.0", "3.0.0"))?
+            .parse()?;
+        assert_eq!(check_circuit(&circuit, v2).unwrap_err().feature,
+            Feature::Const);
+        Ok(())
+    }
+
+    #[test]
+    fn chirrtl_memories() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input addr : UInt<4>
+    cmem c : UInt<8>[16]
+    smem s : UInt<8>[16], new
+    smem t : { a : UInt<1> }[4]
+    infer mport x = c[addr], clock
+    rdwr mport y = s[add(addr, UInt<4>(1))], clock
+    connect x, y
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let stmts = &circuit.modules[0].statements;
+        assert_eq!(stmts[0].kind, StatementKind::ChirrtlMem(
+            ChirrtlMemDecl::new("c", 
+                FirrtlType::Ground(FirrtlTypeGround::UInt(Some(8))), 16, 
+                ChirrtlMemKind::Comb)));
+        assert!(matches!(&stmts[1].kind, StatementKind::ChirrtlMem(m) 
+            if m.kind == ChirrtlMemKind::Seq(Some(ReadUnderWrite::New))));
+        assert!(matches!(&stmts[2].kind, StatementKind::ChirrtlMem(m) 
+            if m.kind == ChirrtlMemKind::Seq(None) && m.depth == 4));
+        assert!(matches!(&stmts[4].kind, StatementKind::MemPort(p) 
+            if p.dir == MemPortDir::ReadWrite && p.mem == "s"));
+
+        // The memory must have a vector type, and a valid read-under-write
+        use ParseErrorKind::*;
+        for (bad, line, kind) in [
+            (src.replace("UInt<8>[16], new", "UInt<8>"), 6, 
+                ExpectedToken(String::new())),
+            (src.replace("], new", "], sometimes"), 6, 
+                UnexpectedKeyword(String::new())),
+            (src.replace("infer mport", "infer port"), 8, 
+                ExpectedKeyword(String::new())),
+        ] {
+            check_err(&bad, line, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn extmodule_refs() -> Result<(), FirrtlParseError> {
+        let src = "\
+circuit Top :
+  extmodule RAM :
+    input addr : UInt<8>
+    output rdata : Probe<UInt<8>>
+    output io : { flip en : UInt<1>, state : RWProbe<UInt<2>> }
+    ref rdata is \"ram.mem.rdata\"
+    ref io.state is \"ram.fsm.state\"
+  module Top :
+    skip
+";
+        let circuit = FirrtlFile::from_str("Top.fir", src)?.parse()?;
+        let ram = &circuit.extmodules[0];
+        assert_eq!(ram.refs.len(), 2);
+        assert_eq!(ram.probe_path("rdata"), Some("ram.mem.rdata"));
+        assert_eq!(ram.probe_path("io.state"), Some("ram.fsm.state"));
+        assert_eq!(ram.probe_path("addr"), None);
+
+        // References to missing, non-probe, or already-declared ports
+        for (line, kind) in [
+            ("    ref nope is \"x\"", "Missing"), 
+            ("    ref addr is \"x\"", "Other"),
+            ("    ref rdata is \"x\"", "Duplicate"),
+        ] {
+            let bad = src.replace("  module Top :", 
+                &format!("{}\n  module Top :", line));
+            let err = FirrtlFile::from_str("Top.fir", &bad)?.parse()
+                .unwrap_err();
+            assert!(format!("{:?}", err.kind).starts_with(kind), 
+                "{}: {:?}", line, err);
+            assert_eq!(err.line, 8);
+        }
+
+        // Unknown declarations are an error rather than a hang
+        let bad = src.replace("    ref io", "    frob io");
+        check_err(&bad, 7, ParseErrorKind::UnexpectedKeyword(String::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_literals() {
+        use crate::ast::*;
+        let lit = |e: &str| -> Result<LiteralNumeric, FirrtlParseError> {
+            let src = format!("circuit Top :\n  module Top :\n    \
+                               node n = {}\n", e);
+            let circuit = FirrtlFile::from_str("lit.fir", &src)?.parse()?;
+            match &circuit.modules[0].statements[0].kind {
+                StatementKind::Node(_, Expr::Const(_, lit)) => Ok(lit.clone()),
+                kind => panic!("unexpected {:?}", kind),
+            }
+        };
+        let uint = |v: BigUint| LiteralNumeric::UInt(v);
+        let sint = |v: i64| LiteralNumeric::SInt(BigInt::from(v));
+
+        let wide: BigUint = (BigUint::from(1u32) << 128u32) - 1u32;
+        let hex = format!("UInt<128>(\"h{}\")", "F".repeat(32));
+        assert_eq!(lit(&hex).unwrap(), uint(wide.clone()));
+        let hex = format!("UInt<128>(0h{})", "f".repeat(32));
+        assert_eq!(lit(&hex).unwrap(), uint(wide));
+        assert_eq!(lit("UInt<4>(0b1010)").unwrap(), uint(10u32.into()));
+        assert_eq!(lit("UInt<3>(\"o7\")").unwrap(), uint(7u32.into()));
+        assert_eq!(lit("UInt(123456789012345678901234567890)").unwrap(), 
+            uint("123456789012345678901234567890".parse().unwrap()));
+        assert_eq!(lit("SInt<8>(\"h-80\")").unwrap(), sint(-128));
+        assert_eq!(lit("SInt<8>(-0h80)").unwrap(), sint(-128));
+        assert_eq!(lit("SInt<8>(+127)").unwrap(), sint(127));
+        assert_eq!(lit("SInt<1>(-1)").unwrap(), sint(-1));
+        assert_eq!(lit("SInt<0>(0)").unwrap(), sint(0));
+
+        // Values that overflow their declared width
+        for e in ["UInt<4>(16)", "UInt<4>(0h10)", "SInt<8>(128)", 
+                  "SInt<8>(-129)", "SInt<1>(1)", "UInt<64>(0h1ffffffffffffffff)"]
+        {
+            let err = lit(e).unwrap_err();
+            assert!(matches!(err.kind, ParseErrorKind::InvalidLiteral(_)), 
+                "{}: {:?}", e, err);
+        }
+        // Malformed values
+        for e in ["UInt(-1)", "UInt(\"hXY\")", "SInt(\"-h-1\")", 
+                  "UInt(\"1\")"]
+        {
+            assert!(lit(e).is_err(), "{}", e);
+        }
+        // Errors refer to the literal as it was written
+        let msg = |e| lit(e).unwrap_err().kind.message();
+        assert_eq!(msg("UInt<4>(-1)"), "Invalid literal: '-1'");
+        assert_eq!(msg("UInt<3>(\"b102\")"), "Invalid literal: '\"b102\"'");
+    }
+
+    #[test]
+    fn truncated_inputs() {
+        for (idx, _) in SAMPLE.char_indices() {
+            let _ = parse_no_panic(&SAMPLE[..idx]);
+        }
+    }
+
+    #[test]
+    fn mangled_inputs() {
+        let lines: Vec<&str> = SAMPLE.lines().collect();
+        for (lnum, line) in lines.iter().enumerate() {
+            let words: Vec<&str> = line.split(' ').collect();
+            let mut variants = Vec::new();
+
+            // Drop each word from this line
+            for widx in 0..words.len() {
+                let mut w = words.clone();
+                w.remove(widx);
+                variants.push(w.join(" "));
+            }
+            // Swap adjacent words on this line
+            for widx in 1..words.len() {
+                let mut w = words.clone();
+                w.swap(widx - 1, widx);
+                variants.push(w.join(" "));
+            }
+            // Change the indentation of this line
+            variants.push(line.trim_start().to_string());
+            variants.push(format!("  {}", line));
+            // Duplicate this line
+            variants.push(format!("{}\n{}", line, line));
+            // Drop this line
+            variants.push(String::new());
+
+            for v in variants {
+                let mut mangled = lines.clone();
+                mangled[lnum] = &v;
+                let _ = parse_no_panic(&mangled.join("\n"));
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 
 use crate::ast::*;
+use crate::version::Feature;
 use crate::lex::*;
 use crate::parse::FirrtlParser;
 
@@ -9,11 +10,11 @@ impl <'a> FirrtlParser {
     {
         let width = if stream.match_punc("<").is_ok() {
             stream.next_token();
-            let w = stream.get_lit_int()?;
+            let w = stream.get_lit_usize()?;
             stream.next_token();
             stream.match_punc(">")?;
             stream.next_token();
-            Some(w.parse::<usize>().unwrap())
+            Some(w)
         } else { 
             None
         };
@@ -69,42 +70,163 @@ impl <'a> FirrtlParser {
         Ok(FirrtlType::Bundle(fields))
     }
 
-    pub fn parse_type(stream: &mut FirrtlStream<'a>) 
+    /// Parse an enumeration type (ie. `{| A, B : UInt<8> |}`).
+    pub fn parse_enum(stream: &mut FirrtlStream<'a>) 
         -> Result<FirrtlType, FirrtlParseError>
     {
-        // Probe/RWProbe
-        if stream.match_identkw("Probe").is_ok() {
+        stream.match_punc("{|")?;
+        stream.require(Feature::Enums)?;
+        stream.next_token();
+
+        let mut variants = Vec::new();
+        loop {
+            if stream.match_punc("|}").is_ok() {
+                stream.next_token();
+                break;
+            }
+            let id = stream.get_identkw()?;
+            if variants.iter().any(|v: &EnumVariant| v.id == id) {
+                return Err(stream.error(
+                    ParseErrorKind::Duplicate(format!("variant {}", id))
+                ));
+            }
             stream.next_token();
-            stream.match_punc("<")?;
+            let ty = if stream.match_punc(":").is_ok() {
+                stream.next_token();
+                Some(FirrtlParser::parse_type(stream)?)
+            } else {
+                None
+            };
+            variants.push(EnumVariant::new(id, ty));
+        }
+        Ok(FirrtlType::Enum(variants))
+    }
+
+    /// Parse the path to a (possibly nested) layer, ie. `A.B`.
+    pub fn parse_layer_path(stream: &mut FirrtlStream<'a>)
+        -> Result<String, FirrtlParseError>
+    {
+        let mut path = stream.get_identkw()?.to_string();
+        stream.next_token();
+        while !stream.is_sol() && stream.match_punc(".").is_ok() {
             stream.next_token();
-            let prb_type = FirrtlParser::parse_type(stream)?;
-            stream.match_punc(">")?;
+            path.push('.');
+            path.push_str(stream.get_identkw()?);
             stream.next_token();
-            Ok(FirrtlType::Ref(FirrtlTypeRef::Probe(Box::new(prb_type))))
+        }
+        Ok(path)
+    }
+
+    /// Parse a property type (ie. `Integer` or `List<Inst<Foo>>`).
+    pub fn parse_property_type(stream: &mut FirrtlStream<'a>) 
+        -> Result<PropertyType, FirrtlParseError>
+    {
+        let kw = stream.match_identkw_multi(&[
+            "Integer", "String", "Bool", "Double", "Path", "AnyRef", "List", 
+            "Inst"
+        ]).map_err(|_| {
+            let name = stream.get_identkw().unwrap_or_default();
+            stream.error(ParseErrorKind::UnknownType(name.to_string()))
+        })?;
+        stream.require(Feature::Properties)?;
+        stream.next_token();
+        let ty = match kw {
+            "Integer" => PropertyType::Integer,
+            "String" => PropertyType::String,
+            "Bool" => PropertyType::Bool,
+            "Double" => PropertyType::Double,
+            "Path" => PropertyType::Path,
+            "AnyRef" => PropertyType::AnyRef,
+            "List" => {
+                stream.match_punc("<")?;
+                stream.next_token();
+                let elem = FirrtlParser::parse_property_type(stream)?;
+                stream.match_punc(">")?;
+                stream.next_token();
+                PropertyType::List(Box::new(elem))
+            },
+            _ => {
+                stream.match_punc("<")?;
+                stream.next_token();
+                let class = stream.get_identkw()?;
+                stream.next_token();
+                stream.match_punc(">")?;
+                stream.next_token();
+                PropertyType::Inst(class.to_string())
+            },
+        };
+        Ok(ty)
+    }
+
+    /// Returns 'true' if the current token begins a property type.
+    pub fn check_property_type(stream: &FirrtlStream<'a>) -> bool {
+        stream.match_identkw_multi(&[
+            "Integer", "String", "Bool", "Double", "Path", "AnyRef", "List", 
+            "Inst"
+        ]).is_ok()
+    }
+
+    /// Returns 'true' if the current token is a previously-declared type 
+    /// alias.
+    pub fn check_type_alias(stream: &FirrtlStream<'a>) -> bool {
+        stream.get_identkw().is_ok_and(|id| stream.type_alias(id).is_some())
+    }
 
-        } else if stream.match_identkw("RWProbe").is_ok() {
+    pub fn parse_type(stream: &mut FirrtlStream<'a>) 
+        -> Result<FirrtlType, FirrtlParseError>
+    {
+        // Probe/RWProbe, with an optional layer color
+        if let Ok(kw) = stream.match_identkw_multi(&["Probe", "RWProbe"]) {
+            stream.require(Feature::Probes)?;
             stream.next_token();
             stream.match_punc("<")?;
             stream.next_token();
-            let prb_type = FirrtlParser::parse_type(stream)?;
+            let prb_type = Box::new(FirrtlParser::parse_type(stream)?);
+            let layer = if stream.match_punc(">").is_err() {
+                stream.require(Feature::Layers)?;
+                Some(FirrtlParser::parse_layer_path(stream)?)
+            } else {
+                None
+            };
             stream.match_punc(">")?;
             stream.next_token();
-            Ok(FirrtlType::Ref(FirrtlTypeRef::RWProbe(Box::new(prb_type))))
+            if kw == "Probe" {
+                Ok(FirrtlType::Ref(FirrtlTypeRef::Probe(prb_type, layer)))
+            } else {
+                Ok(FirrtlType::Ref(FirrtlTypeRef::RWProbe(prb_type, layer)))
+            }
         } 
+        // Property types (which never have a vector suffix)
+        else if FirrtlParser::check_property_type(stream) {
+            let ty = FirrtlParser::parse_property_type(stream)?;
+            Ok(FirrtlType::Property(ty))
+        }
         // Otherwise, this is a ground/aggregate type
         else {
             let is_const = if stream.match_identkw("const").is_ok() {
+                stream.require(Feature::Const)?;
                 stream.next_token();
                 true
             } else {
                 false
             };
 
-            // Either a bundle, or a ground type
+            // Either a bundle, an enumeration, a type alias, or a ground type
             let res_type = if stream.match_punc("{").is_ok() {
                 FirrtlParser::parse_bundle(stream)?
+            } else if stream.match_punc("{|").is_ok() {
+                FirrtlParser::parse_enum(stream)?
+            } else if FirrtlParser::check_type_alias(stream) {
+                let name = stream.get_identkw()?;
+                stream.next_token();
+                FirrtlType::Alias(name.to_string())
             } else {
-                let ground_type = stream.get_identkw()?;
+                let ground_type = stream.match_identkw_multi(&[
+                    "UInt", "SInt", "Analog", "Clock", "Reset", "AsyncReset"
+                ]).map_err(|_| {
+                    let name = stream.get_identkw().unwrap_or_default();
+                    stream.error(ParseErrorKind::UnknownType(name.to_string()))
+                })?;
                 stream.next_token();
                 let width = FirrtlParser::parse_optional_typewidth(stream)?;
                 match ground_type { 
@@ -120,21 +242,27 @@ impl <'a> FirrtlParser {
                         FirrtlType::Ground(FirrtlTypeGround::Reset),
                     "AsyncReset" => 
                         FirrtlType::Ground(FirrtlTypeGround::AsyncReset),
-                    _ => panic!("unknown ground type?"),
+                    _ => return Err(stream.error(
+                        ParseErrorKind::UnknownType(ground_type.to_string())
+                    )),
                 }
             };
 
-            // This is an array of 'res_type'
-            if stream.match_punc("[").is_ok() {
+            // This is an array of 'res_type' (possibly nested, ie. 
+            // 'UInt<1>[2][3]')
+            let mut res_type = res_type;
+            while stream.match_punc("[").is_ok() {
                 stream.next_token();
-                let width = stream.get_lit_int()?;
+                let width = stream.get_lit_usize()?;
                 stream.next_token();
                 stream.match_punc("]")?;
                 stream.next_token();
-                Ok(FirrtlType::Vector(Box::new(res_type), width.parse().unwrap()))
-            } else {
-                Ok(res_type)
+                res_type = FirrtlType::Vector(Box::new(res_type), width);
+            }
+            if is_const {
+                res_type = FirrtlType::Const(Box::new(res_type));
             }
+            Ok(res_type)
         }
     }
 }
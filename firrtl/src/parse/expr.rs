@@ -1,5 +1,6 @@
 
 use crate::ast::*;
+use crate::version::Feature;
 use crate::lex::*;
 use crate::token::Token;
 use crate::parse::FirrtlParser;
@@ -49,7 +50,8 @@ impl <'a> FirrtlParser {
                 PrimOp1Expr1Int::from_str(kw).is_some() ||
                 PrimOp1Expr2Int::from_str(kw).is_some()
             );
-            let has_lparen = stream.peekn_token(1).match_punc("(")
+            let has_lparen = stream.peekn_token(1)
+                .and_then(|t| t.match_punc("("))
                 .unwrap_or(false);
             kw_ok && has_lparen
         } else {
@@ -61,7 +63,8 @@ impl <'a> FirrtlParser {
     pub fn check_mux_expr(stream: &FirrtlStream<'a>) -> bool {
         if stream.remaining_tokens().len() >= 2 {
             stream.match_identkw("mux").is_ok() &&
-                stream.peekn_token(1).match_punc("(").unwrap_or(false)
+                stream.peekn_token(1).and_then(|t| t.match_punc("("))
+                    .unwrap_or(false)
         } else {
             false
         }
@@ -71,12 +74,50 @@ impl <'a> FirrtlParser {
     pub fn check_read_expr(stream: &FirrtlStream<'a>) -> bool {
         if stream.remaining_tokens().len() >= 2 {
             stream.match_identkw("read").is_ok() &&
-                stream.peekn_token(1).match_punc("(").unwrap_or(false)
+                stream.peekn_token(1).and_then(|t| t.match_punc("("))
+                    .unwrap_or(false)
         } else {
             false
         }
     }
 
+    /// Returns 'true' if the current tokens qualify as an enumeration 
+    /// expression.
+    pub fn check_enum_expr(stream: &FirrtlStream<'a>) -> bool {
+        let has_lparen = stream.peekn_token(1)
+            .and_then(|t| t.match_punc("("))
+            .unwrap_or(false);
+        stream.match_punc("{|").is_ok() || 
+            (FirrtlParser::check_type_alias(stream) && has_lparen)
+    }
+
+    /// Returns 'true' if the current tokens qualify as a property expression
+    /// (a property literal, a list, or a property primop).
+    pub fn check_property_expr(stream: &FirrtlStream<'a>) -> bool {
+        let Ok(kw) = stream.get_identkw() else {
+            return false;
+        };
+        let is_punc = |n: usize, p: &str| {
+            stream.peekn_token(n).and_then(|t| t.match_punc(p))
+                .unwrap_or(false)
+        };
+        match kw {
+            "Integer" | "Bool" | "Double" | "String" | "Path" => {
+                is_punc(1, "(")
+            },
+            "List" => is_punc(1, "<"),
+            kw => PropPrimOp::from_str(kw).is_some() && is_punc(1, "("),
+        }
+    }
+
+    /// Returns 'true' if the current tokens qualify as an intrinsic 
+    /// expression.
+    pub fn check_intrinsic_expr(stream: &FirrtlStream<'a>) -> bool {
+        stream.match_identkw("intrinsic").is_ok() &&
+            stream.peekn_token(1).and_then(|t| t.match_punc("("))
+                .unwrap_or(false)
+    }
+
     /// Returns 'true' if the current tokens qualify as a constant expression.
     pub fn check_const_expr(stream: &FirrtlStream<'a>) -> bool {
         let Ok(maybe_keyword) = stream.get_identkw() else {
@@ -86,19 +127,19 @@ impl <'a> FirrtlParser {
             let kw_ok = (
                 (maybe_keyword == "UInt" || maybe_keyword == "SInt")
             );
+            let is_punc = |n: usize, p: &str| {
+                stream.peekn_token(n).and_then(|t| t.match_punc(p))
+                    .unwrap_or(false)
+            };
             let has_width = (
-                if stream.peekn_token(1).match_punc("<").unwrap_or(false) {
-                    if stream.peekn_token(2).is_lit_int() {
-                        if stream.peekn_token(3).match_punc(">")
-                            .unwrap_or(false) { true }
-                        else { false }
-                    } else { false }
-                } else { false }
+                is_punc(1, "<") &&
+                stream.peekn_token(2).is_some_and(|t| t.is_lit_int()) &&
+                is_punc(3, ">")
             );
             let has_lparen = if has_width {
-                stream.peekn_token(4).match_punc("(").unwrap_or(false)
+                is_punc(4, "(")
             } else { 
-                stream.peekn_token(1).match_punc("(").unwrap_or(false)
+                is_punc(1, "(")
             };
             kw_ok && has_lparen
         } else {
@@ -106,14 +147,8 @@ impl <'a> FirrtlParser {
         }
     }
 
-    // FIXME: Do we need to handle postfix operations (indexing/subfields)
-    // for arbitrary expressions? I don't think this is in the spec, but
-    // there's at least one case in CIRCT tests, ie. 
-    //
-    //  wire agg2 : { a : UInt, flip b : UInt<1> }
-    //  ...
-    //  out2 <= read(probe(agg2)).b
-    //
+    /// Parse an expression, including any subfield/subindex/subaccess 
+    /// operations that follow it (ie. `read(probe(agg)).b`).
     pub fn parse_expr(stream: &mut FirrtlStream<'a>)
         -> Result<Expr, FirrtlParseError>
     {
@@ -128,30 +163,93 @@ impl <'a> FirrtlParser {
             ))
         }
 
-        if FirrtlParser::check_primop_expr(stream) {
-            let primop_expr = FirrtlParser::parse_primop_expr(stream)?;
-            return Ok(primop_expr);
+        let expr = if FirrtlParser::check_primop_expr(stream) {
+            FirrtlParser::parse_primop_expr(stream)?
         }
         else if FirrtlParser::check_const_expr(stream) {
-            let const_expr = FirrtlParser::parse_const_expr(stream)?;
-            return Ok(const_expr);
+            FirrtlParser::parse_const_expr(stream)?
+        }
+        else if FirrtlParser::check_property_expr(stream) {
+            FirrtlParser::parse_property_expr(stream)?
+        }
+        else if FirrtlParser::check_enum_expr(stream) {
+            FirrtlParser::parse_enum_expr(stream)?
         }
         else if FirrtlParser::check_mux_expr(stream) {
-            let mux_expr = FirrtlParser::parse_mux_expr(stream)?;
-            return Ok(mux_expr);
+            FirrtlParser::parse_mux_expr(stream)?
         }
         else if FirrtlParser::check_read_expr(stream) {
-            let read_expr = FirrtlParser::parse_read_expr(stream)?;
-            return Ok(read_expr);
+            FirrtlParser::parse_read_expr(stream)?
         } 
+        else if FirrtlParser::check_intrinsic_expr(stream) {
+            let intr = FirrtlParser::parse_intrinsic(stream)?;
+            if intr.ty.is_none() {
+                return Err(stream.error(ParseErrorKind::Missing(
+                    format!("result type for intrinsic '{}'", intr.name)
+                )));
+            }
+            Expr::Intrinsic(intr)
+        }
         else if FirrtlParser::check_reference(stream) {
-            let reference = FirrtlParser::parse_reference(stream)?;
-            return Ok(Expr::Ref(reference));
+            Expr::Ref(FirrtlParser::parse_reference(stream)?)
         } 
         else {
-            panic!("unable to disambiguate expression? {:?}", 
-                stream.remaining_tokens());
+            return Err(stream.error(
+                ParseErrorKind::ExpectedToken("expression".to_string())
+            ));
+        };
+        FirrtlParser::parse_postfix_ops(stream, expr)
+    }
+
+    /// Apply any subfield (`.a`), subindex (`[0]`) or subaccess (`[e]`) 
+    /// operations on the rest of the current line to `expr`.
+    pub fn parse_postfix_ops(stream: &mut FirrtlStream<'a>, mut expr: Expr)
+        -> Result<Expr, FirrtlParseError>
+    {
+        // Postfix operations never continue on the next line
+        while !stream.is_sol() {
+            if stream.match_punc(".").is_ok() {
+                stream.next_token();
+                if stream.is_sol() {
+                    return Err(stream.error(ParseErrorKind::ExpectedToken(
+                        "field name".to_string()
+                    )));
+                }
+                // Numeric field names in a row (ie. 'a.0.1') are lexed
+                // as a single floating-point literal
+                if let Ok(lit) = stream.get_lit_float() {
+                    for field in lit.split('.') {
+                        expr = expr.subfield(field);
+                    }
+                    stream.next_token();
+                    continue;
+                }
+                let field = if let Ok(lit) = stream.get_lit_int() {
+                    lit
+                } else {
+                    stream.get_identkw()?
+                };
+                stream.next_token();
+                expr = expr.subfield(field);
+            }
+            else if stream.match_punc("[").is_ok() {
+                stream.next_token();
+                if stream.get_lit_int().is_ok() {
+                    let index = stream.get_lit_usize()?;
+                    stream.next_token();
+                    expr = expr.subindex(index);
+                } else {
+                    let index_expr = FirrtlParser::parse_expr(stream)?;
+                    expr = expr.subaccess(index_expr);
+                }
+                stream.match_punc("]")?;
+                stream.next_token();
+            }
+            else {
+                break;
+            }
         }
+        Ok(expr)
     }
 
     pub fn parse_mux_expr(stream: &mut FirrtlStream<'a>) 
@@ -171,10 +269,165 @@ impl <'a> FirrtlParser {
         Ok(Expr::Mux(Box::new(e1), Box::new(e2), Box::new(e3)))
     }
 
+    /// Parse an enumeration expression (ie. `{| A, B : UInt<8> |}(B, x)`),
+    /// where the type may also be an alias for an enumeration type.
+    pub fn parse_enum_expr(stream: &mut FirrtlStream<'a>) 
+        -> Result<Expr, FirrtlParseError>
+    {
+        stream.require(Feature::Enums)?;
+        let ty = if stream.match_punc("{|").is_ok() {
+            FirrtlParser::parse_enum(stream)?
+        } else {
+            let name = stream.get_identkw()?;
+            stream.next_token();
+            FirrtlType::Alias(name.to_string())
+        };
+        stream.match_punc("(")?;
+        stream.next_token();
+
+        let resolved = ty.resolve(&|id| stream.type_alias(id));
+        let variant = stream.get_identkw()?;
+        let Some(v) = resolved.as_ref().and_then(|t| t.enum_variant(variant)) 
+        else {
+            return Err(stream.error(ParseErrorKind::Other(format!(
+                "'{}' is not a variant of {}", variant, ty
+            ))));
+        };
+        let has_data = v.ty.is_some();
+        stream.next_token();
+
+        let data = if has_data {
+            Some(Box::new(FirrtlParser::parse_expr(stream)?))
+        } else {
+            None
+        };
+        stream.match_punc(")")?;
+        stream.next_token();
+        Ok(Expr::Enum(ty, variant.to_string(), data))
+    }
+
+    /// Parse a property expression, ie. `Integer(-1)`, `String("a")`, 
+    /// `List<Integer>(a, b)` or `integer_add(a, b)`.
+    pub fn parse_property_expr(stream: &mut FirrtlStream<'a>) 
+        -> Result<Expr, FirrtlParseError>
+    {
+        let kw = stream.get_identkw()?;
+        stream.require(Feature::Properties)?;
+        stream.next_token();
+        let list_ty = if kw == "List" {
+            stream.match_punc("<")?;
+            stream.next_token();
+            let ty = FirrtlParser::parse_property_type(stream)?;
+            stream.match_punc(">")?;
+            stream.next_token();
+            Some(ty)
+        } else {
+            None
+        };
+        stream.match_punc("(")?;
+        stream.next_token();
+
+        // Lists and primops take any number of property expressions
+        if let Some(ty) = list_ty {
+            let elems = FirrtlParser::parse_expr_list(stream)?;
+            return Ok(Expr::List(ty, elems));
+        }
+        if let Some(op) = PropPrimOp::from_str(kw) {
+            let args = FirrtlParser::parse_expr_list(stream)?;
+            return Ok(Expr::PropPrimOp(op, args));
+        }
+
+        let invalid = |lit: &str| {
+            stream.error(ParseErrorKind::InvalidLiteral(lit.to_string()))
+        };
+        let lit = match kw {
+            "Integer" => match stream.get_signed_numeric_literal()? {
+                LiteralNumeric::SInt(v) => PropertyLiteral::Integer(v),
+                LiteralNumeric::UInt(v) => PropertyLiteral::Integer(v.into()),
+            },
+            "Bool" => match stream.get_identkw()? {
+                "true" => PropertyLiteral::Bool(true),
+                "false" => PropertyLiteral::Bool(false),
+                lit => return Err(invalid(lit)),
+            },
+            "Double" => {
+                let lit = stream.get_lit_float()
+                    .or_else(|_| stream.get_lit_int())
+                    .or_else(|_| stream.get_lit_sint())?;
+                PropertyLiteral::Double(lit.to_string())
+            },
+            "String" => {
+                let lit = stream.get_lit_str()?;
+                PropertyLiteral::String(lit[1..lit.len()-1].to_string())
+            },
+            _ => {
+                let lit = stream.get_lit_str()?;
+                PropertyLiteral::Path(lit[1..lit.len()-1].to_string())
+            },
+        };
+        stream.next_token();
+        stream.match_punc(")")?;
+        stream.next_token();
+        Ok(Expr::Property(lit))
+    }
+
+    /// Parse expressions up to (and including) a closing parenthesis.
+    pub fn parse_expr_list(stream: &mut FirrtlStream<'a>) 
+        -> Result<Vec<Expr>, FirrtlParseError>
+    {
+        let mut exprs = Vec::new();
+        while stream.match_punc(")").is_err() {
+            exprs.push(FirrtlParser::parse_expr(stream)?);
+        }
+        stream.next_token();
+        Ok(exprs)
+    }
+
+    /// Parse an intrinsic (ie. `intrinsic(foo<A = 1> : UInt<1>, a, b)`).
+    ///
+    /// The result type is optional here, since intrinsics used as 
+    /// statements don't have one.
+    pub fn parse_intrinsic(stream: &mut FirrtlStream<'a>) 
+        -> Result<Intrinsic, FirrtlParseError>
+    {
+        stream.match_identkw("intrinsic")?;
+        stream.require(Feature::Intrinsics)?;
+        stream.next_token();
+        stream.match_punc("(")?;
+        stream.next_token();
+        let name = stream.get_identkw()?;
+        stream.next_token();
+
+        let mut params = Vec::new();
+        if stream.match_punc("<").is_ok() {
+            stream.next_token();
+            while stream.match_punc(">").is_err() {
+                let id = stream.get_identkw()?;
+                stream.next_token();
+                stream.match_punc("=")?;
+                stream.next_token();
+                let value = FirrtlParser::parse_param_value(stream)?;
+                stream.next_token();
+                params.push(Parameter::new(id, value));
+            }
+            stream.next_token();
+        }
+
+        let ty = if stream.match_punc(":").is_ok() {
+            stream.next_token();
+            Some(FirrtlParser::parse_type(stream)?)
+        } else {
+            None
+        };
+        let args = FirrtlParser::parse_expr_list(stream)?;
+        Ok(Intrinsic { name: name.to_string(), params, ty, args })
+    }
+
     pub fn parse_read_expr(stream: &mut FirrtlStream<'a>) 
         -> Result<Expr, FirrtlParseError>
     {
         stream.match_identkw("read")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         stream.match_punc("(")?;
         stream.next_token();
@@ -205,22 +458,22 @@ impl <'a> FirrtlParser {
         } 
         else if let Some(op) = PrimOp1Expr1Int::from_str(primop_kw) {
             let e1 = FirrtlParser::parse_expr(stream)?;
-            let lit1 = stream.get_lit_int()?;
+            let lit1 = stream.get_lit_usize()?;
             stream.next_token();
-            Expr::PrimOp1Expr1Int(op, Box::new(e1), lit1.parse().unwrap())
+            Expr::PrimOp1Expr1Int(op, Box::new(e1), lit1)
         } 
         else if let Some(op) = PrimOp1Expr2Int::from_str(primop_kw) {
             let e1 = FirrtlParser::parse_expr(stream)?;
-            let lit1 = stream.get_lit_int()?;
+            let lit1 = stream.get_lit_usize()?;
             stream.next_token();
-            let lit2 = stream.get_lit_int()?;
+            let lit2 = stream.get_lit_usize()?;
             stream.next_token();
-            Expr::PrimOp1Expr2Int(op, Box::new(e1),
-                lit1.parse().unwrap(), lit2.parse().unwrap()
-            )
+            Expr::PrimOp1Expr2Int(op, Box::new(e1), lit1, lit2)
 
         } else {
-            panic!("invalid primop {:?} ?", primop_kw);
+            return Err(stream.error(
+                ParseErrorKind::UnexpectedKeyword(primop_kw.to_string())
+            ));
         };
 
         stream.match_punc(")")?;
@@ -239,22 +492,30 @@ impl <'a> FirrtlParser {
 
         stream.match_punc("(")?;
         stream.next_token();
-        let lit = match kw {
-            "UInt" => stream.token().get_unsigned_numeric_literal(),
-            "SInt" => stream.token().get_signed_numeric_literal(),
-            _ => unreachable!(),
+        let lit = if kw == "UInt" {
+            stream.get_unsigned_numeric_literal()?
+        } else {
+            stream.get_signed_numeric_literal()?
         };
+        // The value must fit in the declared width
+        if let Some(w) = width {
+            if lit.min_width() > w {
+                return Err(stream.error(ParseErrorKind::InvalidLiteral(
+                    format!("{} does not fit in {}<{}>", lit, kw, w)
+                )));
+            }
+        }
         stream.next_token();
         stream.match_punc(")")?;
         stream.next_token();
 
-        let ty = match kw {
-            "UInt" => FirrtlType::Ground(FirrtlTypeGround::UInt(width)),
-            "SInt" => FirrtlType::Ground(FirrtlTypeGround::SInt(width)),
-            _ => unreachable!(),
+        let ty = if kw == "UInt" {
+            FirrtlType::Ground(FirrtlTypeGround::UInt(width))
+        } else {
+            FirrtlType::Ground(FirrtlTypeGround::SInt(width))
         };
 
-        Ok(Expr::Const(ty, lit.unwrap()))
+        Ok(Expr::Const(ty, lit))
     }
 
 
@@ -279,14 +540,27 @@ impl <'a> FirrtlParser {
             // Must be a subfield access
             if stream.match_punc(".").is_ok() {
                 stream.next_token();
+                if stream.is_sol() {
+                    return Err(stream.error(ParseErrorKind::ExpectedToken(
+                        "field name".to_string()
+                    )));
+                }
+                // Numeric field names in a row (ie. 'a.0.1') are lexed
+                // as a single floating-point literal
+                if let Ok(lit) = stream.get_lit_float() {
+                    for field in lit.split('.') {
+                        reference = StaticReference::Subfield(
+                            Box::new(reference), field.to_string()
+                        );
+                    }
+                    stream.next_token();
+                    continue;
+                }
                 // FIXME: SFC behavior allows unsigned integer subfield names?
                 let field = if let Ok(lit) = stream.get_lit_int() {
                     lit
-                } else if let Ok(ident) = stream.get_identkw() {
-                    ident
                 } else {
-                    panic!("invalid token {:?} for subfield name", 
-                           stream.token());
+                    stream.get_identkw()?
                 };
                 stream.next_token();
                 reference = StaticReference::Subfield(
@@ -297,13 +571,12 @@ impl <'a> FirrtlParser {
             else if stream.match_punc("[").is_ok() {
                 // Dynamic indexing always terminates a list of postfix ops,
                 // so we should handle this outside the loop?
-                if !stream.peekn_token(1).is_lit_int() {
+                if !stream.peekn_token(1).is_some_and(|t| t.is_lit_int()) {
                     break;
                 }
                 stream.next_token(); // consume '['
 
-                let subindex = stream.get_lit_int()?;
-                let index    = subindex.parse::<usize>().unwrap();
+                let index = stream.get_lit_usize()?;
                 stream.next_token();
                 stream.match_punc("]")?;
                 stream.next_token();
@@ -348,18 +621,21 @@ impl <'a> FirrtlParser {
         -> Result<RefExpr, FirrtlParseError>
     {
         // This must be 'probe(<static_ref>)' or 'rwprobe(<static_ref>)'
-        if stream.peekn_token(1).match_punc("(").unwrap_or(false) {
+        if stream.peekn_token(1).and_then(|t| t.match_punc("("))
+            .unwrap_or(false) 
+        {
             let kw = stream.match_identkw_multi(&["probe", "rwprobe"])?;
+            stream.require(Feature::Probes)?;
             stream.next_token();
             stream.match_punc("(")?;
             stream.next_token();
             let static_ref = FirrtlParser::parse_static_reference(stream)?;
             stream.match_punc(")")?;
             stream.next_token();
-            match kw {
-                "probe" => Ok(RefExpr::Probe(static_ref)),
-                "rwprobe" => Ok(RefExpr::RwProbe(static_ref)),
-                _ => panic!("unexpected keyword {:?}", kw),
+            if kw == "probe" {
+                Ok(RefExpr::Probe(static_ref))
+            } else {
+                Ok(RefExpr::RwProbe(static_ref))
             }
         } 
         // Otherwise this is just a static reference
@@ -1,6 +1,7 @@
 
 use crate::lex::*;
 use crate::ast::*;
+use crate::version::Feature;
 use crate::parse::FirrtlParser;
 use std::collections::HashMap;
 
@@ -8,46 +9,77 @@ impl <'a> FirrtlParser {
     pub fn parse_module(stream: &mut FirrtlStream<'a>) 
         -> Result<Module, FirrtlParseError> 
     {
+        let module_indent = stream.indent_level();
+        let info = stream.source_info();
+        let public = if stream.match_identkw("public").is_ok() {
+            stream.require(Feature::PublicModules)?;
+            stream.next_token();
+            true
+        } else {
+            false
+        };
         stream.match_identkw("module")?;
         stream.next_token();
         let id = stream.get_identkw()?;
         stream.next_token();
+        let mut module = Module::new(id, Vec::new(), Vec::new());
+        module.public = public;
+        module.info = info;
+
+        while stream.match_identkw("enablelayer").is_ok() {
+            stream.require(Feature::Layers)?;
+            stream.next_token();
+            let layer = FirrtlParser::parse_layer_path(stream)?;
+            module.enabled_layers.push(layer);
+        }
         stream.match_punc(":")?;
         stream.next_token();
+        stream.match_sol()?;
 
-        let body_indent_level = stream.indent_level();
+        // There are no ports or statements
+        if stream.indent_level() <= module_indent {
+            return Ok(module);
+        }
 
-        assert!(stream.is_sol());
-        let ports = FirrtlParser::parse_portlist(stream)?;
-        assert!(stream.is_sol());
+        let body_indent_level = stream.indent_level();
+        module.ports = FirrtlParser::parse_portlist(stream)?;
+        stream.match_sol()?;
 
         // There are no statements
         if stream.indent_level() < body_indent_level {
-            return Ok(Module::new(id.to_string(), ports, Vec::new()));
+            return Ok(module);
         }
 
-        let statements = FirrtlParser::parse_statements_block(stream)?;
-        assert!(stream.is_sol());
+        module.statements = FirrtlParser::parse_statements_block(stream)?;
+        stream.match_sol()?;
 
-        Ok(Module::new(id.to_string(), ports, statements))
+        Ok(module)
     }
 
 
     pub fn parse_intmodule(stream: &mut FirrtlStream<'a>) 
         -> Result<IntModule, FirrtlParseError> 
     {
+        let module_indent = stream.indent_level();
+        let info = stream.source_info();
         stream.match_identkw("intmodule")?;
         stream.next_token();
         let id = stream.get_identkw()?;
         stream.next_token();
         stream.match_punc(":")?;
         stream.next_token();
+        stream.match_sol()?;
+
+        // The body must at least contain the name of the intrinsic
+        if stream.indent_level() <= module_indent {
+            return Err(stream.error(
+                ParseErrorKind::Missing("intrinsic".to_string())
+            ));
+        }
 
         let body_indent_level = stream.indent_level();
-        assert!(stream.is_sol());
         let ports = FirrtlParser::parse_portlist(stream)?;
-        assert!(stream.is_sol());
-        let intmodule = IntModule::new(id, ports);
+        stream.match_sol()?;
 
         stream.match_identkw("intrinsic")?;
         stream.next_token();
@@ -55,12 +87,17 @@ impl <'a> FirrtlParser {
         stream.next_token();
         let intrin_id = stream.get_identkw()?;
         stream.next_token();
+        let mut intmodule = IntModule::new(id, ports, intrin_id);
+        intmodule.info = info;
+
+        stream.match_sol()?;
 
         loop {
             if stream.indent_level() < body_indent_level {
                 break;
             }
             let parameter = FirrtlParser::parse_parameter(stream)?;
+            intmodule.params.push(parameter);
         }
         Ok(intmodule)
 
@@ -69,18 +106,27 @@ impl <'a> FirrtlParser {
     pub fn parse_extmodule(stream: &mut FirrtlStream<'a>) 
         -> Result<ExtModule, FirrtlParseError> 
     {
+        let module_indent = stream.indent_level();
+        let info = stream.source_info();
         stream.match_identkw("extmodule")?;
         stream.next_token();
         let id = stream.get_identkw()?;
         stream.next_token();
         stream.match_punc(":")?;
         stream.next_token();
+        stream.match_sol()?;
+
+        let mut extmodule = ExtModule::new(id, Vec::new());
+        extmodule.info = info;
+
+        // There are no ports or statements
+        if stream.indent_level() <= module_indent {
+            return Ok(extmodule);
+        }
 
         let body_indent_level = stream.indent_level();
-        assert!(stream.is_sol());
-        let ports = FirrtlParser::parse_portlist(stream)?;
-        assert!(stream.is_sol());
-        let extmodule = ExtModule::new(id, ports);
+        extmodule.ports = FirrtlParser::parse_portlist(stream)?;
+        stream.match_sol()?;
 
         // FIXME: These have a definite order in the spec
         loop { 
@@ -92,20 +138,107 @@ impl <'a> FirrtlParser {
             // Start of parameters
             if stream.match_identkw("parameter").is_ok() {
                 let parameter = FirrtlParser::parse_parameter(stream)?;
+                extmodule.params.push(parameter);
             } 
             else if stream.match_identkw("defname").is_ok() {
+                if extmodule.defname.is_some() {
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("defname".to_string())
+                    ));
+                }
                 let defname = FirrtlParser::parse_defname(stream)?;
+                extmodule.defname = Some(defname);
             } 
-            // FIXME: Skip 'ref' declarations for now
             else if stream.match_identkw("ref").is_ok() {
-                stream.next_line();
+                let refdecl = FirrtlParser::parse_refdecl(stream, 
+                    &extmodule)?;
+                extmodule.refs.push(refdecl);
+            } 
+            else {
+                return Err(stream.error(ParseErrorKind::UnexpectedKeyword(
+                    stream.get_identkw()?.to_string()
+                )));
             }
         }
         Ok(extmodule)
     }
 
+    /// Parse a class declaration, which only contains property ports and 
+    /// statements.
+    pub fn parse_class(stream: &mut FirrtlStream<'a>) 
+        -> Result<Class, FirrtlParseError> 
+    {
+        let class_indent = stream.indent_level();
+        let info = stream.source_info();
+        stream.match_identkw("class")?;
+        stream.require(Feature::Classes)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        let mut class = Class::new(id, Vec::new(), Vec::new());
+        class.info = info;
+
+        // There are no ports or statements
+        if stream.indent_level() <= class_indent {
+            return Ok(class);
+        }
+
+        let body_indent_level = stream.indent_level();
+        class.ports = FirrtlParser::parse_portlist(stream)?;
+        stream.match_sol()?;
+
+        // There are no statements
+        if stream.indent_level() < body_indent_level {
+            return Ok(class);
+        }
+
+        class.statements = FirrtlParser::parse_statements_block(stream)?;
+        stream.match_sol()?;
+        Ok(class)
+    }
+
+    /// Parse an external class declaration, which only contains ports.
+    pub fn parse_extclass(stream: &mut FirrtlStream<'a>) 
+        -> Result<ExtClass, FirrtlParseError> 
+    {
+        let class_indent = stream.indent_level();
+        let info = stream.source_info();
+        stream.match_identkw("extclass")?;
+        stream.require(Feature::Classes)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        let mut extclass = ExtClass::new(id, Vec::new());
+        extclass.info = info;
+
+        // There are no ports
+        if stream.indent_level() <= class_indent {
+            return Ok(extclass);
+        }
+
+        let body_indent_level = stream.indent_level();
+        extclass.ports = FirrtlParser::parse_portlist(stream)?;
+        stream.match_sol()?;
+
+        // Anything else in the body is unexpected
+        if stream.indent_level() >= body_indent_level {
+            return Err(stream.error(ParseErrorKind::UnexpectedKeyword(
+                stream.get_identkw()?.to_string()
+            )));
+        }
+        Ok(extclass)
+    }
+
     pub fn parse_defname(stream: &mut FirrtlStream<'a>) 
-        -> Result<(), FirrtlParseError> 
+        -> Result<String, FirrtlParseError> 
     {
         stream.match_identkw("defname")?;
         stream.next_token();
@@ -113,12 +246,50 @@ impl <'a> FirrtlParser {
         stream.next_token();
         let id = stream.get_identkw()?;
         stream.next_token();
-        assert!(stream.is_sol());
-        Ok(())
+        stream.match_sol()?;
+        Ok(id.to_string())
+    }
+
+    /// Parse `ref <port> is "<path>"` for some probe port declared on 
+    /// an extmodule.
+    pub fn parse_refdecl(stream: &mut FirrtlStream<'a>, ext: &ExtModule)
+        -> Result<RefDecl, FirrtlParseError>
+    {
+        stream.match_identkw("ref")?;
+        stream.next_token();
+
+        // This must refer to one of the probe ports
+        let (line, span) = (stream.get_source_line(), 
+            stream.get_source_span());
+        let port = FirrtlParser::parse_static_reference(stream)?;
+        let err = |kind| FirrtlParseError { kind, line, span: span.clone() };
+        let Some(decl) = ext.ports.iter().find(|p| p.id == port.get_ident())
+        else {
+            return Err(err(
+                ParseErrorKind::Missing(format!("port {}", port.get_ident()))
+            ));
+        };
+        if !matches!(decl.ty.reference_type(&port), Some(FirrtlType::Ref(_))) {
+            return Err(err(ParseErrorKind::Other(
+                format!("{} is not a probe port", port)
+            )));
+        }
+        if ext.refs.iter().any(|r| r.port == port) {
+            return Err(err(
+                ParseErrorKind::Duplicate(format!("ref {}", port))
+            ));
+        }
+
+        stream.match_identkw("is")?;
+        stream.next_token();
+        let path = stream.get_lit_str()?;
+        stream.next_token();
+        stream.match_sol()?;
+        Ok(RefDecl::new(port, &path[1..path.len()-1]))
     }
 
     pub fn parse_parameter(stream: &mut FirrtlStream<'a>) 
-        -> Result<(), FirrtlParseError> 
+        -> Result<Parameter, FirrtlParseError> 
     {
         stream.match_identkw("parameter")?;
         stream.next_token();
@@ -127,39 +298,52 @@ impl <'a> FirrtlParser {
         stream.match_punc("=")?;
         stream.next_token();
 
-        if let Ok(lit) = stream.get_lit_int() {
-            stream.next_token();
-            assert!(stream.is_sol());
+        let value = FirrtlParser::parse_param_value(stream)?;
+        stream.next_token();
+        stream.match_sol()?;
+        Ok(Parameter::new(param_id, value))
+    }
+
+    /// Parse the value of a parameter (ie. `1`, `-1`, `1.0`, or `"a"`).
+    pub fn parse_param_value(stream: &mut FirrtlStream<'a>) 
+        -> Result<ParamValue, FirrtlParseError> 
+    {
+        let invalid = |lit: &str| {
+            stream.error(ParseErrorKind::InvalidLiteral(lit.to_string()))
+        };
+        let value = if let Ok(lit) = stream.get_lit_int() {
+            ParamValue::Int(lit.parse().map_err(|_| invalid(lit))?)
         } 
         else if let Ok(lit) = stream.get_lit_sint() {
-            stream.next_token();
-            assert!(stream.is_sol());
+            ParamValue::SInt(lit.parse().map_err(|_| invalid(lit))?)
         }
         else if let Ok(lit) = stream.get_lit_float() {
-            stream.next_token();
-            assert!(stream.is_sol());
+            ParamValue::Float(lit.to_string())
         } 
         else if let Ok(lit) = stream.get_lit_str() {
-            stream.next_token();
-            assert!(stream.is_sol());
+            ParamValue::String(lit[1..lit.len()-1].to_string())
         }
         else if let Ok(lit) = stream.get_lit_raw_str() {
-            stream.next_token();
-            assert!(stream.is_sol());
+            ParamValue::RawString(lit[1..lit.len()-1].to_string())
         } else {
-            unimplemented!("unimpl parameter literal?")
-        }
-        Ok(())
+            return Err(stream.error(ParseErrorKind::ExpectedToken(
+                "parameter value".to_string()
+            )));
+        };
+        Ok(value)
     }
 
     pub fn parse_port(stream: &mut FirrtlStream<'a>)
         -> Result<PortDecl, FirrtlParseError>
     {
+        let info = stream.source_info();
         let dir = match stream.get_identkw()? {
             "input"  => Direction::Input,
             "output" => Direction::Output,
-            _ => { 
-                panic!("invalid port direction?");
+            kw => { 
+                return Err(stream.error(
+                    ParseErrorKind::UnexpectedKeyword(kw.to_string())
+                ));
             },
         };
         stream.next_token();
@@ -169,17 +353,22 @@ impl <'a> FirrtlParser {
         stream.match_punc(":")?;
         stream.next_token();
         let ty = FirrtlParser::parse_type(stream)?;
-        Ok(PortDecl::new(id, dir, ty))
+        stream.add_decl_type(id, ty.clone());
+        let mut port = PortDecl::new(id, dir, ty);
+        port.info = info;
+        Ok(port)
     }
 
     /// Returns 'true' if the current input qualifies as a port declaration.
     pub fn check_port(stream: &mut FirrtlStream<'a>) -> bool {
         let has_dir  = (
-            stream.token().match_identkw("input").unwrap_or(false) || 
-            stream.token().match_identkw("output").unwrap_or(false)
+            stream.match_identkw("input").is_ok() || 
+            stream.match_identkw("output").is_ok()
         );
-        let has_id  = stream.peekn_token(1).is_identkw();
-        let has_col = stream.peekn_token(2).match_punc(":")
+        let has_id  = stream.peekn_token(1)
+            .is_some_and(|t| t.is_identkw());
+        let has_col = stream.peekn_token(2)
+            .and_then(|t| t.match_punc(":"))
             .unwrap_or(false);
         has_dir && has_id && has_col
     }
@@ -190,17 +379,27 @@ impl <'a> FirrtlParser {
         let mut portlist = Vec::new();
         let body_indent_level = stream.indent_level();
         loop {
-            assert!(stream.is_sol());
+            stream.match_sol()?;
             // There are no more port declarations to handle
-            if stream.indent_level() < body_indent_level {
+            if stream.is_eof() || stream.indent_level() < body_indent_level {
                 break;
             }
             // There are no more port declarations to handle
             if !FirrtlParser::check_port(stream) {
                 break;
             }
-            let port = FirrtlParser::parse_port(stream)?;
-            portlist.push(port);
+            let start_line = stream.line_index();
+            let res = FirrtlParser::parse_port(stream).and_then(|port| {
+                stream.match_sol()?;
+                Ok(port)
+            });
+            match res {
+                Ok(port) => portlist.push(port),
+                Err(e) => {
+                    FirrtlParser::recover(stream, e, start_line, 
+                        body_indent_level)?;
+                },
+            }
         }
         Ok(portlist)
     }
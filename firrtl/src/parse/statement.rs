@@ -1,5 +1,6 @@
 
 use crate::ast::*;
+use crate::version::Feature;
 use crate::lex::*;
 use crate::parse::FirrtlParser;
 
@@ -9,29 +10,46 @@ impl <'a> FirrtlParser {
     {
         let mut statements = Vec::new();
         let body_indent_level = stream.indent_level();
-        assert!(stream.is_sol());
+        stream.match_sol()?;
+        if stream.is_eof() {
+            return Err(stream.error(ParseErrorKind::UnexpectedEof));
+        }
         loop {
-            if stream.indent_level() < body_indent_level {
+            if stream.is_eof() || stream.indent_level() < body_indent_level {
                 break;
             }
-            let statement = FirrtlParser::parse_statement(stream)?;
-            statements.push(statement);
-
-            // FIXME: Dynamic field access after a statement?
-            if !stream.is_sol() {
-                if stream.match_punc(".").is_ok() {
-                    stream.next_token();
-                    let dynamic_subfield = stream.get_identkw()?;
-                    stream.next_token();
-                }
+            let start_line = stream.line_index();
+            match FirrtlParser::parse_block_statement(stream) {
+                Ok(statement) => statements.push(statement),
+                Err(e) => {
+                    FirrtlParser::recover(stream, e, start_line, 
+                        body_indent_level)?;
+                },
             }
-            assert!(stream.is_sol());
         }
         Ok(statements)
     }
 
+    /// Parse a statement that must be terminated by the end of a line.
+    pub fn parse_block_statement(stream: &mut FirrtlStream<'a>)
+        -> Result<Statement, FirrtlParseError>
+    {
+        let statement = FirrtlParser::parse_statement(stream)?;
+        stream.match_sol()?;
+        Ok(statement)
+    }
+
+    /// Parse a statement along with any source info on the current line.
     pub fn parse_statement(stream: &mut FirrtlStream<'a>)
         -> Result<Statement, FirrtlParseError>
+    {
+        let info = stream.source_info();
+        let kind = FirrtlParser::parse_statement_kind(stream)?;
+        Ok(Statement::new(kind, info))
+    }
+
+    pub fn parse_statement_kind(stream: &mut FirrtlStream<'a>)
+        -> Result<StatementKind, FirrtlParseError>
     {
         // We have to check for statements that begin with a 'reference'
         // first. Otherwise, this is a "simple" statement where we can 
@@ -47,128 +65,138 @@ impl <'a> FirrtlParser {
         match stream.get_identkw()? {
             "wire" => {
                 let (id, ty) = FirrtlParser::parse_wire_stmt(stream)?;
-                return Ok(Statement::Wire(id, ty));
+                return Ok(StatementKind::Wire(id, ty));
             },
             "reg" => {
-                let (id, ty, clkexpr, rvexpr) = 
-                    FirrtlParser::parse_reg_stmt(stream)?;
-                return Ok(Statement::Reg(id, ty, clkexpr, rvexpr));
+                let reg = FirrtlParser::parse_reg_stmt(stream)?;
+                return Ok(reg);
+            },
+            "regreset" => {
+                let decl = FirrtlParser::parse_regreset_stmt(stream)?;
+                return Ok(StatementKind::RegReset(decl));
             },
             "mem" => {
                 let mem_decl = FirrtlParser::parse_mem_stmt(stream)?;
-                return Ok(Statement::Mem(mem_decl));
+                return Ok(StatementKind::Mem(mem_decl));
             }
             "inst" => { 
                 let (id, mid) = FirrtlParser::parse_inst_stmt(stream)?;
-                return Ok(Statement::Inst(id, mid));
+                return Ok(StatementKind::Inst(id, mid));
+            },
+            "instchoice" => {
+                let choice = FirrtlParser::parse_instchoice_stmt(stream)?;
+                return Ok(StatementKind::InstChoice(choice));
+            },
+            "object" => { 
+                let (id, class) = FirrtlParser::parse_object_stmt(stream)?;
+                return Ok(StatementKind::Object(id, class));
             },
             "node" => { 
                 let (id, expr) = FirrtlParser::parse_node_stmt(stream)?;
-                return Ok(Statement::Node(id, expr));
+                return Ok(StatementKind::Node(id, expr));
             },
             "when" => { 
                 let (expr, ws, es) = FirrtlParser::parse_when_stmt(stream)?;
-                let s = Statement::When(expr, ws, es);
+                let s = StatementKind::When(expr, ws, es);
                 return Ok(s);
             },
+            "layerblock" => { 
+                let (layer, blk) = FirrtlParser::parse_layerblock_stmt(stream)?;
+                return Ok(StatementKind::LayerBlock(layer, blk));
+            },
+            "match" => { 
+                let (expr, arms) = FirrtlParser::parse_match_stmt(stream)?;
+                return Ok(StatementKind::Match(expr, arms));
+            },
 
             "connect" => { 
                 let (refr, expr) = FirrtlParser::parse_connect_stmt(stream)?;
-                return Ok(Statement::Connect(refr, expr));
+                return Ok(StatementKind::Connect(refr, expr));
+            },
+            "propassign" => { 
+                let (sref, expr) = FirrtlParser::parse_propassign_stmt(stream)?;
+                return Ok(StatementKind::PropAssign(sref, expr));
             },
             "invalidate" => { 
                 let refr = FirrtlParser::parse_invalidate_stmt(stream)?;
-                return Ok(Statement::Invalidate(refr));
+                return Ok(StatementKind::Invalidate(refr));
             },
             "attach" => { 
                 let refs = FirrtlParser::parse_attach_stmt(stream)?;
-                return Ok(Statement::Attach(refs));
+                return Ok(StatementKind::Attach(refs));
             },
 
             // NOTE: These are all only relevant to simulation?
             "define" => { 
                 let (sref, rexpr) = FirrtlParser::parse_define_stmt(stream)?;
-                return Ok(Statement::Define(sref, rexpr));
+                return Ok(StatementKind::Define(sref, rexpr));
             },
             "force_initial" => { 
                 let (rexpr, expr) = 
                     FirrtlParser::parse_force_initial_stmt(stream)?;
-                return Ok(Statement::ForceInitial(rexpr, expr));
+                return Ok(StatementKind::ForceInitial(rexpr, expr));
             },
             "release_initial" => { 
                 let rexpr = FirrtlParser::parse_release_initial_stmt(stream)?;
-                return Ok(Statement::ReleaseInitial(rexpr));
+                return Ok(StatementKind::ReleaseInitial(rexpr));
             },
             "force" => { 
                 let (e1, e2, rexpr, e3) = 
                     FirrtlParser::parse_force_stmt(stream)?;
-                return Ok(Statement::Force(e1, e2, rexpr, e3));
+                return Ok(StatementKind::Force(e1, e2, rexpr, e3));
             },
             "release" => { 
                 let (e1, e2, rexpr) = FirrtlParser::parse_release_stmt(stream)?;
-                return Ok(Statement::Release(e1, e2, rexpr));
+                return Ok(StatementKind::Release(e1, e2, rexpr));
             },
             "stop" => { 
-                let (e1, e2, lit) = FirrtlParser::parse_stop_stmt(stream)?;
-                return Ok(Statement::Stop(e1, e2, lit));
+                let (e1, e2, lit, name) = 
+                    FirrtlParser::parse_stop_stmt(stream)?;
+                return Ok(StatementKind::Stop(e1, e2, lit, name));
             },
             "printf" => { 
-                let (clk, cond, fmtstr, args) = 
-                    FirrtlParser::parse_printf_stmt(stream)?;
-                return Ok(Statement::Printf(clk, cond, fmtstr, args));
+                let p = FirrtlParser::parse_printf_stmt(stream)?;
+                return Ok(StatementKind::Printf(p));
             },
 
 
-            // FIXME: These are old SFC statements that I don't want
-            // to deal with right now (is this CHIRRTL?)
-            "cmem" => {
-                stream.next_line(); 
-                return Ok(Statement::Unimplemented("cmem".to_string()));
-            },
-            "smem" => { 
-                stream.next_line(); 
-                return Ok(Statement::Unimplemented("smem".to_string()));
-            },
-            "infer" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("infer".to_string()));
+            // CHIRRTL memories and memory ports
+            "cmem" | "smem" => {
+                let decl = FirrtlParser::parse_chirrtl_mem_stmt(stream)?;
+                return Ok(StatementKind::ChirrtlMem(decl));
             },
-            "read" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("read".to_string()));
-            },
-            "write" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("write".to_string()));
-            },
-            "rdwr" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("rdwr".to_string()));
+            "infer" | "read" | "write" | "rdwr" => {
+                let decl = FirrtlParser::parse_mport_stmt(stream)?;
+                return Ok(StatementKind::MemPort(decl));
             },
 
-            // FIXME: These are verification statements that aren't
-            // properly in the spec yet
             "assert" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("assert".to_string()));
+                let v = FirrtlParser::parse_verification_stmt(stream)?;
+                return Ok(StatementKind::Assert(v));
             },
             "assume" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("assume".to_string()));
+                let v = FirrtlParser::parse_verification_stmt(stream)?;
+                return Ok(StatementKind::Assume(v));
             }, 
             "cover" => {
-                stream.next_line();
-                return Ok(Statement::Unimplemented("cover".to_string()));
+                let v = FirrtlParser::parse_verification_stmt(stream)?;
+                return Ok(StatementKind::Cover(v));
+            },
+            "intrinsic" => {
+                let intr = FirrtlParser::parse_intrinsic(stream)?;
+                return Ok(StatementKind::Intrinsic(intr));
             },
 
             // FIXME: Should probably ignore these in a different way..
             "skip" => { 
                 stream.next_token();
-                return Ok(Statement::Skip);
+                return Ok(StatementKind::Skip);
             },
             // Otherwise, this is an invalid statement
-            identkw @ _ => {
-                panic!("unexpected statement keyword {}", identkw);
+            identkw => {
+                Err(stream.error(
+                    ParseErrorKind::UnexpectedKeyword(identkw.to_string())
+                ))
             },
         }
     }
@@ -183,7 +211,12 @@ impl <'a> FirrtlParser {
         stream.next_token();
         stream.match_punc(":")?;
         stream.next_token();
-        assert!(stream.is_sol() && stream.indent_level() > stmt_blk_level);
+        stream.match_sol()?;
+        if stream.indent_level() <= stmt_blk_level {
+            return Err(stream.error(ParseErrorKind::Indentation(
+                "expected an indented memory declaration".to_string()
+            )));
+        }
 
         // FIXME: In 'parse-basic.fir' from llvm/circt, there are some examples
         // where the order of declarations here does not match the following
@@ -225,56 +258,56 @@ impl <'a> FirrtlParser {
                 stream.next_token();
                 let ty = FirrtlParser::parse_type(stream)?;
                 if datatype.is_some() {
-                    panic!("Multiple data-type statements?");
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("data-type".to_string())
+                    ));
                 } else {
                     datatype = Some(ty);
                 }
             }
-
-            if stream.match_identkw("depth").is_ok() {
+            else if stream.match_identkw("depth").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
-                let val = stream.get_lit_int()?;
+                let val = stream.get_lit_usize()?;
                 stream.next_token();
                 if depth.is_some() {
-                    panic!("Multiple depth statements?");
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("depth".to_string())
+                    ));
                 } else {
-                    let val = val.parse::<usize>().unwrap();
                     depth = Some(val);
                 }
             }
-
-            if stream.match_identkw("read-latency").is_ok() {
+            else if stream.match_identkw("read-latency").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
-                let val = stream.get_lit_int()?;
+                let val = stream.get_lit_usize()?;
                 stream.next_token();
                 if read_latency.is_some() {
-                    panic!("Multiple read-latency statements?");
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("read-latency".to_string())
+                    ));
                 } else {
-                    let val = val.parse::<usize>().unwrap();
                     read_latency = Some(val);
                 }
             }
-
-            if stream.match_identkw("write-latency").is_ok() {
+            else if stream.match_identkw("write-latency").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
-                let val = stream.get_lit_int()?;
+                let val = stream.get_lit_usize()?;
                 stream.next_token();
                 if write_latency.is_some() {
-                    panic!("Multiple write-latency statements?");
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("write-latency".to_string())
+                    ));
                 } else {
-                    let val = val.parse::<usize>().unwrap();
                     write_latency = Some(val);
                 }
-
             }
-
-            if stream.match_identkw("read-under-write").is_ok() {
+            else if stream.match_identkw("read-under-write").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
@@ -282,17 +315,20 @@ impl <'a> FirrtlParser {
                     "old" => ReadUnderWrite::Old,
                     "new" => ReadUnderWrite::New,
                     "undefined" => ReadUnderWrite::Undefined,
-                    _ => panic!("unexpected read-under-write keyword"),
+                    kw => return Err(stream.error(
+                        ParseErrorKind::UnexpectedKeyword(kw.to_string())
+                    )),
                 };
                 stream.next_token();
                 if read_under_write.is_some() {
-                    panic!("Multiple read-under-write statements?");
+                    return Err(stream.error(
+                        ParseErrorKind::Duplicate("read-under-write".to_string())
+                    ));
                 } else {
                     read_under_write = Some(ruw);
                 }
             }
-
-            if stream.match_identkw("reader").is_ok() {
+            else if stream.match_identkw("reader").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
@@ -302,7 +338,7 @@ impl <'a> FirrtlParser {
                     stream.next_token();
                 }
             }
-            if stream.match_identkw("writer").is_ok() {
+            else if stream.match_identkw("writer").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
@@ -312,7 +348,7 @@ impl <'a> FirrtlParser {
                     stream.next_token();
                 }
             }
-            if stream.match_identkw("readwriter").is_ok() {
+            else if stream.match_identkw("readwriter").is_ok() {
                 stream.next_token();
                 stream.match_punc("=>")?;
                 stream.next_token();
@@ -321,23 +357,38 @@ impl <'a> FirrtlParser {
                     rwp_list.push(rwp_id.to_string());
                     stream.next_token();
                 }
+            } 
+            else {
+                return Err(stream.error(ParseErrorKind::UnexpectedKeyword(
+                    stream.get_identkw()?.to_string()
+                )));
             }
         }
 
         let Some(datatype) = datatype else { 
-            panic!("Missing data-type declaration");
+            return Err(stream.error(
+                ParseErrorKind::Missing("data-type".to_string())
+            ));
         };
         let Some(depth) = depth else { 
-            panic!("Missing depth declaration");
+            return Err(stream.error(
+                ParseErrorKind::Missing("depth".to_string())
+            ));
         };
         let Some(read_latency) = read_latency else { 
-            panic!("Missing read-latency declaration");
+            return Err(stream.error(
+                ParseErrorKind::Missing("read-latency".to_string())
+            ));
         };
         let Some(write_latency) = write_latency else { 
-            panic!("Missing write-latency declaration");
+            return Err(stream.error(
+                ParseErrorKind::Missing("write-latency".to_string())
+            ));
         };
         let Some(read_under_write) = read_under_write else { 
-            panic!("Missing read-under-write declaration");
+            return Err(stream.error(
+                ParseErrorKind::Missing("read-under-write".to_string())
+            ));
         };
 
         Ok(MemDecl::new(id, datatype, depth, read_latency, write_latency,
@@ -348,9 +399,10 @@ impl <'a> FirrtlParser {
         ))
     }
 
+    /// Parse a register, which may have a reset in the legacy syntax, ie. 
+    /// `reg r : UInt<8>, clock with : (reset => (rst, UInt<8>(0)))`.
     pub fn parse_reg_stmt(stream: &mut FirrtlStream<'a>)
-        -> Result<(String, FirrtlType, Expr, Option<(Expr, Expr)>), 
-                    FirrtlParseError>
+        -> Result<StatementKind, FirrtlParseError>
     {
         stream.match_identkw("reg")?;
         stream.next_token();
@@ -361,37 +413,127 @@ impl <'a> FirrtlParser {
         stream.next_token();
 
         let ty = FirrtlParser::parse_type(stream)?;
+        stream.add_decl_type(id, ty.clone());
         let clk_expr = FirrtlParser::parse_expr(stream)?;
+        if stream.match_identkw("with").is_err() {
+            return Ok(StatementKind::Reg(id.to_string(), ty, clk_expr));
+        }
+        stream.require(Feature::LegacyRegReset)?;
 
-        let mut reset_val_expr = None;
-        if stream.match_identkw("with").is_ok() {
-            stream.next_token();
-            stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+
+        // Apparently optional parenthesis?
+        if stream.match_punc("(").is_ok() {
             stream.next_token();
+        }
 
-            // Apparently optional parenthesis?
-            if stream.match_punc("(").is_ok() {
-                stream.next_token();
-            }
+        stream.match_identkw("reset")?;
+        stream.next_token();
+        stream.match_punc("=>")?;
+        stream.next_token();
+        stream.match_punc("(")?;
+        stream.next_token();
+        let reset_expr = FirrtlParser::parse_expr(stream)?;
+        let init_expr = FirrtlParser::parse_expr(stream)?;
+        stream.match_punc(")")?;
+        stream.next_token();
 
-            stream.match_identkw("reset")?;
-            stream.next_token();
-            stream.match_punc("=>")?;
-            stream.next_token();
-            stream.match_punc("(")?;
+        // Apparently optional parenthesis?
+        if stream.match_punc(")").is_ok() {
             stream.next_token();
-            let reset_expr = FirrtlParser::parse_expr(stream)?;
-            let val_expr = FirrtlParser::parse_expr(stream)?;
-            reset_val_expr = Some((reset_expr, val_expr));
-            stream.match_punc(")")?;
+        }
+        Ok(StatementKind::RegReset(RegResetDecl::new(id, ty, clk_expr, 
+            reset_expr, init_expr)))
+    }
+
+    /// Parse a register with a reset, ie. 
+    /// `regreset r : UInt<8>, clock, rst, UInt<8>(0)`
+    pub fn parse_regreset_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<RegResetDecl, FirrtlParseError>
+    {
+        stream.match_identkw("regreset")?;
+        stream.require(Feature::RegReset)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.add_module_ctx(id);
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+
+        let ty = FirrtlParser::parse_type(stream)?;
+        stream.add_decl_type(id, ty.clone());
+        let clk_expr = FirrtlParser::parse_expr(stream)?;
+        let reset_expr = FirrtlParser::parse_expr(stream)?;
+        let init_expr = FirrtlParser::parse_expr(stream)?;
+        Ok(RegResetDecl::new(id, ty, clk_expr, reset_expr, init_expr))
+    }
+
+    /// Parse a CHIRRTL memory, ie. `smem m : UInt<8>[16], undefined`
+    pub fn parse_chirrtl_mem_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<ChirrtlMemDecl, FirrtlParseError>
+    {
+        let kw = stream.match_identkw_multi(&["cmem", "smem"])?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+
+        // The element type and depth are given as a vector type
+        let FirrtlType::Vector(ty, depth) = FirrtlParser::parse_type(stream)? 
+        else {
+            return Err(stream.error(ParseErrorKind::ExpectedToken(
+                "vector type".to_string()
+            )));
+        };
+
+        let kind = if kw == "cmem" {
+            ChirrtlMemKind::Comb
+        } else if stream.is_sol() {
+            ChirrtlMemKind::Seq(None)
+        } else {
+            let ruw = stream.get_identkw()?;
+            let Some(ruw) = ReadUnderWrite::from_str(ruw) else {
+                return Err(stream.error(
+                    ParseErrorKind::UnexpectedKeyword(ruw.to_string())
+                ));
+            };
             stream.next_token();
+            ChirrtlMemKind::Seq(Some(ruw))
+        };
+        Ok(ChirrtlMemDecl::new(id, *ty, depth, kind))
+    }
 
-            // Apparently optional parenthesis?
-            if stream.match_punc(")").is_ok() {
-                stream.next_token();
-            }
-        }
-        Ok((id.to_string(), ty, clk_expr, reset_val_expr))
+    /// Parse a CHIRRTL memory port, ie. `read mport r = m[addr], clock`
+    pub fn parse_mport_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<MemPortDecl, FirrtlParseError>
+    {
+        let kw = stream.get_identkw()?;
+        let Some(dir) = MemPortDir::from_str(kw) else {
+            return Err(stream.error(
+                ParseErrorKind::UnexpectedKeyword(kw.to_string())
+            ));
+        };
+        stream.next_token();
+        stream.match_identkw("mport")?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.add_module_ctx(id);
+        stream.next_token();
+        stream.match_punc("=")?;
+        stream.next_token();
+
+        let mem = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc("[")?;
+        stream.next_token();
+        let addr = FirrtlParser::parse_expr(stream)?;
+        stream.match_punc("]")?;
+        stream.next_token();
+        let clock = FirrtlParser::parse_expr(stream)?;
+        Ok(MemPortDecl::new(dir, id, mem, addr, clock))
     }
 
     pub fn parse_inst_stmt(stream: &mut FirrtlStream<'a>)
@@ -411,10 +553,83 @@ impl <'a> FirrtlParser {
         Ok((id.to_string(), module_id.to_string()))
     }
 
+    /// Parse an instance choice along with its cases, ie. 
+    ///
+    /// ```text
+    /// instchoice i of Default, Platform :
+    ///   FPGA => FpgaImpl
+    /// ```
+    pub fn parse_instchoice_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<InstChoice, FirrtlParseError>
+    {
+        let current_indent = stream.indent_level();
+        stream.match_identkw("instchoice")?;
+        stream.require(Feature::OptionGroups)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.add_module_ctx(id);
+        stream.next_token();
+        stream.match_identkw("of")?;
+        stream.next_token();
+        let default = stream.get_identkw()?;
+        stream.next_token();
+        let option = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        let mut choice = InstChoice::new(id, default, option);
+        let case_indent = stream.indent_level();
+        while !stream.is_eof() && case_indent > current_indent && 
+            stream.indent_level() == case_indent
+        {
+            let case = stream.get_identkw()?;
+            stream.next_token();
+            stream.match_punc("=>")?;
+            stream.next_token();
+            let module = stream.get_identkw()?;
+            stream.next_token();
+            choice.cases.push((case.to_string(), module.to_string()));
+            stream.match_sol()?;
+        }
+        Ok(choice)
+    }
+
+    /// Parse an object instantiation, ie. `object o of Foo`
+    pub fn parse_object_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<(String, String), FirrtlParseError>
+    {
+        stream.match_identkw("object")?;
+        stream.require(Feature::Classes)?;
+        stream.next_token();
+        let id = stream.get_identkw()?;
+        stream.add_module_ctx(id);
+        stream.next_token();
+        stream.match_identkw("of")?;
+        stream.next_token();
+        let class_id = stream.get_identkw()?;
+        stream.next_token();
+        Ok((id.to_string(), class_id.to_string()))
+    }
+
+    /// Parse a property assignment, ie. `propassign o.x, Integer(1)`
+    pub fn parse_propassign_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<(StaticReference, Expr), FirrtlParseError>
+    {
+        stream.match_identkw("propassign")?;
+        stream.require(Feature::Properties)?;
+        stream.next_token();
+        let static_ref = FirrtlParser::parse_static_reference(stream)?;
+        let expr = FirrtlParser::parse_expr(stream)?;
+        Ok((static_ref, expr))
+    }
+
     pub fn parse_define_stmt(stream: &mut FirrtlStream<'a>)
         -> Result<(StaticReference, RefExpr), FirrtlParseError>
     {
         stream.match_identkw("define")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         let static_ref = FirrtlParser::parse_static_reference(stream)?;
         stream.match_punc("=")?;
@@ -445,6 +660,7 @@ impl <'a> FirrtlParser {
         -> Result<(RefExpr, Expr), FirrtlParseError>
     {
         stream.match_identkw("force_initial")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         stream.match_punc("(")?;
         stream.next_token();
@@ -460,6 +676,7 @@ impl <'a> FirrtlParser {
         -> Result<RefExpr, FirrtlParseError>
     {
         stream.match_identkw("release_initial")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         stream.match_punc("(")?;
         stream.next_token();
@@ -473,6 +690,7 @@ impl <'a> FirrtlParser {
         -> Result<(Expr, Expr, RefExpr, Expr), FirrtlParseError>
     {
         stream.match_identkw("force")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         stream.match_punc("(")?;
         stream.next_token();
@@ -490,6 +708,7 @@ impl <'a> FirrtlParser {
         -> Result<(Expr, Expr, RefExpr), FirrtlParseError>
     {
         stream.match_identkw("release")?;
+        stream.require(Feature::Probes)?;
         stream.next_token();
         stream.match_punc("(")?;
         stream.next_token();
@@ -506,6 +725,7 @@ impl <'a> FirrtlParser {
         -> Result<(Reference, Expr), FirrtlParseError>
     {
         stream.match_identkw("connect")?;
+        stream.require(Feature::Connect)?;
         stream.next_token();
         let reference = FirrtlParser::parse_reference(stream)?;
         let expr = FirrtlParser::parse_expr(stream)?;
@@ -515,13 +735,14 @@ impl <'a> FirrtlParser {
         -> Result<Reference, FirrtlParseError>
     {
         stream.match_identkw("invalidate")?;
+        stream.require(Feature::Connect)?;
         stream.next_token();
         let reference = FirrtlParser::parse_reference(stream)?;
         Ok(reference)
     }
 
     pub fn parse_printf_stmt(stream: &mut FirrtlStream<'a>)
-        -> Result<(Expr, Expr, String, Vec<Expr>), FirrtlParseError>
+        -> Result<Printf, FirrtlParseError>
     {
         stream.match_identkw("printf")?;
         stream.next_token();
@@ -544,16 +765,45 @@ impl <'a> FirrtlParser {
             arg_exprs.push(e);
         }
 
-        if stream.match_punc(":").is_ok() {
-            stream.next_token();
-            let _ = stream.get_identkw()?;
-            stream.next_token();
+        let mut res = Printf::new(clk_expr, cond_expr, fmtstr, arg_exprs);
+        res.name = FirrtlParser::parse_stmt_name(stream)?;
+        Ok(res)
+    }
+
+    /// Parse an `assert`, `assume` or `cover` statement.
+    pub fn parse_verification_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<Verification, FirrtlParseError>
+    {
+        stream.match_identkw_multi(&["assert", "assume", "cover"])?;
+        stream.next_token();
+        stream.match_punc("(")?;
+        stream.next_token();
+
+        let clk_expr = FirrtlParser::parse_expr(stream)?;
+        let pred_expr = FirrtlParser::parse_expr(stream)?;
+        let en_expr = FirrtlParser::parse_expr(stream)?;
+        let msg = stream.get_lit_str()?;
+        stream.next_token();
+
+        // Parse format arguments until we reach the closing paren
+        let mut arg_exprs = Vec::new();
+        loop {
+            if stream.match_punc(")").is_ok() {
+                stream.next_token();
+                break;
+            }
+            let e = FirrtlParser::parse_expr(stream)?;
+            arg_exprs.push(e);
         }
-        Ok((clk_expr, cond_expr, fmtstr.to_string(), arg_exprs))
+
+        let mut res = Verification::new(clk_expr, pred_expr, en_expr, msg, 
+            arg_exprs);
+        res.name = FirrtlParser::parse_stmt_name(stream)?;
+        Ok(res)
     }
 
     pub fn parse_stop_stmt(stream: &mut FirrtlStream<'a>)
-        -> Result<(Expr, Expr, usize), FirrtlParseError>
+        -> Result<(Expr, Expr, usize, Option<String>), FirrtlParseError>
     {
         stream.match_identkw("stop")?;
         stream.next_token();
@@ -561,45 +811,60 @@ impl <'a> FirrtlParser {
         stream.next_token();
         let e1 = FirrtlParser::parse_expr(stream)?;
         let e2 = FirrtlParser::parse_expr(stream)?;
-        let lit = stream.get_lit_int()?;
+        let lit = stream.get_lit_usize()?;
         stream.next_token();
         stream.match_punc(")")?;
         stream.next_token();
 
-        if stream.match_punc(":").is_ok() {
-            stream.next_token();
-            let _ = stream.get_identkw()?;
-            stream.next_token();
+        let name = FirrtlParser::parse_stmt_name(stream)?;
+        Ok((e1, e2, lit, name))
+    }
+
+    /// Parse the optional label (`: <name>`) at the end of a `printf`, 
+    /// `stop`, or verification statement.
+    pub fn parse_stmt_name(stream: &mut FirrtlStream<'a>)
+        -> Result<Option<String>, FirrtlParseError>
+    {
+        if stream.match_punc(":").is_err() {
+            return Ok(None);
         }
-        Ok((e1, e2, lit.parse().unwrap()))
+        stream.next_token();
+        let name = stream.get_identkw()?;
+        stream.next_token();
+        Ok(Some(name.to_string()))
     }
 
 
     pub fn parse_reference_stmt(stream: &mut FirrtlStream<'a>)
-        -> Result<Statement, FirrtlParseError>
+        -> Result<StatementKind, FirrtlParseError>
     {
         //println!("parsing reference stmt @ {:?}", stream.remaining_tokens());
         let reference = FirrtlParser::parse_reference(stream)?;
         // Must be an assignment '<=', this is an identifier
         if stream.match_punc("<=").is_ok() {
+            stream.require(Feature::LegacyConnect)?;
             stream.next_token();
             let expr = FirrtlParser::parse_expr(stream)?;
-            Ok(Statement::Connect(reference, expr))
+            Ok(StatementKind::Connect(reference, expr))
         } 
         // Must be a partial assignment '<-'?,
         else if stream.match_punc("<-").is_ok() {
+            stream.require(Feature::LegacyConnect)?;
             stream.next_token();
             let expr = FirrtlParser::parse_expr(stream)?;
-            Ok(Statement::PartialConnect(reference, expr))
+            Ok(StatementKind::PartialConnect(reference, expr))
         }
         // Must be 'is invalid', this is an identifier
         else if stream.match_identkw("is").is_ok() {
+            stream.require(Feature::LegacyConnect)?;
             stream.next_token();
             stream.match_identkw("invalid")?;
             stream.next_token();
-            Ok(Statement::Invalidate(reference))
+            Ok(StatementKind::Invalidate(reference))
         } else { 
-            panic!("unexpected keyword in reference statement?");
+            Err(stream.error(ParseErrorKind::ExpectedPunctuation(
+                "<=', '<-' or 'is invalid".to_string()
+            )))
         }
     }
 
@@ -614,6 +879,7 @@ impl <'a> FirrtlParser {
         stream.match_punc(":")?;
         stream.next_token();
         let ty = FirrtlParser::parse_type(stream)?;
+        stream.add_decl_type(id, ty.clone());
         Ok((id.to_string(), ty))
     }
 
@@ -632,6 +898,134 @@ impl <'a> FirrtlParser {
     }
 
 
+    pub fn parse_layerblock_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<(String, Vec<Statement>), FirrtlParseError>
+    {
+        let current_indent = stream.indent_level();
+        stream.match_identkw("layerblock")?;
+        stream.require(Feature::Layers)?;
+        stream.next_token();
+        let layer = stream.get_identkw()?;
+        stream.next_token();
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+        if stream.indent_level() <= current_indent {
+            return Err(stream.error(ParseErrorKind::Indentation(
+                "expected an indented 'layerblock' block".to_string()
+            )));
+        }
+        let blk = FirrtlParser::parse_statements_block(stream)?;
+        Ok((layer.to_string(), blk))
+    }
+
+    pub fn parse_match_stmt(stream: &mut FirrtlStream<'a>)
+        -> Result<(Expr, Vec<MatchArm>), FirrtlParseError>
+    {
+        let current_indent = stream.indent_level();
+
+        // Consume 'match <expr> :'
+        stream.match_identkw("match")?;
+        stream.require(Feature::Enums)?;
+        stream.next_token();
+        let expr = FirrtlParser::parse_expr(stream)?;
+        stream.match_punc(":")?;
+        stream.next_token();
+        stream.match_sol()?;
+
+        // Each arm is 'variant :' or 'variant(binding) :', followed by 
+        // an indented block of statements
+        let mut arms: Vec<MatchArm> = Vec::new();
+        let arm_indent = stream.indent_level();
+        if stream.is_eof() || arm_indent <= current_indent {
+            return Err(stream.error(ParseErrorKind::Indentation(
+                "expected at least one indented 'match' arm".to_string()
+            )));
+        }
+
+        // Arms are checked against the type of the matched expression 
+        // when we can tell what it is
+        let ty = FirrtlParser::match_expr_type(stream, &expr);
+        let variants = match ty.as_ref().map(|t| t.without_const()) {
+            Some(FirrtlType::Enum(variants)) => Some(variants),
+            Some(t) => return Err(stream.error(ParseErrorKind::Other(
+                format!("cannot match on non-enumeration type {}", t)
+            ))),
+            None => None,
+        };
+
+        while !stream.is_eof() && stream.indent_level() == arm_indent {
+            let variant = stream.get_identkw()?;
+            if arms.iter().any(|arm| arm.variant == variant) {
+                return Err(stream.error(ParseErrorKind::Duplicate(
+                    format!("match arm {}", variant)
+                )));
+            }
+            let has_data = match variants {
+                Some(variants) => {
+                    let Some(v) = variants.iter().find(|v| v.id == variant)
+                    else {
+                        return Err(stream.error(ParseErrorKind::Other(
+                            format!("'{}' is not a variant of {}", variant, 
+                                ty.as_ref().unwrap())
+                        )));
+                    };
+                    Some(v.ty.is_some())
+                },
+                None => None,
+            };
+            stream.next_token();
+            let has_binding = stream.match_punc("(").is_ok();
+            if has_data.is_some_and(|d| d != has_binding) {
+                let msg = if has_binding {
+                    format!("variant '{}' has no data to bind", variant)
+                } else {
+                    format!("variant '{}' requires a binding", variant)
+                };
+                return Err(stream.error(ParseErrorKind::Other(msg)));
+            }
+            let binding = if has_binding {
+                stream.next_token();
+                let id = stream.get_identkw()?;
+                stream.add_module_ctx(id);
+                stream.next_token();
+                stream.match_punc(")")?;
+                stream.next_token();
+                Some(id.to_string())
+            } else {
+                None
+            };
+            stream.match_punc(":")?;
+            stream.next_token();
+            stream.match_sol()?;
+            if stream.indent_level() <= arm_indent {
+                return Err(stream.error(ParseErrorKind::Indentation(
+                    "expected an indented 'match' arm".to_string()
+                )));
+            }
+            let body = FirrtlParser::parse_statements_block(stream)?;
+            arms.push(MatchArm::new(variant, binding, body));
+        }
+        Ok((expr, arms))
+    }
+
+    /// The (alias-resolved) type of the expression in a 'match' statement,
+    /// if it can be determined while parsing.
+    fn match_expr_type(stream: &FirrtlStream<'a>, expr: &Expr) 
+        -> Option<FirrtlType>
+    {
+        let ty = match expr {
+            Expr::Ref(Reference::Static(sr)) => {
+                let decl = stream.decl_type(sr.get_ident())?
+                    .resolve(&|id| stream.type_alias(id))?;
+                decl.reference_type(sr)?.clone()
+            },
+            Expr::Enum(ty, ..) => ty.clone(),
+            _ => return None,
+        };
+        ty.resolve(&|id| stream.type_alias(id))
+    }
+
     pub fn parse_when_stmt(stream: &mut FirrtlStream<'a>)
         -> Result<(Expr, Vec<Statement>, Vec<Statement>), FirrtlParseError>
     {
@@ -659,12 +1053,16 @@ impl <'a> FirrtlParser {
                 let else_stmt = FirrtlParser::parse_statement(stream)?;
                 else_statements.push(else_stmt);
             }
-            assert!(stream.is_sol());
+            stream.match_sol()?;
             return Ok((cond_expr, when_statements, else_statements));
         }
 
         // Otherwise, this must be 'when <expr> : { statements }'
-        assert!(stream.indent_level() > current_indent);
+        if stream.indent_level() <= current_indent {
+            return Err(stream.error(ParseErrorKind::Indentation(
+                "expected an indented 'when' block".to_string()
+            )));
+        }
         let when_block = FirrtlParser::parse_statements_block(stream)?;
 
         // There are no 'else' statements
@@ -1,5 +1,10 @@
 //! Simple passes over the FIRRTL AST
 
+pub mod chirrtl;
+pub mod constness;
+pub mod layers;
+pub mod specialize;
+
 #[cfg(test)]
 mod tests {
     use crate::file::*;
@@ -11,7 +16,7 @@ mod tests {
         use std::fs::File;
         use std::io::*;
         let filename = "../chisel-tests/firrtl/MyAlu.fir";
-        let sf = FirrtlFile::from_file(filename);
+        let sf = FirrtlFile::from_file(filename)?;
         let circuit = sf.parse()?;
         Ok(())
     }
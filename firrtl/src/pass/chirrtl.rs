@@ -0,0 +1,676 @@
+//! Lowering CHIRRTL memories (`cmem`/`smem` and their `mport`s) into
+//! spec `mem` declarations.
+//!
+//! Each memory port becomes a reader, writer or readwriter on the lowered
+//! memory. The port's address, clock and enable are connected where the
+//! `mport` was declared, and every other use of the port is redirected to
+//! the corresponding field of the memory:
+//!
+//! - Reading from a port reads `data` (or `rdata` for a readwriter)
+//! - Writing to a port writes `data` (or `wdata`) and sets the matching
+//!   `mask` (or `wmask`) bits; a readwriter also sets `wmode`
+//!
+//! Ports declared with `infer` become readers, writers or readwriters
+//! depending on whether they are read from, written to, or both.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::*;
+
+/// An error encountered while lowering CHIRRTL memories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChirrtlError {
+    /// The module containing the offending statement
+    pub module: String,
+    pub message: String,
+}
+impl fmt::Display for ChirrtlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}", self.module, self.message)
+    }
+}
+impl std::error::Error for ChirrtlError {}
+
+/// A memory port after its direction has been resolved.
+struct Port {
+    mem: String,
+    dir: MemPortDir,
+    /// Element type of the memory
+    ty: FirrtlType,
+}
+
+/// How a port is used in the body of a module.
+#[derive(Default, Clone, Copy)]
+struct Usage {
+    read: bool,
+    write: bool,
+}
+
+/// Lower all CHIRRTL memories in `circuit` into `mem` declarations.
+pub fn lower_chirrtl(circuit: &mut Circuit) -> Result<(), ChirrtlError> {
+    for module in circuit.modules.iter_mut() {
+        lower_module(module, &circuit.type_aliases)?;
+    }
+    Ok(())
+}
+
+/// Lower all CHIRRTL memories in a single module (where `aliases` are the 
+/// type aliases declared in the enclosing circuit).
+pub fn lower_module(module: &mut Module, aliases: &[TypeAlias]) 
+    -> Result<(), ChirrtlError> 
+{
+    let err = |message: String| ChirrtlError {
+        module: module.id.clone(), message
+    };
+
+    let mut mems = HashMap::new();
+    let mut decls = Vec::new();
+    let mut usage = HashMap::new();
+    collect(&module.statements, &mut mems, &mut decls, &mut usage);
+    if decls.is_empty() {
+        return Ok(());
+    }
+
+    let mut ports = HashMap::new();
+    let mut mem_ports: HashMap<String, Vec<String>> = HashMap::new();
+    for decl in decls {
+        let Some(mem) = mems.get(&decl.mem) else {
+            return Err(err(format!("'{}' is not a CHIRRTL memory", decl.mem)));
+        };
+        let u: Usage = usage.get(&decl.id).copied().unwrap_or_default();
+        let dir = match decl.dir {
+            MemPortDir::Infer => match (u.read, u.write) {
+                (true, true) => MemPortDir::ReadWrite,
+                (false, true) => MemPortDir::Write,
+                _ => MemPortDir::Read,
+            },
+            dir => dir,
+        };
+        // Masks are generated for each leaf of the element type
+        let ty = mem.ty.resolve(&|id| {
+            aliases.iter().find(|t| t.id == id).map(|t| &t.ty)
+        }).ok_or_else(|| err(format!("unknown type alias in '{}'", mem.ty)))?;
+        mem_ports.entry(decl.mem.clone()).or_default().push(decl.id.clone());
+        ports.insert(decl.id.clone(),
+            Port { mem: decl.mem.clone(), dir, ty });
+    }
+
+    let lowering = Lowering { ports, mem_ports };
+    let stmts = std::mem::take(&mut module.statements);
+    module.statements = lowering.lower_block(stmts).map_err(err)?;
+    Ok(())
+}
+
+/// Find all CHIRRTL memories and ports, and record how each port is used.
+fn collect(stmts: &[Statement],
+    mems: &mut HashMap<String, ChirrtlMemDecl>,
+    decls: &mut Vec<MemPortDecl>,
+    usage: &mut HashMap<String, Usage>)
+{
+    for stmt in stmts {
+        let mut reads = Vec::new();
+        match &stmt.kind {
+            StatementKind::ChirrtlMem(decl) => {
+                mems.insert(decl.id.clone(), decl.clone());
+            },
+            StatementKind::MemPort(decl) => {
+                decls.push(decl.clone());
+                reads.push(&decl.addr);
+                reads.push(&decl.clock);
+            },
+            StatementKind::Connect(r, e) |
+            StatementKind::PartialConnect(r, e) => {
+                usage.entry(r.get_ident().to_string()).or_default()
+                    .write = true;
+                if let Reference::DynamicIndex(_, idx) = r {
+                    reads.push(idx);
+                }
+                reads.push(e);
+            },
+            StatementKind::Invalidate(r) => {
+                usage.entry(r.get_ident().to_string()).or_default()
+                    .write = true;
+            },
+            StatementKind::Attach(refs) => {
+                for r in refs {
+                    usage.entry(r.get_ident().to_string()).or_default()
+                        .read = true;
+                }
+            },
+            StatementKind::When(cond, when_blk, else_blk) => {
+                reads.push(cond);
+                collect(when_blk, mems, decls, usage);
+                collect(else_blk, mems, decls, usage);
+            },
+            StatementKind::Match(e, arms) => {
+                reads.push(e);
+                for arm in arms {
+                    collect(&arm.body, mems, decls, usage);
+                }
+            },
+            StatementKind::LayerBlock(_, blk) => {
+                collect(blk, mems, decls, usage);
+            },
+            kind => reads.extend(stmt_exprs(kind)),
+        }
+        for e in reads {
+            expr_idents(e, &mut |id| {
+                usage.entry(id.to_string()).or_default().read = true;
+            });
+        }
+    }
+}
+
+/// The expressions read by a statement (other than those handled
+/// explicitly in [collect]).
+fn stmt_exprs(kind: &StatementKind) -> Vec<&Expr> {
+    match kind {
+        StatementKind::Reg(_, _, clk) => vec![clk],
+        StatementKind::RegReset(decl) => {
+            vec![&decl.clock, &decl.reset, &decl.init]
+        },
+        StatementKind::Node(_, e) => vec![e],
+        StatementKind::Stop(clk, en, ..) => vec![clk, en],
+        StatementKind::Force(clk, pred, _, e) => vec![clk, pred, e],
+        StatementKind::Release(clk, pred, _) => vec![clk, pred],
+        StatementKind::ForceInitial(_, e) => vec![e],
+        StatementKind::Printf(p) => {
+            let mut res = vec![&p.clock, &p.en];
+            res.extend(p.args.iter());
+            res
+        },
+        StatementKind::Assert(v) |
+        StatementKind::Assume(v) |
+        StatementKind::Cover(v) => {
+            let mut res = vec![&v.clock, &v.pred, &v.en];
+            res.extend(v.args.iter());
+            res
+        },
+        StatementKind::Intrinsic(intr) => intr.args.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Call `f` with the base identifier of every reference in `e`.
+fn expr_idents(e: &Expr, f: &mut impl FnMut(&str)) {
+    match e {
+        Expr::Ref(r) => {
+            f(r.get_ident());
+            if let Reference::DynamicIndex(_, idx) = r {
+                expr_idents(idx, f);
+            }
+        },
+        Expr::Mux(e1, e2, e3) => {
+            expr_idents(e1, f);
+            expr_idents(e2, f);
+            expr_idents(e3, f);
+        },
+        Expr::PrimOp2Expr(_, e1, e2) => {
+            expr_idents(e1, f);
+            expr_idents(e2, f);
+        },
+        Expr::PrimOp1Expr(_, e1) |
+        Expr::PrimOp1Expr1Int(_, e1, _) |
+        Expr::PrimOp1Expr2Int(_, e1, _, _) => expr_idents(e1, f),
+        Expr::Enum(_, _, Some(e1)) |
+        Expr::Subfield(e1, _) |
+        Expr::Subindex(e1, _) => expr_idents(e1, f),
+        Expr::Subaccess(e1, idx) => {
+            expr_idents(e1, f);
+            expr_idents(idx, f);
+        },
+        Expr::Intrinsic(intr) => {
+            for arg in intr.args.iter() {
+                expr_idents(arg, f);
+            }
+        },
+        // Properties can't refer to memory ports
+        Expr::Const(..) | Expr::Read(_) | Expr::Enum(..) | Expr::Property(_) |
+        Expr::List(..) | Expr::PropPrimOp(..) | Expr::None => {},
+    }
+}
+
+fn uint1(value: u32) -> Expr {
+    Expr::Const(FirrtlType::Ground(FirrtlTypeGround::UInt(Some(1))),
+        LiteralNumeric::UInt(BigUint::from(value)))
+}
+
+/// Append every ground-typed leaf of a value with type `ty` (referred to
+/// by `base`) to `out`.
+fn leaves(ty: &FirrtlType, base: StaticReference,
+    out: &mut Vec<StaticReference>)
+{
+    match ty {
+        FirrtlType::Vector(elem, len) => {
+            for idx in 0..*len {
+                leaves(elem, StaticReference::Subindex(Box::new(base.clone()),
+                    idx), out);
+            }
+        },
+        FirrtlType::Bundle(fields) => {
+            for field in fields {
+                leaves(&field.ty, StaticReference::Subfield(
+                    Box::new(base.clone()), field.id.clone()), out);
+            }
+        },
+        _ => out.push(base),
+    }
+}
+
+struct Lowering {
+    ports: HashMap<String, Port>,
+    /// Names of the ports on each memory (in declaration order)
+    mem_ports: HashMap<String, Vec<String>>,
+}
+impl Lowering {
+    /// `mem.port.field`
+    fn field(&self, port: &str, field: &str) -> StaticReference {
+        let mem = StaticReference::new_static(&self.ports[port].mem);
+        let port = StaticReference::Subfield(Box::new(mem), port.to_string());
+        StaticReference::Subfield(Box::new(port), field.to_string())
+    }
+
+    fn connect(&self, r: StaticReference, e: Expr, info: &Option<SourceInfo>)
+        -> Statement
+    {
+        Statement::new(StatementKind::Connect(Reference::Static(r), e),
+            info.clone())
+    }
+
+    /// Replace the port at the base of `r` with `mem.port.field`.
+    fn rebase(&self, r: &StaticReference, field: &str) -> StaticReference {
+        match r {
+            StaticReference::Static(id) => self.field(id, field),
+            StaticReference::Subfield(inner, f) => StaticReference::Subfield(
+                Box::new(self.rebase(inner, field)), f.clone()),
+            StaticReference::Subindex(inner, idx) => StaticReference::Subindex(
+                Box::new(self.rebase(inner, field)), *idx),
+        }
+    }
+
+    /// The name of the data field read from/written to on `port`.
+    fn data_field(&self, port: &str, write: bool) -> &'static str {
+        match (self.ports[port].dir, write) {
+            (MemPortDir::ReadWrite, false) => "rdata",
+            (MemPortDir::ReadWrite, true) => "wdata",
+            _ => "data",
+        }
+    }
+
+    /// Rewrite a reference which may refer to a memory port.
+    fn lower_ref(&self, r: &Reference, write: bool) -> Reference {
+        let id = r.get_ident();
+        match r {
+            Reference::Static(sr) if self.ports.contains_key(id) => {
+                Reference::Static(self.rebase(sr, self.data_field(id, write)))
+            },
+            Reference::DynamicIndex(sr, idx) => {
+                let sr = if self.ports.contains_key(id) {
+                    self.rebase(sr, self.data_field(id, write))
+                } else {
+                    sr.clone()
+                };
+                Reference::DynamicIndex(sr, Box::new(self.lower_expr(idx)))
+            },
+            _ => r.clone(),
+        }
+    }
+
+    fn lower_expr(&self, e: &Expr) -> Expr {
+        let b = |e: &Expr| Box::new(self.lower_expr(e));
+        match e {
+            Expr::Ref(r) => Expr::Ref(self.lower_ref(r, false)),
+            Expr::Mux(e1, e2, e3) => Expr::Mux(b(e1), b(e2), b(e3)),
+            Expr::PrimOp2Expr(op, e1, e2) => Expr::PrimOp2Expr(*op, b(e1),
+                b(e2)),
+            Expr::PrimOp1Expr(op, e1) => Expr::PrimOp1Expr(*op, b(e1)),
+            Expr::PrimOp1Expr1Int(op, e1, x) => {
+                Expr::PrimOp1Expr1Int(*op, b(e1), *x)
+            },
+            Expr::PrimOp1Expr2Int(op, e1, x, y) => {
+                Expr::PrimOp1Expr2Int(*op, b(e1), *x, *y)
+            },
+            Expr::Enum(ty, variant, e1) => Expr::Enum(ty.clone(), 
+                variant.clone(), e1.as_deref().map(b)),
+            Expr::Intrinsic(intr) => {
+                Expr::Intrinsic(self.lower_intrinsic(intr))
+            },
+            Expr::Subfield(e1, field) => self.lower_expr(e1).subfield(field),
+            Expr::Subindex(e1, idx) => self.lower_expr(e1).subindex(*idx),
+            Expr::Subaccess(e1, idx) => {
+                self.lower_expr(e1).subaccess(self.lower_expr(idx))
+            },
+            Expr::Const(..) | Expr::Read(_) | Expr::Property(_) | 
+            Expr::List(..) | Expr::PropPrimOp(..) | Expr::None => e.clone(),
+        }
+    }
+
+    fn lower_intrinsic(&self, intr: &Intrinsic) -> Intrinsic {
+        Intrinsic {
+            args: intr.args.iter().map(|e| self.lower_expr(e)).collect(),
+            ..intr.clone()
+        }
+    }
+
+    fn lower_verification(&self, v: Verification) -> Verification {
+        Verification {
+            clock: self.lower_expr(&v.clock),
+            pred: self.lower_expr(&v.pred),
+            en: self.lower_expr(&v.en),
+            args: v.args.iter().map(|e| self.lower_expr(e)).collect(),
+            ..v
+        }
+    }
+
+    /// Statements setting the mask bits covered by a write to `r`.
+    fn write_mask(&self, r: &Reference, info: &Option<SourceInfo>)
+        -> Result<Vec<Statement>, String>
+    {
+        let id = r.get_ident();
+        let port = &self.ports[id];
+        let mask = match port.dir {
+            MemPortDir::ReadWrite => "wmask",
+            _ => "mask",
+        };
+        let mut res = Vec::new();
+        match r {
+            Reference::Static(sr) => {
+                let ty = port.ty.reference_type(sr).ok_or_else(|| {
+                    format!("invalid reference '{}' to memory port", sr)
+                })?;
+                let mut targets = Vec::new();
+                leaves(ty, self.rebase(sr, mask), &mut targets);
+                res.extend(targets.into_iter()
+                    .map(|t| self.connect(t, uint1(1), info)));
+            },
+            Reference::DynamicIndex(sr, idx) => {
+                let elem = match port.ty.reference_type(sr) {
+                    Some(FirrtlType::Vector(elem, _)) => elem,
+                    _ => return Err(format!(
+                        "invalid reference '{}' to memory port", r)),
+                };
+                if !matches!(**elem, FirrtlType::Ground(_)) {
+                    return Err(format!(
+                        "unsupported write to aggregate element '{}' \
+                         of memory port", r));
+                }
+                let target = Reference::DynamicIndex(self.rebase(sr, mask),
+                    Box::new(self.lower_expr(idx)));
+                res.push(Statement::new(
+                    StatementKind::Connect(target, uint1(1)), info.clone()));
+            },
+        }
+        if port.dir == MemPortDir::ReadWrite {
+            res.push(self.connect(self.field(id, "wmode"), uint1(1), info));
+        }
+        Ok(res)
+    }
+
+    /// The `mem` declaration for a CHIRRTL memory, followed by default
+    /// connections for all of its ports.
+    fn lower_mem(&self, decl: ChirrtlMemDecl, info: &Option<SourceInfo>)
+        -> Vec<Statement>
+    {
+        let names = self.mem_ports.get(&decl.id).cloned().unwrap_or_default();
+        let with_dir = |dir: MemPortDir| -> Vec<String> {
+            names.iter().filter(|p| self.ports[*p].dir == dir)
+                .cloned().collect()
+        };
+        let (read_latency, ruw) = match decl.kind {
+            ChirrtlMemKind::Comb => (0, ReadUnderWrite::Undefined),
+            ChirrtlMemKind::Seq(ruw) => {
+                (1, ruw.unwrap_or(ReadUnderWrite::Undefined))
+            },
+        };
+        let mem = MemDecl::new(&decl.id, decl.ty.clone(), decl.depth,
+            read_latency, 1, ruw,
+            with_dir(MemPortDir::Read),
+            with_dir(MemPortDir::Write),
+            with_dir(MemPortDir::ReadWrite));
+
+        let mut res = vec![Statement::new(StatementKind::Mem(mem),
+            info.clone())];
+        let invalidate = |r: StaticReference| Statement::new(
+            StatementKind::Invalidate(Reference::Static(r)), info.clone());
+        for name in names.iter() {
+            res.push(self.connect(self.field(name, "en"), uint1(0), info));
+            res.push(invalidate(self.field(name, "addr")));
+            res.push(invalidate(self.field(name, "clk")));
+            let (data, mask) = match self.ports[name].dir {
+                MemPortDir::Write => ("data", "mask"),
+                MemPortDir::ReadWrite => {
+                    res.push(self.connect(self.field(name, "wmode"),
+                        uint1(0), info));
+                    ("wdata", "wmask")
+                },
+                _ => continue,
+            };
+            res.push(invalidate(self.field(name, data)));
+            let mut targets = Vec::new();
+            leaves(&self.ports[name].ty, self.field(name, mask),
+                &mut targets);
+            res.extend(targets.into_iter()
+                .map(|t| self.connect(t, uint1(0), info)));
+        }
+        res
+    }
+
+    fn lower_block(&self, stmts: Vec<Statement>)
+        -> Result<Vec<Statement>, String>
+    {
+        let mut res = Vec::new();
+        for stmt in stmts {
+            let info = stmt.info;
+            let kind = match stmt.kind {
+                StatementKind::ChirrtlMem(decl) => {
+                    res.extend(self.lower_mem(decl, &info));
+                    continue;
+                },
+                StatementKind::MemPort(decl) => {
+                    let id = &decl.id;
+                    res.push(self.connect(self.field(id, "addr"),
+                        self.lower_expr(&decl.addr), &info));
+                    res.push(self.connect(self.field(id, "clk"),
+                        self.lower_expr(&decl.clock), &info));
+                    res.push(self.connect(self.field(id, "en"),
+                        uint1(1), &info));
+                    continue;
+                },
+                StatementKind::Connect(r, e) |
+                StatementKind::PartialConnect(r, e)
+                    if self.ports.contains_key(r.get_ident()) =>
+                {
+                    res.push(Statement::new(StatementKind::Connect(
+                        self.lower_ref(&r, true), self.lower_expr(&e)),
+                        info.clone()));
+                    res.extend(self.write_mask(&r, &info)?);
+                    continue;
+                },
+                StatementKind::Connect(r, e) => {
+                    StatementKind::Connect(self.lower_ref(&r, true),
+                        self.lower_expr(&e))
+                },
+                StatementKind::PartialConnect(r, e) => {
+                    StatementKind::PartialConnect(self.lower_ref(&r, true),
+                        self.lower_expr(&e))
+                },
+                StatementKind::Invalidate(r) => {
+                    StatementKind::Invalidate(self.lower_ref(&r, true))
+                },
+                StatementKind::Attach(refs) => StatementKind::Attach(
+                    refs.iter().map(|r| self.lower_ref(r, false)).collect()),
+                StatementKind::When(cond, when_blk, else_blk) => {
+                    StatementKind::When(self.lower_expr(&cond),
+                        self.lower_block(when_blk)?,
+                        self.lower_block(else_blk)?)
+                },
+                StatementKind::LayerBlock(layer, blk) => {
+                    StatementKind::LayerBlock(layer, self.lower_block(blk)?)
+                },
+                StatementKind::Match(e, arms) => {
+                    let arms = arms.into_iter().map(|arm| {
+                        Ok(MatchArm { 
+                            body: self.lower_block(arm.body)?, ..arm 
+                        })
+                    }).collect::<Result<_, String>>()?;
+                    StatementKind::Match(self.lower_expr(&e), arms)
+                },
+                StatementKind::Reg(id, ty, clk) => {
+                    StatementKind::Reg(id, ty, self.lower_expr(&clk))
+                },
+                StatementKind::RegReset(decl) => {
+                    StatementKind::RegReset(RegResetDecl {
+                        clock: self.lower_expr(&decl.clock),
+                        reset: self.lower_expr(&decl.reset),
+                        init: self.lower_expr(&decl.init),
+                        ..decl
+                    })
+                },
+                StatementKind::Node(id, e) => {
+                    StatementKind::Node(id, self.lower_expr(&e))
+                },
+                StatementKind::Stop(clk, en, code, name) => {
+                    StatementKind::Stop(self.lower_expr(&clk),
+                        self.lower_expr(&en), code, name)
+                },
+                StatementKind::Force(clk, pred, r, e) => {
+                    StatementKind::Force(self.lower_expr(&clk),
+                        self.lower_expr(&pred), r, self.lower_expr(&e))
+                },
+                StatementKind::Release(clk, pred, r) => {
+                    StatementKind::Release(self.lower_expr(&clk),
+                        self.lower_expr(&pred), r)
+                },
+                StatementKind::ForceInitial(r, e) => {
+                    StatementKind::ForceInitial(r, self.lower_expr(&e))
+                },
+                StatementKind::Printf(p) => {
+                    StatementKind::Printf(Printf {
+                        clock: self.lower_expr(&p.clock),
+                        en: self.lower_expr(&p.en),
+                        args: p.args.iter().map(|e| self.lower_expr(e))
+                            .collect(),
+                        ..p
+                    })
+                },
+                StatementKind::Assert(v) => {
+                    StatementKind::Assert(self.lower_verification(v))
+                },
+                StatementKind::Assume(v) => {
+                    StatementKind::Assume(self.lower_verification(v))
+                },
+                StatementKind::Cover(v) => {
+                    StatementKind::Cover(self.lower_verification(v))
+                },
+                StatementKind::Intrinsic(intr) => {
+                    StatementKind::Intrinsic(self.lower_intrinsic(&intr))
+                },
+                kind => kind,
+            };
+            res.push(Statement::new(kind, info));
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+circuit Top :
+  type Pair = { a : UInt<8>, b : UInt<8> }
+  module Top :
+    input clock : Clock
+    input addr : UInt<4>
+    input wen : UInt<1>
+    input in : Pair
+    output out : Pair
+    output q : UInt<8>
+    smem s : Pair[16], old
+    cmem c : UInt<8>[4]
+    invalidate out
+    when wen :
+      write mport w = s[addr], clock
+      connect w, in
+    else :
+      read mport r = s[addr], clock
+      connect out, r
+    infer mport x = c[bits(addr, 1, 0)], clock
+    infer mport y = c[UInt<2>(0)], clock
+    connect x, add(y, UInt<8>(1))
+    connect q, y
+";
+
+    fn mem<'a>(circuit: &'a Circuit, id: &str) -> &'a MemDecl {
+        circuit.modules[0].statements.iter().find_map(|s| match &s.kind {
+            StatementKind::Mem(m) if m.id == id => Some(m),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn lower_ports() {
+        let mut circuit = parse(SAMPLE);
+        lower_chirrtl(&mut circuit).unwrap();
+
+        let s = mem(&circuit, "s");
+        assert_eq!(s.read_latency, 1);
+        assert_eq!(s.read_under_write, ReadUnderWrite::Old);
+        assert_eq!(s.rp_list, vec!["r"]);
+        assert_eq!(s.wp_list, vec!["w"]);
+
+        let c = mem(&circuit, "c");
+        assert_eq!(c.read_latency, 0);
+        assert_eq!(c.rp_list, vec!["y"]);
+        assert_eq!(c.wp_list, vec!["x"]);
+
+        // The lowered circuit should be valid FIRRTL
+        let text = circuit.to_string();
+        assert!(!text.contains("mport"), "{}", text);
+        assert!(!text.contains("connect s.w.mask,"), "{}", text);
+        assert!(text.contains("connect s.w.mask.a, UInt<1>(0)"), "{}", text);
+        assert!(text.contains("connect s.w.mask.b, UInt<1>(1)"), "{}", text);
+        assert!(text.contains("connect out, s.r.data"), "{}", text);
+        assert!(text.contains("connect c.x.data, add(c.y.data, UInt<8>(1))"),
+            "{}", text);
+        assert_eq!(parse(&text), circuit);
+    }
+
+    #[test]
+    fn lower_readwrite() {
+        let mut circuit = parse("\
+circuit Top :
+  module Top :
+    input clock : Clock
+    output q : UInt<8>[2]
+    cmem m : UInt<8>[2][8]
+    infer mport p = m[UInt<3>(1)], clock
+    connect q, p
+    connect p[1], UInt<8>(3)
+");
+        lower_chirrtl(&mut circuit).unwrap();
+        assert_eq!(mem(&circuit, "m").rwp_list, vec!["p"]);
+        let text = circuit.to_string();
+        assert!(text.contains("connect q, m.p.rdata"), "{}", text);
+        assert!(text.contains("connect m.p.wdata[1], UInt<8>(3)"), "{}", text);
+        assert!(text.contains("connect m.p.wmask[1], UInt<1>(1)"), "{}", text);
+        assert!(text.contains("connect m.p.wmode, UInt<1>(1)"), "{}", text);
+        assert_eq!(parse(&text), circuit);
+    }
+
+    #[test]
+    fn lower_unknown_mem() {
+        let mut circuit = parse("\
+circuit Top :
+  module Top :
+    input clock : Clock
+    wire m : UInt<8>
+    read mport p = m[UInt<1>(0)], clock
+");
+        let e = lower_chirrtl(&mut circuit).unwrap_err();
+        assert_eq!(e.to_string(), "Top: 'm' is not a CHIRRTL memory");
+    }
+}
@@ -0,0 +1,360 @@
+//! Checking connections to `const` values.
+//!
+//! A value with a `const` type can't change at runtime, so each `const` part
+//! of a sink must be driven by a `const` source: a literal, a `const` port
+//! or wire, or a node computed only from other `const` values.
+//!
+//! Connecting to a `const` sink is also rejected under a `when` or `match`
+//! whose condition isn't `const` (or through a dynamic index that isn't),
+//! since the value could then still change at runtime.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::*;
+
+/// An error encountered while checking connections to `const` values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstError {
+    /// The module containing the offending statement
+    pub module: String,
+    pub message: String,
+}
+impl fmt::Display for ConstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}", self.module, self.message)
+    }
+}
+impl std::error::Error for ConstError {}
+
+/// Check every connection in `circuit`, returning the first one that
+/// drives a `const` value with something that isn't `const`.
+pub fn check_const(circuit: &Circuit) -> Result<(), ConstError> {
+    for module in circuit.modules.iter() {
+        let mut checker = Checker { circuit, decls: HashMap::new() };
+        for port in module.ports.iter() {
+            checker.declare(&port.id, &port.ty);
+        }
+        checker.check_block(&module.statements, true).map_err(|message| {
+            ConstError { module: module.id.clone(), message }
+        })?;
+    }
+    Ok(())
+}
+
+/// Something declared in a module body.
+struct Decl {
+    /// Type of the declaration (with aliases resolved), if known
+    ty: Option<FirrtlType>,
+    /// Set when the whole value is `const` (ie. a node with a `const`
+    /// expression)
+    is_const: bool,
+}
+
+struct Checker<'c> {
+    circuit: &'c Circuit,
+    decls: HashMap<String, Decl>,
+}
+impl <'c> Checker<'c> {
+    fn declare(&mut self, id: &str, ty: &FirrtlType) {
+        let ty = self.circuit.resolve_type(ty);
+        self.decls.insert(id.to_string(), Decl { ty, is_const: false });
+    }
+
+    /// The type of an instance of module `mid`, as seen from outside.
+    fn instance_type(&self, mid: &str) -> Option<FirrtlType> {
+        let c = self.circuit;
+        let ports = c.modules.iter().find(|m| m.id == mid).map(|m| &m.ports)
+            .or_else(|| c.extmodules.iter().find(|m| m.id == mid)
+                .map(|m| &m.ports))
+            .or_else(|| c.intmodules.iter().find(|m| m.id == mid)
+                .map(|m| &m.ports))?;
+        Some(FirrtlType::Bundle(ports.iter().map(|p| {
+            BundleField::new(p.dir == Direction::Input, &p.id, p.ty.clone())
+        }).collect()))
+    }
+
+    fn declare_instance(&mut self, id: &str, mid: &str) {
+        let ty = self.instance_type(mid)
+            .and_then(|ty| self.circuit.resolve_type(&ty));
+        self.decls.insert(id.to_string(), Decl { ty, is_const: false });
+    }
+
+    /// Get the type (without `const`) of the value referred to by `r`, and
+    /// whether that value is `const`.
+    fn ref_info(&self, r: &StaticReference) -> (Option<&FirrtlType>, bool) {
+        let (is_const, part) = match r {
+            StaticReference::Static(id) => {
+                let Some(decl) = self.decls.get(id) else {
+                    return (None, false);
+                };
+                (decl.is_const, decl.ty.as_ref())
+            },
+            StaticReference::Subfield(inner, field) => {
+                let (ty, is_const) = self.ref_info(inner);
+                (is_const, ty.and_then(|t| t.bundle_field_type(field)))
+            },
+            StaticReference::Subindex(inner, _) => {
+                let (ty, is_const) = self.ref_info(inner);
+                let elem = match ty {
+                    Some(FirrtlType::Vector(elem, _)) => Some(&**elem),
+                    _ => None,
+                };
+                (is_const, elem)
+            },
+        };
+        let is_const = is_const || part.is_some_and(|t| t.is_const());
+        (part.map(|t| t.without_const()), is_const)
+    }
+
+    /// Like [Self::ref_info] for the element at some dynamic index into
+    /// the vector referred to by `r`.
+    fn elem_info(&self, r: &StaticReference) -> (Option<&FirrtlType>, bool) {
+        let (ty, is_const) = self.ref_info(r);
+        match ty {
+            Some(FirrtlType::Vector(elem, _)) => {
+                (Some(elem.without_const()), is_const || elem.is_const())
+            },
+            _ => (None, is_const),
+        }
+    }
+
+    /// Like [Self::ref_info] for an arbitrary expression.
+    fn expr_info(&self, e: &Expr) -> (Option<&FirrtlType>, bool) {
+        match e {
+            Expr::Ref(Reference::Static(r)) => self.ref_info(r),
+            // Any part of the vector may be selected at runtime
+            Expr::Ref(Reference::DynamicIndex(r, idx)) => {
+                if self.expr_const(idx) {
+                    self.elem_info(r)
+                } else {
+                    (None, false)
+                }
+            },
+            e => (None, self.expr_const(e)),
+        }
+    }
+
+    /// Returns 'true' if the whole value of `e` is `const`.
+    fn expr_const(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(..) => true,
+            Expr::Ref(_) => self.expr_info(e).1,
+            Expr::Mux(e1, e2, e3) => {
+                self.expr_const(e1) && self.expr_const(e2) &&
+                    self.expr_const(e3)
+            },
+            Expr::PrimOp2Expr(_, e1, e2) |
+            Expr::Subaccess(e1, e2) => {
+                self.expr_const(e1) && self.expr_const(e2)
+            },
+            Expr::PrimOp1Expr(_, e1) |
+            Expr::PrimOp1Expr1Int(_, e1, _) |
+            Expr::PrimOp1Expr2Int(_, e1, _, _) |
+            Expr::Subfield(e1, _) |
+            Expr::Subindex(e1, _) => self.expr_const(e1),
+            Expr::Enum(_, _, data) => {
+                data.as_deref().is_none_or(|e1| self.expr_const(e1))
+            },
+            // The declared result type says whether this is const
+            Expr::Intrinsic(intr) => intr.ty.as_ref().is_some_and(|ty| {
+                self.circuit.resolve_type(ty).is_some_and(|t| t.is_const())
+            }),
+            // Probes and properties are never const
+            Expr::Read(_) | Expr::Property(_) | Expr::List(..) |
+            Expr::PropPrimOp(..) | Expr::None => false,
+        }
+    }
+
+    fn check_block(&mut self, stmts: &[Statement], cond_const: bool)
+        -> Result<(), String>
+    {
+        for stmt in stmts {
+            match &stmt.kind {
+                StatementKind::Wire(id, ty) |
+                StatementKind::Reg(id, ty, _) => self.declare(id, ty),
+                StatementKind::RegReset(decl) => {
+                    self.declare(&decl.id, &decl.ty);
+                },
+                StatementKind::Node(id, e) => {
+                    let (ty, is_const) = self.expr_info(e);
+                    let ty = ty.cloned();
+                    self.decls.insert(id.clone(), Decl { ty, is_const });
+                },
+                StatementKind::Inst(id, mid) => {
+                    self.declare_instance(id, mid);
+                },
+                StatementKind::InstChoice(choice) => {
+                    self.declare_instance(&choice.id, &choice.default);
+                },
+                StatementKind::Connect(r, e) |
+                StatementKind::PartialConnect(r, e) => {
+                    self.check_connect(r, e, cond_const)?;
+                },
+                StatementKind::When(cond, when_blk, else_blk) => {
+                    let cond_const = cond_const && self.expr_const(cond);
+                    self.check_block(when_blk, cond_const)?;
+                    self.check_block(else_blk, cond_const)?;
+                },
+                StatementKind::Match(e, arms) => {
+                    let cond_const = cond_const && self.expr_const(e);
+                    for arm in arms {
+                        if let Some(binding) = &arm.binding {
+                            self.decls.insert(binding.clone(), Decl {
+                                ty: None, is_const: cond_const,
+                            });
+                        }
+                        self.check_block(&arm.body, cond_const)?;
+                    }
+                },
+                StatementKind::LayerBlock(_, blk) => {
+                    self.check_block(blk, cond_const)?;
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn check_connect(&self, r: &Reference, e: &Expr, cond_const: bool)
+        -> Result<(), String>
+    {
+        let (sink, sink_const, cond_const) = match r {
+            Reference::Static(sr) => {
+                let (ty, is_const) = self.ref_info(sr);
+                (ty, is_const, cond_const)
+            },
+            Reference::DynamicIndex(sr, idx) => {
+                let (ty, is_const) = self.elem_info(sr);
+                (ty, is_const, cond_const && self.expr_const(idx))
+            },
+        };
+        let (src, src_const) = self.expr_info(e);
+        let drives_const = match sink {
+            Some(sink) => drive(sink, sink_const, src, src_const),
+            None if sink_const && !src_const => None,
+            None => Some(sink_const),
+        };
+        match drives_const {
+            None => Err(format!(
+                "non-const value '{}' connected to const '{}'", e, r
+            )),
+            Some(true) if !cond_const => Err(format!(
+                "connection to const '{}' under a non-const condition", r
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Check that every `const` part of `sink` is driven by a `const` part of
+/// `src` (where an unknown source type is only `const` if all of it is).
+///
+/// Returns `None` if some `const` part is driven by a non-const value, and
+/// otherwise whether any `const` part is driven at all. The roles are
+/// swapped for flipped fields.
+fn drive(sink: &FirrtlType, sink_const: bool,
+    src: Option<&FirrtlType>, src_const: bool) -> Option<bool>
+{
+    let sink_const = sink_const || sink.is_const();
+    let src_const = src_const || src.is_some_and(|t| t.is_const());
+    let src = src.map(|t| t.without_const());
+    if sink_const {
+        return if src_const { Some(true) } else { None };
+    }
+    match sink.without_const() {
+        FirrtlType::Bundle(fields) => {
+            let mut res = false;
+            for f in fields {
+                let src_field = match src {
+                    Some(FirrtlType::Bundle(src_fields)) => {
+                        src_fields.iter().find(|sf| sf.id == f.id)
+                    },
+                    _ => None,
+                };
+                res |= match (f.flip, src_field) {
+                    (false, _) => {
+                        drive(&f.ty, false, src_field.map(|sf| &sf.ty),
+                            src_const)?
+                    },
+                    (true, Some(sf)) => {
+                        drive(&sf.ty, src_const, Some(&f.ty), false)?
+                    },
+                    // We can't tell what drives this
+                    (true, None) => false,
+                };
+            }
+            Some(res)
+        },
+        FirrtlType::Vector(elem, _) => {
+            let src_elem = match src {
+                Some(FirrtlType::Vector(src_elem, _)) => Some(&**src_elem),
+                _ => None,
+            };
+            drive(elem, false, src_elem, src_const)
+        },
+        _ => Some(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+circuit Top :
+  module Top :
+    input clock : Clock
+    input strap : const UInt<4>
+    input a : UInt<4>
+    input cfg : { mode : const UInt<2>, data : UInt<8> }
+    output o : const UInt<4>
+    wire w : { mode : const UInt<2>, data : UInt<8> }
+    inst sub of Sub
+    node n = add(strap, UInt<4>(1))
+    connect o, tail(n, 1)
+    connect w, cfg
+    connect sub.mode, w.mode
+    connect sub.data, cfg.data
+    when eq(strap, UInt<4>(0)) :
+      connect o, UInt<4>(2)
+  extmodule Sub :
+    input mode : const UInt<2>
+    input data : UInt<8>
+";
+
+    fn check(src: &str) -> Result<(), ConstError> {
+        check_const(&parse(src))
+    }
+
+    #[test]
+    fn const_connections() {
+        assert_eq!(check(SAMPLE), Ok(()));
+
+        // Non-const sources for const sinks
+        for (from, to) in [
+            ("connect o, tail(n, 1)", "connect o, a"),
+            ("connect o, tail(n, 1)", "connect o, mux(a, strap, strap)"),
+            ("connect sub.mode, w.mode", "connect sub.mode, w.data"),
+            ("connect w, cfg", "connect w.mode, a"),
+            ("node n = add(strap,", "node n = add(a,"),
+            ("input cfg : { mode : const", "input cfg : { mode :"),
+        ] {
+            let bad = SAMPLE.replace(from, to);
+            let e = check(&bad).unwrap_err();
+            assert_eq!(e.module, "Top");
+            assert!(e.message.starts_with("non-const value"), "{}", e);
+        }
+
+        // Const sinks under a non-const condition
+        let bad = SAMPLE.replace("when eq(strap,", "when eq(a,");
+        let e = check(&bad).unwrap_err();
+        assert!(e.message.contains("non-const condition"), "{}", e);
+
+        // Only the non-const parts can be driven conditionally
+        let ok = SAMPLE.replace("when eq(strap, UInt<4>(0)) :\n      \
+            connect o, UInt<4>(2)", "when a :\n      connect w.data, a");
+        assert_eq!(check(&ok), Ok(()));
+    }
+}
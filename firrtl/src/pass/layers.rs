@@ -0,0 +1,271 @@
+//! Inlining or stripping a layer from a circuit.
+//!
+//! Inlining a layer moves the contents of its `layerblock`s into the
+//! enclosing block, so that they're always part of the design. Stripping a
+//! layer removes its `layerblock`s entirely.
+//!
+//! Either way, the layer's declaration is removed from the circuit:
+//!
+//! - When inlining, any layers nested inside it become children of its
+//!   parent (ie. inlining `A.B` turns `A.B.C` into `A.C`)
+//! - When stripping, any layers nested inside it are removed along with it
+//!
+//! References to the layer in `enablelayer` and in probe colors are moved
+//! to its parent layer (or dropped, for a layer at the top of the circuit).
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// What to do with the contents of a layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayerAction {
+    /// Keep the contents of the layer in the design
+    Inline,
+    /// Remove the contents of the layer from the design
+    Strip,
+}
+
+/// An error encountered while inlining/stripping a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayerError {
+    /// There is no layer with this path in the circuit
+    Unknown(String),
+}
+impl fmt::Display for LayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Unknown(path) => write!(f, "No layer '{}' in circuit", path),
+        }
+    }
+}
+impl std::error::Error for LayerError {}
+
+/// Inline or strip the layer at `path` (ie. `A.B`) in `circuit`.
+pub fn lower_layer(circuit: &mut Circuit, path: &str, action: LayerAction)
+    -> Result<(), LayerError>
+{
+    let lowering = Lowering {
+        target: path.split('.').map(str::to_string).collect(),
+        action,
+    };
+    lowering.lower_decl(&mut circuit.layers)
+        .ok_or_else(|| LayerError::Unknown(path.to_string()))?;
+
+    for t in circuit.type_aliases.iter_mut() {
+        lowering.recolor(&mut t.ty);
+    }
+    for m in circuit.modules.iter_mut() {
+        let mut enabled: Vec<String> = Vec::new();
+        for layer in m.enabled_layers.iter() {
+            if let Some(l) = lowering.map_path(layer) {
+                if !enabled.contains(&l) {
+                    enabled.push(l);
+                }
+            }
+        }
+        m.enabled_layers = enabled;
+        for port in m.ports.iter_mut() {
+            lowering.recolor(&mut port.ty);
+        }
+        let stmts = std::mem::take(&mut m.statements);
+        m.statements = lowering.lower_block(stmts, &[]);
+    }
+    for port in circuit.intmodules.iter_mut().flat_map(|m| m.ports.iter_mut())
+    {
+        lowering.recolor(&mut port.ty);
+    }
+    for port in circuit.extmodules.iter_mut().flat_map(|m| m.ports.iter_mut())
+    {
+        lowering.recolor(&mut port.ty);
+    }
+    Ok(())
+}
+
+struct Lowering {
+    /// Path to the layer being removed
+    target: Vec<String>,
+    action: LayerAction,
+}
+impl Lowering {
+    /// Remove the target layer's declaration from `layers`.
+    ///
+    /// Returns `None` if the layer doesn't exist.
+    fn lower_decl(&self, layers: &mut Vec<Layer>) -> Option<()> {
+        let (last, parents) = self.target.split_last()?;
+        let mut layers = layers;
+        for part in parents {
+            layers = &mut layers.iter_mut().find(|l| &l.id == part)?.layers;
+        }
+        let idx = layers.iter().position(|l| &l.id == last)?;
+        let layer = layers.remove(idx);
+        if self.action == LayerAction::Inline {
+            layers.splice(idx..idx, layer.layers);
+        }
+        Some(())
+    }
+
+    /// Get the new path for a reference to some layer, or `None` if the
+    /// reference should be removed.
+    fn map_path(&self, path: &str) -> Option<String> {
+        let parts: Vec<&str> = path.split('.').collect();
+        let n = self.target.len();
+        if parts.len() < n || parts[..n] != self.target[..] {
+            return Some(path.to_string());
+        }
+        let res = match self.action {
+            LayerAction::Inline => [&parts[..n - 1], &parts[n..]].concat(),
+            LayerAction::Strip => parts[..n - 1].to_vec(),
+        };
+        if res.is_empty() { None } else { Some(res.join(".")) }
+    }
+
+    /// Update the layer colors of any probes in `ty`.
+    fn recolor(&self, ty: &mut FirrtlType) {
+        match ty {
+            FirrtlType::Vector(elem, _) |
+            FirrtlType::Const(elem) => self.recolor(elem),
+            FirrtlType::Bundle(fields) => {
+                for f in fields.iter_mut() {
+                    self.recolor(&mut f.ty);
+                }
+            },
+            FirrtlType::Enum(variants) => {
+                for ty in variants.iter_mut().filter_map(|v| v.ty.as_mut()) {
+                    self.recolor(ty);
+                }
+            },
+            FirrtlType::Ref(FirrtlTypeRef::Probe(elem, layer)) |
+            FirrtlType::Ref(FirrtlTypeRef::RWProbe(elem, layer)) => {
+                self.recolor(elem);
+                *layer = layer.as_deref().and_then(|l| self.map_path(l));
+            },
+            FirrtlType::Ground(_) | FirrtlType::Property(_) | 
+            FirrtlType::Alias(_) | FirrtlType::None => {},
+        }
+    }
+
+    /// Lower a block of statements nested inside the layer at `current`.
+    fn lower_block(&self, stmts: Vec<Statement>, current: &[String])
+        -> Vec<Statement>
+    {
+        let mut res = Vec::new();
+        for stmt in stmts {
+            let kind = match stmt.kind {
+                StatementKind::LayerBlock(id, blk) => {
+                    let mut path = current.to_vec();
+                    path.push(id.clone());
+                    if path != self.target {
+                        let blk = self.lower_block(blk, &path);
+                        StatementKind::LayerBlock(id, blk)
+                    } else {
+                        if self.action == LayerAction::Inline {
+                            res.extend(self.lower_block(blk, current));
+                        }
+                        continue;
+                    }
+                },
+                StatementKind::When(cond, when_blk, else_blk) => {
+                    StatementKind::When(cond,
+                        self.lower_block(when_blk, current),
+                        self.lower_block(else_blk, current))
+                },
+                StatementKind::Match(expr, arms) => {
+                    StatementKind::Match(expr, arms.into_iter().map(|arm| {
+                        MatchArm {
+                            body: self.lower_block(arm.body, current),
+                            ..arm
+                        }
+                    }).collect())
+                },
+                StatementKind::Wire(id, mut ty) => {
+                    self.recolor(&mut ty);
+                    StatementKind::Wire(id, ty)
+                },
+                StatementKind::Reg(id, mut ty, clk) => {
+                    self.recolor(&mut ty);
+                    StatementKind::Reg(id, ty, clk)
+                },
+                StatementKind::RegReset(mut decl) => {
+                    self.recolor(&mut decl.ty);
+                    StatementKind::RegReset(decl)
+                },
+                kind => kind,
+            };
+            res.push(Statement::new(kind, stmt.info));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+circuit Top :
+  layer Verif, bind :
+    layer Assert, bind :
+    layer Cover, inline :
+  module Top enablelayer Verif.Cover :
+    input clock : Clock
+    input a : UInt<8>
+    output p : Probe<UInt<8>, Verif.Assert>
+    layerblock Verif :
+      node b = add(a, UInt<8>(1))
+      layerblock Assert :
+        node c = eq(b, UInt<9>(0))
+        define p = probe(c)
+        assert(clock, c, UInt<1>(1), \"\")
+      layerblock Cover :
+        cover(clock, b, UInt<1>(1), \"\")
+";
+
+    #[test]
+    fn inline_layer() {
+        let mut circuit = parse(SAMPLE);
+        lower_layer(&mut circuit, "Verif", LayerAction::Inline).unwrap();
+        assert_eq!(circuit, parse("\
+circuit Top :
+  layer Assert, bind :
+  layer Cover, inline :
+  module Top enablelayer Cover :
+    input clock : Clock
+    input a : UInt<8>
+    output p : Probe<UInt<8>, Assert>
+    node b = add(a, UInt<8>(1))
+    layerblock Assert :
+      node c = eq(b, UInt<9>(0))
+      define p = probe(c)
+      assert(clock, c, UInt<1>(1), \"\")
+    layerblock Cover :
+      cover(clock, b, UInt<1>(1), \"\")
+"));
+    }
+
+    #[test]
+    fn strip_layer() {
+        let mut circuit = parse(SAMPLE);
+        lower_layer(&mut circuit, "Verif.Assert", LayerAction::Strip).unwrap();
+        assert!(circuit.layer("Verif.Assert").is_none());
+        assert!(circuit.layer("Verif.Cover").is_some());
+        let text = circuit.to_string();
+        assert!(!text.contains("assert"), "{}", text);
+        assert!(text.contains("output p : Probe<UInt<8>, Verif>"), "{}", text);
+
+        let mut circuit = parse(SAMPLE);
+        lower_layer(&mut circuit, "Verif", LayerAction::Strip).unwrap();
+        assert!(circuit.layers.is_empty());
+        assert!(circuit.modules[0].enabled_layers.is_empty());
+        assert_eq!(circuit.modules[0].statements, Vec::new());
+    }
+
+    #[test]
+    fn unknown_layer() {
+        let mut circuit = parse(SAMPLE);
+        let e = lower_layer(&mut circuit, "Verif.Nope", LayerAction::Strip);
+        assert_eq!(e, Err(LayerError::Unknown("Verif.Nope".to_string())));
+        assert_eq!(circuit, parse(SAMPLE));
+    }
+}
@@ -0,0 +1,171 @@
+//! Specializing a circuit for some assignment of its option groups.
+//!
+//! Every `instchoice` is replaced with an `inst` of the module selected by
+//! the case assigned to its option group. Instance choices for an option
+//! without an assigned case use their default module.
+//!
+//! Since there are no `instchoice` statements left afterwards, the option
+//! group declarations are removed from the circuit.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::*;
+
+/// An error encountered while specializing a circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecializeError {
+    /// There is no option group with this name in the circuit
+    UnknownOption(String),
+    /// The option group (first) has no case with this name (second)
+    UnknownCase(String, String),
+}
+impl fmt::Display for SpecializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::UnknownOption(id) => {
+                write!(f, "No option '{}' in circuit", id)
+            },
+            Self::UnknownCase(id, case) => {
+                write!(f, "Option '{}' has no case '{}'", id, case)
+            },
+        }
+    }
+}
+impl std::error::Error for SpecializeError {}
+
+/// Resolve every `instchoice` in `circuit`, where `choices` maps the name
+/// of an option group to the selected case.
+///
+/// The circuit is left unchanged if this fails.
+pub fn specialize(circuit: &mut Circuit, choices: &HashMap<String, String>)
+    -> Result<(), SpecializeError>
+{
+    for (id, case) in choices {
+        let option = circuit.option(id)
+            .ok_or_else(|| SpecializeError::UnknownOption(id.clone()))?;
+        if !option.has_case(case) {
+            return Err(SpecializeError::UnknownCase(id.clone(), case.clone()));
+        }
+    }
+
+    let mut bodies = Vec::new();
+    for m in circuit.modules.iter() {
+        bodies.push(specialize_block(circuit, choices, &m.statements)?);
+    }
+    for (m, body) in circuit.modules.iter_mut().zip(bodies) {
+        m.statements = body;
+    }
+    circuit.options.clear();
+    Ok(())
+}
+
+fn specialize_block(circuit: &Circuit, choices: &HashMap<String, String>,
+    stmts: &[Statement]) -> Result<Vec<Statement>, SpecializeError>
+{
+    let block = |stmts| specialize_block(circuit, choices, stmts);
+    let mut res = Vec::new();
+    for stmt in stmts {
+        let kind = match &stmt.kind {
+            StatementKind::InstChoice(choice) => {
+                if circuit.option(&choice.option).is_none() {
+                    return Err(SpecializeError::UnknownOption(
+                        choice.option.clone()));
+                }
+                let case = choices.get(&choice.option).map(String::as_str);
+                StatementKind::Inst(choice.id.clone(),
+                    choice.target(case).to_string())
+            },
+            StatementKind::When(cond, when_blk, else_blk) => {
+                StatementKind::When(cond.clone(), block(when_blk)?,
+                    block(else_blk)?)
+            },
+            StatementKind::Match(expr, arms) => {
+                let mut res_arms = Vec::new();
+                for arm in arms {
+                    res_arms.push(MatchArm {
+                        body: block(&arm.body)?,
+                        ..arm.clone()
+                    });
+                }
+                StatementKind::Match(expr.clone(), res_arms)
+            },
+            StatementKind::LayerBlock(id, blk) => {
+                StatementKind::LayerBlock(id.clone(), block(blk)?)
+            },
+            kind => kind.clone(),
+        };
+        res.push(Statement::new(kind, stmt.info.clone()));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    const SAMPLE: &str = "\
+circuit Top :
+  option Platform :
+    FPGA
+    ASIC
+  public module Top :
+    input clock : Clock
+    instchoice mem of SimMem, Platform :
+      FPGA => FpgaMem
+      ASIC => AsicMem
+    when UInt<1>(1) :
+      instchoice pll of SimPll, Platform :
+        ASIC => AsicPll
+  extmodule SimMem :
+  extmodule FpgaMem :
+  extmodule AsicMem :
+  extmodule SimPll :
+  extmodule AsicPll :
+";
+
+    fn choices(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn specialize_cases() {
+        let mut circuit = parse(SAMPLE);
+        specialize(&mut circuit, &choices(&[("Platform", "ASIC")])).unwrap();
+        let text = circuit.to_string();
+        assert!(circuit.options.is_empty());
+        assert!(text.contains("    inst mem of AsicMem\n"), "{}", text);
+        assert!(text.contains("      inst pll of AsicPll\n"), "{}", text);
+
+        // A case without a module (or no case at all) uses the default
+        let mut circuit = parse(SAMPLE);
+        specialize(&mut circuit, &choices(&[("Platform", "FPGA")])).unwrap();
+        let text = circuit.to_string();
+        assert!(text.contains("    inst mem of FpgaMem\n"), "{}", text);
+        assert!(text.contains("      inst pll of SimPll\n"), "{}", text);
+
+        let mut circuit = parse(SAMPLE);
+        specialize(&mut circuit, &HashMap::new()).unwrap();
+        let text = circuit.to_string();
+        assert!(text.contains("    inst mem of SimMem\n"), "{}", text);
+        assert!(!text.contains("instchoice"), "{}", text);
+    }
+
+    #[test]
+    fn specialize_errors() {
+        let mut circuit = parse(SAMPLE);
+        let e = specialize(&mut circuit, &choices(&[("Speed", "Fast")]));
+        assert_eq!(e, Err(SpecializeError::UnknownOption("Speed".to_string())));
+        let e = specialize(&mut circuit, &choices(&[("Platform", "GPU")]));
+        assert_eq!(e, Err(SpecializeError::UnknownCase(
+            "Platform".to_string(), "GPU".to_string())));
+
+        let mut circuit = parse(&SAMPLE.replace("Platform :\n    FPGA",
+            "Other :\n    FPGA"));
+        let e = specialize(&mut circuit, &HashMap::new());
+        assert_eq!(e, Err(SpecializeError::UnknownOption(
+            "Platform".to_string())));
+        assert_eq!(circuit.options.len(), 1);
+    }
+}
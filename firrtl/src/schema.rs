@@ -0,0 +1,186 @@
+//! JSON schema for serialized circuits (with the `serde` feature).
+//!
+//! The layout is the default representation produced by `serde_json` for
+//! the types in [crate::ast]. Any change to that layout must also bump the
+//! version in the schema's `$id`.
+
+/// JSON schema describing a serialized [crate::ast::Circuit].
+pub const JSON_SCHEMA: &str = include_str!("../schema/circuit.schema.json");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Circuit;
+    use crate::test_util::parse;
+    use serde_json::Value;
+
+    const SAMPLE: &str = "\
+FIRRTL version 4.0.0
+circuit Top : @[Top.scala 1:1]
+  layer Verif, bind, \"verif\" :
+    layer Assert, inline :
+  option Platform :
+    FPGA
+  type Byte = UInt<8>
+  public module Top enablelayer Verif :
+    input clock : Clock
+    input a : { flip x : UInt<8>, y : SInt<4>[2] }
+    output b : UInt
+    output d : Byte[2]
+    output c : Probe<UInt<8>>
+    output v : Probe<UInt<8>, Verif.Assert>
+    input e : {| A, B : UInt<8> |}
+    input cfg : { mode : const UInt<2>, data : UInt<8> }[2]
+    output meta : AnyRef
+    regreset r : UInt<8>, clock, a.x, UInt<8>(0)
+    reg r2 : UInt<8>, clock
+    regreset r3 : UInt<8>, clock, a.x, r2
+    mem m :
+      data-type => UInt<8>
+      depth => 16
+      read-latency => 0
+      write-latency => 1
+      read-under-write => undefined
+      reader => r0
+    object o of Meta
+    instchoice sub of Sub, Platform :
+      FPGA => Sub
+    propassign o.w, Integer(8)
+    propassign meta, o
+    cmem cm : UInt<8>[4]
+    smem sm : UInt<8>[4], old
+    infer mport mp = cm[UInt<2>(0)], clock
+    read mport sp = sm[UInt<2>(1)], clock
+    when eq(r, UInt(0)) :
+      connect b, r
+    else :
+      invalidate b
+    printf(clock, UInt(1), \"%d\", bits(a.y[1], 3, 0)) : p0
+    stop(clock, a.x, 1) : done
+    define c = probe(r)
+    node rd = read(probe(a)).y[0]
+    node sel = mux(a.x, a.y, a.y)[a.x]
+    assert(clock, eq(r, r), UInt<1>(1), \"r=%d\", r) : r_ok
+    cover(clock, a.x, UInt<1>(1), \"\")
+    node t = intrinsic(circt_plusargs_test<FORMAT = \"foo\"> : UInt<1>)
+    intrinsic(circt_trace<DEPTH = 2>, clock, t)
+    layerblock Verif :
+      layerblock Assert :
+        define v = probe(r)
+    match e :
+      A :
+        skip
+      B(x) :
+        node y = {| A, B : UInt<8> |}(B, x)
+  intmodule Plus :
+    output out : UInt<8>
+    intrinsic = circt_plus
+  extmodule Sub :
+    input in : UInt<8>
+    output p : Probe<UInt<8>>
+    defname = SubImpl
+    parameter WIDTH = 8
+    parameter OFFSET = -3
+    parameter SCALE = 0.5
+    parameter NAME = \"sub\"
+    ref p is \"sub.x\"
+  class Meta :
+    input w : Integer
+    output l : List<Integer>
+    output s : String
+    output b : Bool
+    output x : Double
+    output p : Path
+    output i : Inst<Ext>
+    object ext of Ext
+    propassign l, List<Integer>(w, integer_add(w, Integer(-1)))
+    propassign s, String(\"meta\")
+    propassign b, Bool(true)
+    propassign x, Double(0.5)
+    propassign p, Path(\"OMDeleted:\")
+    propassign i, ext
+  extclass Ext :
+    output d : Double
+";
+
+    /// Check `value` against (the subset of JSON schema used by) `schema`.
+    fn validate(root: &Value, schema: &Value, value: &Value, path: &str)
+        -> Result<(), String>
+    {
+        let err = |msg: &str| Err(format!("{}: {} ({})", path, msg, value));
+        if let Some(r) = schema.get("$ref").and_then(|r| r.as_str()) {
+            let name = r.trim_start_matches("#/$defs/");
+            return validate(root, &root["$defs"][name], value, path);
+        }
+        if let Some(alts) = schema.get("oneOf").and_then(|a| a.as_array()) {
+            let ok = alts.iter()
+                .filter(|s| validate(root, s, value, path).is_ok())
+                .count();
+            return if ok == 1 { Ok(()) } else { err("no unique match") };
+        }
+        if let Some(vals) = schema.get("enum").and_then(|e| e.as_array()) {
+            return if vals.contains(value) { Ok(()) } else { err("enum") };
+        }
+        match schema.get("type").and_then(|t| t.as_str()) {
+            Some("null") if !value.is_null() => return err("null"),
+            Some("string") if !value.is_string() => return err("string"),
+            Some("boolean") if !value.is_boolean() => return err("boolean"),
+            Some("integer") => {
+                let min_ok = match schema.get("minimum") {
+                    Some(_) => value.is_u64(),
+                    None => value.is_i64() || value.is_u64(),
+                };
+                if !min_ok { return err("integer"); }
+            },
+            Some("object") => {
+                let Some(obj) = value.as_object() else { return err("object") };
+                let props = schema["properties"].as_object().unwrap();
+                for (k, v) in obj {
+                    let Some(s) = props.get(k) else {
+                        return err(&format!("unexpected key '{}'", k));
+                    };
+                    validate(root, s, v, &format!("{}.{}", path, k))?;
+                }
+                if props.keys().any(|k| !obj.contains_key(k)) {
+                    return err("missing key");
+                }
+            },
+            Some("array") => {
+                let Some(arr) = value.as_array() else { return err("array") };
+                if let Some(items) = schema.get("prefixItems") {
+                    let items = items.as_array().unwrap();
+                    if items.len() != arr.len() { return err("length"); }
+                    for (idx, (s, v)) in items.iter().zip(arr).enumerate() {
+                        validate(root, s, v, &format!("{}[{}]", path, idx))?;
+                    }
+                } else {
+                    for (idx, v) in arr.iter().enumerate() {
+                        validate(root, &schema["items"], v,
+                            &format!("{}[{}]", path, idx))?;
+                    }
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn sample() -> Circuit {
+        parse(SAMPLE)
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let circuit = sample();
+        let json = serde_json::to_string(&circuit).unwrap();
+        let res: Circuit = serde_json::from_str(&json).unwrap();
+        assert_eq!(res, circuit);
+    }
+
+    #[test]
+    fn json_matches_schema() {
+        let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+        let value = serde_json::to_value(sample()).unwrap();
+        validate(&schema, &schema, &value, "$").unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+//! Helpers shared by the unit tests in this crate.
+
+use crate::ast::Circuit;
+use crate::file::FirrtlFile;
+use crate::lex::ParseErrorKind;
+
+/// Parse a circuit from `src`, panicking on any error.
+pub fn parse(src: &str) -> Circuit {
+    FirrtlFile::from_str("test.fir", src)
+        .and_then(|ff| ff.parse())
+        .unwrap_or_else(|e| panic!("{}\n{}", e, src))
+}
+
+/// Parse `src` and check that it fails on `line` with the same kind of
+/// error as `kind`.
+pub fn check_err(src: &str, line: usize, kind: ParseErrorKind) {
+    let err = FirrtlFile::from_str("test.fir", src)
+        .and_then(|ff| ff.parse())
+        .expect_err(src);
+    assert_eq!(std::mem::discriminant(&err.kind),
+        std::mem::discriminant(&kind), "{}\n{}", err, src);
+    assert_eq!(err.line, line, "{}\n{}", err, src);
+}
@@ -1,6 +1,7 @@
 
 use logos::Logos;
 use crate::ast;
+use num_bigint::{ BigInt, BigUint, Sign };
 
 /// A primitive token that might occur in a FIRRTL file.
 ///
@@ -28,14 +29,19 @@ pub enum Token {
     #[regex("[0-9]+", |lex| lex.slice().parse().ok())]
     LiteralInt(String),
 
-    /// A literal floating-point value
-    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse().ok())]
+    /// A literal floating-point value, ie. `1.5`, `-0.25` or `1.0E-3`
+    #[regex(r"[+-]?[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", 
+        |lex| lex.slice().parse().ok())]
     LiteralFloat(String),
 
     /// A literal signed integer value
     #[regex("[+-][0-9]+", |lex| lex.slice().parse().ok())]
     LiteralSInt(String),
 
+    /// A literal integer value with a radix specifier, ie. `0h1F`
+    #[regex("[+-]?0[bodh][0-9a-fA-F]+", |lex| lex.slice().parse().ok())]
+    LiteralRadixInt(String),
+
     #[token(".")]  Period,
     #[token(":")]  Colon,
     #[token("?")]  Question,
@@ -43,6 +49,8 @@ pub enum Token {
     #[token(")")]  RParen,
     #[token("{")]  LBrace,
     #[token("}")]  RBrace,
+    #[token("{|")] LBraceBar,
+    #[token("|}")] BarRBrace,
     #[token("[")]  LSquare,
     #[token("]")]  RSquare,
     #[token("<")]  Less,
@@ -62,6 +70,8 @@ impl Token {
             ")"  => Self::RParen,
             "{"  => Self::LBrace,
             "}"  => Self::RBrace,
+            "{|" => Self::LBraceBar,
+            "|}" => Self::BarRBrace,
             "["  => Self::LSquare,
             "]"  => Self::RSquare,
             "<"  => Self::Less,
@@ -76,6 +86,36 @@ impl Token {
 }
 
 impl Token {
+    /// Returns the text of this token as it appeared in the source.
+    pub fn text(&self) -> &str {
+        match self {
+            Self::IdentKw(s) 
+            | Self::LiteralString(s) 
+            | Self::RawString(s) 
+            | Self::LiteralInt(s) 
+            | Self::LiteralFloat(s) 
+            | Self::LiteralSInt(s) 
+            | Self::LiteralRadixInt(s) => s,
+            Self::Period       => ".",
+            Self::Colon        => ":",
+            Self::Question     => "?",
+            Self::LParen       => "(",
+            Self::RParen       => ")",
+            Self::LBrace       => "{",
+            Self::RBrace       => "}",
+            Self::LBraceBar    => "{|",
+            Self::BarRBrace    => "|}",
+            Self::LSquare      => "[",
+            Self::RSquare      => "]",
+            Self::Less         => "<",
+            Self::LessMinus    => "<-",
+            Self::LessEqual    => "<=",
+            Self::Greater      => ">",
+            Self::Equal        => "=",
+            Self::EqualGreater => "=>",
+        }
+    }
+
     pub fn is_lit_int(&self) -> bool {
         matches!(self, Token::LiteralInt(_))
     }
@@ -97,6 +137,8 @@ impl Token {
             | Self::RParen 
             | Self::LBrace 
             | Self::RBrace 
+            | Self::LBraceBar 
+            | Self::BarRBrace 
             | Self::LSquare 
             | Self::RSquare 
             | Self::Less 
@@ -146,65 +188,72 @@ impl Token {
         }
     }
 
-    pub fn get_unsigned_numeric_literal(&self) -> Option<ast::LiteralNumeric> {
+    /// Returns the value of some integer literal token, ie. `12`, `-12`,
+    /// `0h1F`, `-0b101`, `"h1F"` or `"h-1F"`.
+    fn get_integer_literal(&self) -> Option<BigInt> {
         match self {
-            Token::LiteralInt(s) => {
-                Some(ast::LiteralNumeric::UInt(s.parse().unwrap()))
+            Token::LiteralInt(s) | Token::LiteralSInt(s) => {
+                let (neg, digits) = strip_sign(s);
+                parse_digits(neg, digits, 10)
+            },
+            Token::LiteralRadixInt(s) => {
+                let (neg, rest) = strip_sign(s);
+                parse_radix_digits(neg, rest.strip_prefix('0')?)
             },
             Token::LiteralString(s) => {
                 let slice = &s[1..s.len()-1];
-                if let Some(hex_num) = slice.strip_prefix('h') {
-                    Some(ast::LiteralNumeric::UInt(
-                        usize::from_str_radix(hex_num, 16).unwrap())
-                    )
-                }
-                else if let Some(oct_num) = slice.strip_prefix('o') {
-                    Some(ast::LiteralNumeric::UInt(
-                        usize::from_str_radix(oct_num, 8).unwrap())
-                    )
-                }
-                else if let Some(bin_num) = slice.strip_prefix('b') {
-                    Some(ast::LiteralNumeric::UInt(
-                        usize::from_str_radix(bin_num, 2).unwrap())
-                    )
-                } else {
-                    panic!("unexpected numeric unsigned literal format {:?}", s);
-                }
+                let (neg, rest) = strip_sign(slice);
+                parse_radix_digits(neg, rest)
             },
-            _ => panic!("unexpected token {:?} for unsigned literal", self),
+            _ => None,
         }
     }
+
+    /// Returns `None` if this token is not a valid unsigned literal.
+    pub fn get_unsigned_numeric_literal(&self) -> Option<ast::LiteralNumeric> {
+        let val = self.get_integer_literal()?.to_biguint()?;
+        Some(ast::LiteralNumeric::UInt(val))
+    }
+
+    /// Returns `None` if this token is not a valid signed literal.
     pub fn get_signed_numeric_literal(&self) -> Option<ast::LiteralNumeric> {
-        match self {
-            Token::LiteralSInt(s) => {
-                Some(ast::LiteralNumeric::SInt(s.parse().unwrap()))
-            },
-            Token::LiteralInt(s) => {
-                Some(ast::LiteralNumeric::SInt(s.parse().unwrap()))
-            },
-            Token::LiteralString(s) => {
-                let slice = &s[1..s.len()-1];
-                if let Some(hex_num) = slice.strip_prefix('h') {
-                    Some(ast::LiteralNumeric::SInt(
-                        isize::from_str_radix(hex_num, 16).unwrap())
-                    )
-                }
-                else if let Some(oct_num) = slice.strip_prefix('o') {
-                    Some(ast::LiteralNumeric::SInt(
-                        isize::from_str_radix(oct_num, 8).unwrap())
-                    )
-                }
-                else if let Some(bin_num) = slice.strip_prefix('b') {
-                    Some(ast::LiteralNumeric::SInt(
-                        isize::from_str_radix(bin_num, 2).unwrap())
-                    )
-                } else {
-                    panic!("unexpected numeric signed literal format {:?}", s);
-                }
-            },
-            _ => panic!("unexpected token {:?} for signed literal", self),
-        }
+        let val = self.get_integer_literal()?;
+        Some(ast::LiteralNumeric::SInt(val))
     }
 
 }
 
+/// Split an optional leading sign from `s`. Returns 'true' if negative.
+fn strip_sign(s: &str) -> (bool, &str) {
+    if let Some(rest) = s.strip_prefix('-') {
+        (true, rest)
+    } else {
+        (false, s.strip_prefix('+').unwrap_or(s))
+    }
+}
+
+/// Parse digits with a leading radix specifier, ie. `h1F` or `h-1F`.
+fn parse_radix_digits(neg: bool, s: &str) -> Option<BigInt> {
+    let radix = match s.chars().next()? {
+        'b' => 2, 'o' => 8, 'd' => 10, 'h' => 16,
+        _ => return None,
+    };
+    let rest = &s[1..];
+    let (inner_neg, digits) = strip_sign(rest);
+    // There can only be one sign
+    if neg && digits.len() != rest.len() {
+        return None;
+    }
+    parse_digits(neg || inner_neg, digits, radix)
+}
+
+/// Parse unsigned digits in some radix.
+fn parse_digits(neg: bool, digits: &str, radix: u32) -> Option<BigInt> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let mag = BigUint::parse_bytes(digits.as_bytes(), radix)?;
+    let sign = if neg { Sign::Minus } else { Sign::Plus };
+    Some(BigInt::from_biguint(sign, mag))
+}
+
@@ -0,0 +1,421 @@
+//! The syntax allowed by each version of the FIRRTL specification.
+//!
+//! When a file has a `FIRRTL version` header, the parser only accepts
+//! the [Feature]s that exist in that version. Files without a header
+//! are parsed permissively.
+//!
+//! When emitting for some target version, [check_circuit] finds anything
+//! in a circuit that can't be written for that version. Some constructs
+//! are written differently depending on the version instead (ie.
+//! `connect a, b` is written as `a <= b` before 3.0.0).
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// The most recent version of the specification supported here.
+pub const LATEST: Version = Version::new(4, 0, 0);
+
+/// Some syntax that was added to (or removed from) the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+    /// Legacy connects and invalidation (`<=`, `<-` and `is invalid`)
+    LegacyConnect,
+    /// Legacy reset registers (`reg ... with : (reset => (...))`)
+    LegacyRegReset,
+    /// Probe types, `define`, `force`/`release` and `probe`/`read`
+    Probes,
+    /// `connect` and `invalidate`
+    Connect,
+    /// `regreset`
+    RegReset,
+    /// Enumeration types, expressions and `match`
+    Enums,
+    /// `const` types
+    Const,
+    /// Property types, expressions and `propassign`
+    Properties,
+    /// `class`, `extclass` and `object`
+    Classes,
+    /// `layer`, `layerblock`, `enablelayer` and layer-colored probes
+    Layers,
+    /// Type alias declarations (`type`)
+    TypeAliases,
+    /// `public` modules
+    PublicModules,
+    /// Intrinsic expressions and statements (`intrinsic(...)`)
+    Intrinsics,
+    /// Option groups and `instchoice`
+    OptionGroups,
+}
+impl Feature {
+    /// The version that introduced this feature (if it isn't part of the
+    /// original specification).
+    pub fn since(&self) -> Option<Version> {
+        match self {
+            Self::LegacyConnect | Self::LegacyRegReset => None,
+            Self::Probes => Some(Version::new(2, 0, 0)),
+            Self::Enums => Some(Version::new(2, 1, 0)),
+            Self::Connect | Self::RegReset | Self::Const => {
+                Some(Version::new(3, 0, 0))
+            },
+            Self::Properties => Some(Version::new(3, 1, 0)),
+            Self::Classes | Self::Layers => Some(Version::new(3, 2, 0)),
+            Self::TypeAliases | Self::PublicModules => {
+                Some(Version::new(3, 3, 0))
+            },
+            Self::Intrinsics | Self::OptionGroups => {
+                Some(Version::new(4, 0, 0))
+            },
+        }
+    }
+
+    /// The version that removed this feature (if any).
+    pub fn until(&self) -> Option<Version> {
+        match self {
+            Self::LegacyConnect | Self::LegacyRegReset => {
+                Some(Version::new(3, 0, 0))
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns 'true' if this feature is part of version `v`.
+    pub fn supported(&self, v: Version) -> bool {
+        self.since().is_none_or(|since| v >= since) &&
+            self.until().is_none_or(|until| v < until)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::LegacyConnect => "'<=', '<-' and 'is invalid'",
+            Self::LegacyRegReset => "'reg' with a reset",
+            Self::Connect => "'connect' and 'invalidate'",
+            Self::RegReset => "'regreset'",
+            Self::Probes => "probes",
+            Self::Enums => "enumerations",
+            Self::Const => "'const' types",
+            Self::Properties => "properties",
+            Self::Classes => "classes",
+            Self::Layers => "layers",
+            Self::TypeAliases => "type aliases",
+            Self::PublicModules => "public modules",
+            Self::Intrinsics => "intrinsic expressions and statements",
+            Self::OptionGroups => "option groups",
+        }
+    }
+}
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Some feature isn't part of a particular version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionError {
+    pub feature: Feature,
+    pub version: Version,
+}
+impl VersionError {
+    /// Returns an error if `feature` isn't part of `version`.
+    pub fn check(feature: Feature, version: Version) -> Result<(), Self> {
+        if feature.supported(version) {
+            Ok(())
+        } else {
+            Err(Self { feature, version })
+        }
+    }
+}
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        // NOTE: Feature descriptions may be singular or plural, so they
+        // only ever appear as the object here
+        write!(f, "FIRRTL version {} does not support {}", self.version,
+            self.feature)?;
+        match (self.feature.since(), self.feature.until()) {
+            (Some(since), _) if self.version < since => {
+                write!(f, " (added in {})", since)
+            },
+            (_, Some(until)) => write!(f, " (removed in {})", until),
+            _ => Ok(()),
+        }
+    }
+}
+impl std::error::Error for VersionError {}
+
+/// Check that everything in `circuit` can be written for `version`.
+pub fn check_circuit(circuit: &Circuit, version: Version)
+    -> Result<(), VersionError>
+{
+    let c = Checker { version };
+    if !circuit.layers.is_empty() {
+        c.require(Feature::Layers)?;
+    }
+    if !circuit.options.is_empty() {
+        c.require(Feature::OptionGroups)?;
+    }
+    for t in circuit.type_aliases.iter() {
+        c.require(Feature::TypeAliases)?;
+        c.check_type(&t.ty)?;
+    }
+    for m in circuit.modules.iter() {
+        if m.public {
+            c.require(Feature::PublicModules)?;
+        }
+        if !m.enabled_layers.is_empty() {
+            c.require(Feature::Layers)?;
+        }
+        c.check_ports(&m.ports)?;
+        c.check_block(&m.statements)?;
+    }
+    for m in circuit.intmodules.iter() {
+        c.check_ports(&m.ports)?;
+    }
+    for m in circuit.extmodules.iter() {
+        c.check_ports(&m.ports)?;
+    }
+    for cls in circuit.classes.iter() {
+        c.require(Feature::Classes)?;
+        c.check_ports(&cls.ports)?;
+        c.check_block(&cls.statements)?;
+    }
+    for cls in circuit.extclasses.iter() {
+        c.require(Feature::Classes)?;
+        c.check_ports(&cls.ports)?;
+    }
+    Ok(())
+}
+
+struct Checker {
+    version: Version,
+}
+impl Checker {
+    fn require(&self, feature: Feature) -> Result<(), VersionError> {
+        VersionError::check(feature, self.version)
+    }
+
+    fn check_ports(&self, ports: &[PortDecl]) -> Result<(), VersionError> {
+        ports.iter().try_for_each(|p| self.check_type(&p.ty))
+    }
+
+    fn check_type(&self, ty: &FirrtlType) -> Result<(), VersionError> {
+        match ty {
+            FirrtlType::Vector(elem, _) => self.check_type(elem),
+            FirrtlType::Const(elem) => {
+                self.require(Feature::Const)?;
+                self.check_type(elem)
+            },
+            FirrtlType::Bundle(fields) => {
+                fields.iter().try_for_each(|f| self.check_type(&f.ty))
+            },
+            FirrtlType::Enum(variants) => {
+                self.require(Feature::Enums)?;
+                variants.iter().filter_map(|v| v.ty.as_ref())
+                    .try_for_each(|ty| self.check_type(ty))
+            },
+            FirrtlType::Ref(FirrtlTypeRef::Probe(elem, layer)) |
+            FirrtlType::Ref(FirrtlTypeRef::RWProbe(elem, layer)) => {
+                self.require(Feature::Probes)?;
+                if layer.is_some() {
+                    self.require(Feature::Layers)?;
+                }
+                self.check_type(elem)
+            },
+            FirrtlType::Property(_) => self.require(Feature::Properties),
+            FirrtlType::Alias(_) => self.require(Feature::TypeAliases),
+            FirrtlType::Ground(_) | FirrtlType::None => Ok(()),
+        }
+    }
+
+    fn check_exprs<'e>(&self, exprs: impl IntoIterator<Item=&'e Expr>)
+        -> Result<(), VersionError>
+    {
+        exprs.into_iter().try_for_each(|e| self.check_expr(e))
+    }
+
+    fn check_expr(&self, e: &Expr) -> Result<(), VersionError> {
+        match e {
+            Expr::Ref(r) => self.check_ref(r),
+            Expr::Mux(e1, e2, e3) => self.check_exprs([&**e1, e2, e3]),
+            Expr::PrimOp2Expr(_, e1, e2) => self.check_exprs([&**e1, e2]),
+            Expr::PrimOp1Expr(_, e1) |
+            Expr::PrimOp1Expr1Int(_, e1, _) |
+            Expr::PrimOp1Expr2Int(_, e1, _, _) |
+            Expr::Subfield(e1, _) |
+            Expr::Subindex(e1, _) => self.check_expr(e1),
+            Expr::Subaccess(e1, idx) => self.check_exprs([&**e1, idx]),
+            Expr::Enum(ty, _, data) => {
+                self.require(Feature::Enums)?;
+                self.check_type(ty)?;
+                self.check_exprs(data.as_deref())
+            },
+            Expr::Property(_) => self.require(Feature::Properties),
+            Expr::List(_, elems) | Expr::PropPrimOp(_, elems) => {
+                self.require(Feature::Properties)?;
+                self.check_exprs(elems)
+            },
+            Expr::Intrinsic(intr) => self.check_intrinsic(intr),
+            Expr::Read(_) => self.require(Feature::Probes),
+            Expr::Const(..) | Expr::None => Ok(()),
+        }
+    }
+
+    fn check_ref(&self, r: &Reference) -> Result<(), VersionError> {
+        match r {
+            Reference::DynamicIndex(_, idx) => self.check_expr(idx),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_intrinsic(&self, intr: &Intrinsic) -> Result<(), VersionError> {
+        self.require(Feature::Intrinsics)?;
+        if let Some(ty) = &intr.ty {
+            self.check_type(ty)?;
+        }
+        self.check_exprs(&intr.args)
+    }
+
+    fn check_block(&self, stmts: &[Statement]) -> Result<(), VersionError> {
+        stmts.iter().try_for_each(|s| self.check_statement(&s.kind))
+    }
+
+    fn check_statement(&self, kind: &StatementKind)
+        -> Result<(), VersionError>
+    {
+        match kind {
+            StatementKind::Wire(_, ty) => self.check_type(ty),
+            StatementKind::Reg(_, ty, clk) => {
+                self.check_type(ty)?;
+                self.check_expr(clk)
+            },
+            StatementKind::RegReset(decl) => {
+                self.check_type(&decl.ty)?;
+                self.check_exprs([&decl.clock, &decl.reset, &decl.init])
+            },
+            StatementKind::InstChoice(_) => {
+                self.require(Feature::OptionGroups)
+            },
+            StatementKind::Object(..) => self.require(Feature::Classes),
+            StatementKind::Mem(decl) => self.check_type(&decl.ty),
+            StatementKind::Node(_, e) => self.check_expr(e),
+            StatementKind::ChirrtlMem(decl) => self.check_type(&decl.ty),
+            StatementKind::MemPort(decl) => {
+                self.check_exprs([&decl.addr, &decl.clock])
+            },
+            StatementKind::Attach(refs) => {
+                refs.iter().try_for_each(|r| self.check_ref(r))
+            },
+            // There's no equivalent for a partial connect in newer versions
+            StatementKind::PartialConnect(r, e) => {
+                self.require(Feature::LegacyConnect)?;
+                self.check_ref(r)?;
+                self.check_expr(e)
+            },
+            StatementKind::Connect(r, e) => {
+                self.check_ref(r)?;
+                self.check_expr(e)
+            },
+            StatementKind::PropAssign(_, e) => {
+                self.require(Feature::Properties)?;
+                self.check_expr(e)
+            },
+            StatementKind::Invalidate(r) => self.check_ref(r),
+            StatementKind::When(cond, when_blk, else_blk) => {
+                self.check_expr(cond)?;
+                self.check_block(when_blk)?;
+                self.check_block(else_blk)
+            },
+            StatementKind::Match(e, arms) => {
+                self.require(Feature::Enums)?;
+                self.check_expr(e)?;
+                arms.iter().try_for_each(|arm| self.check_block(&arm.body))
+            },
+            StatementKind::LayerBlock(_, blk) => {
+                self.require(Feature::Layers)?;
+                self.check_block(blk)
+            },
+            StatementKind::Stop(clk, en, ..) => self.check_exprs([clk, en]),
+            StatementKind::Release(clk, en, _) => {
+                self.require(Feature::Probes)?;
+                self.check_exprs([clk, en])
+            },
+            StatementKind::Force(clk, en, _, e) => {
+                self.require(Feature::Probes)?;
+                self.check_exprs([clk, en, e])
+            },
+            StatementKind::ForceInitial(_, e) => {
+                self.require(Feature::Probes)?;
+                self.check_expr(e)
+            },
+            StatementKind::ReleaseInitial(_) |
+            StatementKind::Define(..) => self.require(Feature::Probes),
+            StatementKind::Printf(p) => {
+                self.check_exprs([&p.clock, &p.en])?;
+                self.check_exprs(&p.args)
+            },
+            StatementKind::Assert(v) |
+            StatementKind::Assume(v) |
+            StatementKind::Cover(v) => {
+                self.check_exprs([&v.clock, &v.pred, &v.en])?;
+                self.check_exprs(&v.args)
+            },
+            StatementKind::Intrinsic(intr) => self.check_intrinsic(intr),
+            StatementKind::Inst(..) |
+            StatementKind::Skip => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[test]
+    fn features() {
+        let v2 = Version::new(2, 0, 0);
+        let v3 = Version::new(3, 0, 0);
+        assert!(Feature::LegacyConnect.supported(v2));
+        assert!(!Feature::LegacyConnect.supported(v3));
+        assert!(!Feature::Connect.supported(v2));
+        assert!(Feature::Connect.supported(v3));
+        assert!(Feature::Layers.supported(LATEST));
+        assert!(!Feature::Layers.supported(Version::new(3, 1, 9)));
+
+        let e = VersionError::check(Feature::Layers, v3).unwrap_err();
+        assert_eq!(e.to_string(),
+            "FIRRTL version 3.0.0 does not support layers (added in 3.2.0)");
+        let e = VersionError::check(Feature::LegacyConnect, v3).unwrap_err();
+        assert_eq!(e.to_string(), "FIRRTL version 3.0.0 does not support \
+            '<=', '<-' and 'is invalid' (removed in 3.0.0)");
+        let e = VersionError::check(Feature::RegReset, v2).unwrap_err();
+        assert_eq!(e.to_string(), "FIRRTL version 2.0.0 does not support \
+            'regreset' (added in 3.0.0)");
+    }
+
+    #[test]
+    fn check_nested() {
+        let src = "\
+circuit Top :
+  module Top :
+    input a : UInt<1>
+    output b : UInt<8>
+    when a :
+      layerblock Verif :
+        node n = intrinsic(circt_sizeof : UInt<8>, b)
+    connect b, UInt<8>(0)
+  layer Verif, bind :
+";
+        let circuit = parse(src);
+        assert_eq!(check_circuit(&circuit, LATEST), Ok(()));
+        let v32 = Version::new(3, 2, 0);
+        assert_eq!(check_circuit(&circuit, v32), Err(VersionError {
+            feature: Feature::Intrinsics,
+            version: v32,
+        }));
+        let v31 = Version::new(3, 1, 0);
+        assert_eq!(check_circuit(&circuit, v31).unwrap_err().feature,
+            Feature::Layers);
+    }
+}
@@ -21,25 +21,80 @@
 pub mod signal;
 
 use std::collections::*;
+use std::fmt;
 use num::{ BigUint, Zero };
 
 use firrtl::{ FirrtlFile, FirrtlParseError };
 use firrtl::ast::*;
+use firrtl::intrinsic::*;
 
 use crate::signal::*;
 
 
+/// A problem found while walking the design.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VisitError {
+    /// An intrinsic is unknown or was used incorrectly
+    Intrinsic(IntrinsicError),
+    /// A subfield or index doesn't agree with the type it's applied to
+    Type(String),
+}
+impl fmt::Display for VisitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Intrinsic(e) => write!(f, "{}", e),
+            Self::Type(msg) => write!(f, "Type error: {}", msg),
+        }
+    }
+}
+impl std::error::Error for VisitError {}
+impl From<IntrinsicError> for VisitError {
+    fn from(e: IntrinsicError) -> Self {
+        Self::Intrinsic(e)
+    }
+}
+
 /// For walking the FIRRTL AST and turning everything into simulator state.
 pub struct FirrtlVisitor {
     tbl: SignalTable,
+    /// Verification statements (`assert`, `assume` and `cover`) to be 
+    /// checked during simulation
+    checks: Vec<Statement>,
+    /// Problems found while walking the design
+    errors: Vec<VisitError>,
+    /// Implementations for any intrinsics used in the design
+    intrinsics: Box<dyn IntrinsicRegistry>,
 }
 impl FirrtlVisitor {
     pub fn new() -> Self {
         Self {
             tbl: SignalTable::new(),
+            checks: Vec::new(),
+            errors: Vec::new(),
+            intrinsics: Box::new(Intrinsics::new()),
         }
     }
 
+    /// Use `intrinsics` to resolve any intrinsics used in the design.
+    pub fn with_intrinsics(mut self, 
+        intrinsics: impl IntrinsicRegistry + 'static) -> Self 
+    {
+        self.intrinsics = Box::new(intrinsics);
+        self
+    }
+
+    /// Verification statements found while walking the design.
+    pub fn checks(&self) -> &[Statement] {
+        &self.checks
+    }
+
+    /// Problems found while walking the design. 
+    ///
+    /// NOTE: The rest of the statement containing a problem is skipped. 
+    pub fn errors(&self) -> &[VisitError] {
+        &self.errors
+    }
+
     pub fn run(&mut self, m: &Module) {
         let mut inputs = Vec::new();
         let mut outputs = Vec::new();
@@ -57,80 +112,145 @@ impl FirrtlVisitor {
     }
 
     /// Resolve the FIRRTL type of the provided FIRRTL 'reference'
-    fn resolve_ref_type(&self, refr: &Reference) -> FirrtlType {
+    fn resolve_ref_type(&self, refr: &Reference) 
+        -> Result<FirrtlType, VisitError> 
+    {
         let s = self.tbl.signal_from_ref(refr);
         match refr { 
             Reference::Static(sr) => match sr {
-                StaticReference::Static(_) => { s.ty.clone() },
+                StaticReference::Static(_) => { Ok(s.ty.clone()) },
                 StaticReference::Subfield(_, field_name) => {
-                    s.ty.bundle_field_type(field_name).unwrap().clone()
+                    Self::resolve_field_type(&s.ty, field_name)
                 },
-                StaticReference::Subindex(_, _) => { s.ty.clone() },
+                StaticReference::Subindex(_, _) => { Ok(s.ty.clone()) },
             },
-            Reference::DynamicIndex(_, _) => { s.ty.clone() },
+            Reference::DynamicIndex(_, _) => { Ok(s.ty.clone()) },
         }
     }
 
+    /// Resolve the type of the field `field_name` in the bundle type `ty`
+    fn resolve_field_type(ty: &FirrtlType, field_name: &str) 
+        -> Result<FirrtlType, VisitError> 
+    {
+        ty.bundle_field_type(field_name).cloned().ok_or_else(|| {
+            VisitError::Type(format!("no field '{}' in {:?}", field_name, ty))
+        })
+    }
+
     /// Resolve the FIRRTL type of the provided FIRRTL expression. 
-    fn resolve_expr_type(&self, expr: &Expr) -> FirrtlType {
-        match expr {
+    fn resolve_expr_type(&self, expr: &Expr) 
+        -> Result<FirrtlType, VisitError> 
+    {
+        let ty = match expr {
             Expr::None => FirrtlType::None,
             Expr::Const(ty, _) => ty.clone(),
             Expr::Ref(refr) => {
-                self.resolve_ref_type(refr)
+                self.resolve_ref_type(refr)?
             },
             Expr::PrimOp2Expr(_, e1, e2) => {
-                let t1 = self.resolve_expr_type(e1);
-                let t2 = self.resolve_expr_type(e2);
+                let t1 = self.resolve_expr_type(e1)?;
+                let t2 = self.resolve_expr_type(e2)?;
                 println!("t1 {:?}", t1);
                 println!("t2 {:?}", t2);
                 FirrtlType::None
             },
             Expr::PrimOp1Expr(_, e1) => {
-                self.resolve_expr_type(e1)
+                self.resolve_expr_type(e1)?
             },
             Expr::PrimOp1Expr1Int(_, e1, lit1) => {
-                self.resolve_expr_type(e1)
+                self.resolve_expr_type(e1)?
             },
             Expr::PrimOp1Expr2Int(_, e1, lit1, lit2) => {
-                self.resolve_expr_type(e1)
+                self.resolve_expr_type(e1)?
+            },
+            Expr::Intrinsic(intr) => {
+                self.resolve_intrinsic(intr)?.unwrap_or(FirrtlType::None)
+            },
+            Expr::Subfield(e1, field_name) => {
+                let ty = self.resolve_expr_type(e1)?;
+                Self::resolve_field_type(&ty, field_name)?
+            },
+            Expr::Subindex(e1, _) | Expr::Subaccess(e1, _) => {
+                match self.resolve_expr_type(e1)?.without_const() {
+                    FirrtlType::Vector(elem, _) => (**elem).clone(),
+                    ty => return Err(VisitError::Type(
+                        format!("can't index into {:?}", ty)
+                    )),
+                }
             },
             _ => unimplemented!("{:?}", expr),
-        }
+        };
+        Ok(ty)
     }
 
-    /// Walk a block of FIRRTL statements
+    /// Check a use of an intrinsic, returning its result type (if any).
+    fn resolve_intrinsic(&self, intr: &Intrinsic) 
+        -> Result<Option<FirrtlType>, VisitError> 
+    {
+        let args = intr.args.iter()
+            .map(|e| self.resolve_expr_type(e))
+            .collect::<Result<Vec<FirrtlType>, VisitError>>()?;
+        Ok(self.intrinsics.check(intr, &args)?)
+    }
+
+    /// Walk a block of FIRRTL statements, collecting any errors.
     fn walk_statements(&mut self, statements: &Vec<Statement>) {
         for s in statements { 
-            match s {
-                Statement::Wire(id, ty) => {
-                    self.tbl.alloc(id, ty);
-                },
-                Statement::Connect(refr, expr) => {
-                    let tgt = self.tbl.signal_from_ref_mut(refr);
-                    if tgt.ty.width().is_none() {
-                    }
-
-                    let expr_ty = self.resolve_expr_type(expr);
-                },
-                Statement::Node(id, expr) => {
-                    let ty = self.resolve_expr_type(expr);
-                    self.tbl.alloc(id, &ty);
-                },
-                Statement::Reg(id, ty, clkexpr, rstexpr) => {
-                    self.tbl.alloc(id, &ty);
-                },
-                Statement::When(cond_expr, when_stmt, else_stmt) => {
-                    let ty = self.resolve_expr_type(cond_expr);
-                    self.walk_statements(when_stmt);
-                    self.walk_statements(else_stmt);
-                },
-                Statement::Inst(id, module_id) => {
-                },
-                _ => unimplemented!("{:?}", s),
+            if let Err(e) = self.walk_statement(s) {
+                self.errors.push(e);
             }
         }
     }
+
+    fn walk_statement(&mut self, s: &Statement) -> Result<(), VisitError> {
+        match &s.kind {
+            StatementKind::Wire(id, ty) => {
+                self.tbl.alloc(id, ty);
+            },
+            StatementKind::Connect(refr, expr) => {
+                let tgt = self.tbl.signal_from_ref_mut(refr);
+                if tgt.ty.width().is_none() {
+                }
+
+                let expr_ty = self.resolve_expr_type(expr)?;
+            },
+            StatementKind::Node(id, expr) => {
+                // The node is declared even if its type is unknown, since
+                // later statements may still use it
+                let ty = self.resolve_expr_type(expr);
+                self.tbl.alloc(id, ty.as_ref().unwrap_or(&FirrtlType::None));
+                ty?;
+            },
+            StatementKind::Reg(id, ty, clkexpr) => {
+                self.tbl.alloc(id, &ty);
+            },
+            StatementKind::RegReset(decl) => {
+                self.tbl.alloc(&decl.id, &decl.ty);
+            },
+            StatementKind::When(cond_expr, when_stmt, else_stmt) => {
+                let ty = self.resolve_expr_type(cond_expr)?;
+                self.walk_statements(when_stmt);
+                self.walk_statements(else_stmt);
+            },
+            StatementKind::Inst(id, module_id) => {
+            },
+            StatementKind::InstChoice(_) => {
+            },
+            StatementKind::LayerBlock(_, blk) => {
+                self.walk_statements(blk);
+            },
+            StatementKind::Assert(_) |
+            StatementKind::Assume(_) |
+            StatementKind::Cover(_) => {
+                self.checks.push(s.clone());
+            },
+            StatementKind::Intrinsic(intr) => {
+                self.resolve_intrinsic(intr)?;
+            },
+            _ => unimplemented!("{:?}", s.kind),
+        }
+        Ok(())
+    }
 }
 
 
@@ -142,7 +262,8 @@ mod test {
 
     #[test]
     fn foo() -> Result<(), String> {
-        let f = FirrtlFile::from_file("../chisel-tests/firrtl/MyAlu.fir");
+        let f = FirrtlFile::from_file("../chisel-tests/firrtl/MyAlu.fir")
+            .map_err(|e| e.kind.message())?;
         //let f = FirrtlFile::from_file("/tmp/foo.fir");
         let c = f.parse().map_err(|e| e.kind.message())?;
         c.dump();
@@ -157,4 +278,72 @@ mod test {
         Ok(())
 
     }
+
+    #[test]
+    fn intrinsics() -> Result<(), String> {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use firrtl::intrinsic::*;
+
+        let src = "\
+circuit Top :
+  module Top :
+    input a : UInt<8>
+    node s = intrinsic(sizeof<BITS = 4> : UInt<4>, a)
+    intrinsic(trace, s)
+";
+        let c = FirrtlFile::from_str("Top.fir", src)
+            .and_then(|f| f.parse())
+            .map_err(|e| e.kind.message())?;
+
+        // Record the operand types seen by each intrinsic
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut intrinsics = Intrinsics::new();
+        let log = seen.clone();
+        intrinsics.register("sizeof", move |i: &Intrinsic, a: &[FirrtlType]| {
+            log.borrow_mut().push((i.name.clone(), a.to_vec()));
+            Ok(i.ty.clone())
+        });
+        let log = seen.clone();
+        intrinsics.register("trace", move |i: &Intrinsic, a: &[FirrtlType]| {
+            log.borrow_mut().push((i.name.clone(), a.to_vec()));
+            Ok(None)
+        });
+
+        let mut builder = FirrtlVisitor::new().with_intrinsics(intrinsics);
+        builder.run(c.top_module().unwrap());
+        let uint = |w| FirrtlType::Ground(FirrtlTypeGround::UInt(Some(w)));
+        assert_eq!(*seen.borrow(), [
+            ("sizeof".to_string(), vec![uint(8)]),
+            ("trace".to_string(), vec![uint(4)]),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<(), String> {
+        let src = "\
+circuit Top :
+  module Top :
+    input a : UInt<8>
+    input v : UInt<8>[2]
+    node x = intrinsic(nope : UInt<1>, a)
+    node y = a.b
+    node z = v[1]
+    node w = x
+";
+        let c = FirrtlFile::from_str("Top.fir", src)
+            .and_then(|f| f.parse())
+            .map_err(|e| e.kind.message())?;
+
+        // Problems are collected rather than stopping the walk
+        let mut builder = FirrtlVisitor::new();
+        builder.run(c.top_module().unwrap());
+        let errors = builder.errors();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0], 
+            VisitError::Intrinsic(IntrinsicError::Unknown("nope".into())));
+        assert!(matches!(errors[1], VisitError::Type(_)));
+        Ok(())
+    }
 }
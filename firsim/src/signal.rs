@@ -72,6 +72,8 @@ impl SignalValue {
                 }
                 Self::Vector(data, *size)
             },
+            // Const values are stored like any other value
+            FirrtlType::Const(ty) => Self::new(ty),
             FirrtlType::Ground(gt) => match gt {
                 FirrtlTypeGround::Clock => Self::Bool(false),
                 FirrtlTypeGround::Reset => Self::Bool(false),
@@ -83,7 +85,16 @@ impl SignalValue {
                     Self::UInt(BigUint::zero(), width)
                 },
             },
+            // Enumerations are stored as their packed tag and data
+            FirrtlType::Enum(_) => {
+                Self::UInt(BigUint::zero(), ty.width().unwrap_or(0))
+            },
             FirrtlType::Ref(r) => Self::Undefined,
+            // Properties have no hardware representation
+            FirrtlType::Property(_) => Self::Undefined,
+            // NOTE: Aliases should already be resolved by the caller
+            // (see [Circuit::resolve_type])
+            FirrtlType::Alias(_) => Self::Undefined,
             FirrtlType::None => Self::Undefined,
         }
     }